@@ -41,52 +41,302 @@ CREATE TYPE public.message_type AS ENUM (
     'system'
 );
 
+-- 📬 MESSAGE STATUS ENUM
+-- Delivery lifecycle, only ever moves forward
+CREATE TYPE public.message_status AS ENUM (
+    'sent',
+    'delivered',
+    'read'
+);
+
+-- 🖼️ MEDIA STATUS ENUM
+-- Progress of the background job that thumbnails image attachments
+CREATE TYPE public.media_status AS ENUM (
+    'pending',
+    'ready',
+    'failed'
+);
+
+-- 🧑‍🤝‍🧑 CONVERSATIONS TABLE
+-- One row per 1:1 or group chat. Direct conversations use
+-- create_conversation_id(user1, user2) as their ID so they are never duplicated,
+-- and are only ever created by create_direct_conversation.
+CREATE TABLE public.conversations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name VARCHAR, -- Display name (groups only)
+    is_group BOOLEAN NOT NULL DEFAULT false,
+    created_by UUID REFERENCES public.users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW() -- Bumped on every new message
+);
+
+-- 👥 CONVERSATION PARTICIPANTS TABLE
+CREATE TABLE public.conversation_participants (
+    conversation_id UUID NOT NULL REFERENCES public.conversations(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    
+    PRIMARY KEY (conversation_id, user_id)
+);
+
+-- Membership checks used by RLS policies. SECURITY DEFINER so the policies on
+-- conversation_participants can consult the table without recursing into themselves.
+CREATE OR REPLACE FUNCTION public.is_conversation_participant(p_conversation_id UUID)
+RETURNS BOOLEAN AS $$
+    SELECT EXISTS (
+        SELECT 1 FROM public.conversation_participants
+        WHERE conversation_id = p_conversation_id AND user_id = auth.uid()
+    );
+$$ LANGUAGE sql STABLE SECURITY DEFINER SET search_path = public;
+
+CREATE OR REPLACE FUNCTION public.is_conversation_creator(p_conversation_id UUID)
+RETURNS BOOLEAN AS $$
+    SELECT EXISTS (
+        SELECT 1 FROM public.conversations
+        WHERE id = p_conversation_id AND created_by = auth.uid()
+    );
+$$ LANGUAGE sql STABLE SECURITY DEFINER SET search_path = public;
+
+CREATE OR REPLACE FUNCTION public.is_group_conversation(p_conversation_id UUID)
+RETURNS BOOLEAN AS $$
+    SELECT EXISTS (
+        SELECT 1 FROM public.conversations
+        WHERE id = p_conversation_id AND is_group
+    );
+$$ LANGUAGE sql STABLE SECURITY DEFINER SET search_path = public;
+
+-- Enable Row Level Security
+ALTER TABLE public.conversations ENABLE ROW LEVEL SECURITY;
+ALTER TABLE public.conversation_participants ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policies for conversations
+CREATE POLICY "Participants can view their conversations" ON public.conversations
+    FOR SELECT USING (
+        auth.uid() = created_by OR public.is_conversation_participant(id)
+    );
+
+-- No INSERT policy: conversations are only created by create_direct_conversation
+-- and create_group_conversation. Direct conversations have a predictable ID, so
+-- letting clients insert them would let anyone claim another pair's
+-- conversation before they first talk.
+
+CREATE POLICY "Participants can update their conversations" ON public.conversations
+    FOR UPDATE USING (public.is_conversation_participant(id));
+
+-- RLS Policies for conversation participants
+CREATE POLICY "Participants can view fellow participants" ON public.conversation_participants
+    FOR SELECT USING (public.is_conversation_participant(conversation_id));
+
+CREATE POLICY "Creators and participants can add group participants" ON public.conversation_participants
+    FOR INSERT WITH CHECK (
+        public.is_group_conversation(conversation_id)
+        AND (
+            public.is_conversation_creator(conversation_id)
+            OR public.is_conversation_participant(conversation_id)
+        )
+    );
+
+CREATE POLICY "Users can leave conversations" ON public.conversation_participants
+    FOR DELETE USING (auth.uid() = user_id);
+
+CREATE INDEX idx_conversation_participants_user ON public.conversation_participants(user_id);
+
+-- 🔒 A conversation stays direct or group for life; otherwise a participant
+-- could turn a direct conversation into a group and invite others into it
+CREATE OR REPLACE FUNCTION keep_conversation_kind()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.is_group IS DISTINCT FROM OLD.is_group THEN
+        RAISE EXCEPTION 'A conversation cannot switch between direct and group';
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER keep_conversation_kind_before_update BEFORE UPDATE ON public.conversations
+    FOR EACH ROW EXECUTE FUNCTION keep_conversation_kind();
+
 -- 💬 MESSAGES TABLE (ENCRYPTED)
 -- Stores encrypted messages with all necessary metadata
 CREATE TABLE public.messages (
     id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    conversation_id UUID NOT NULL REFERENCES public.conversations(id) ON DELETE CASCADE,
     sender_id UUID NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
-    receiver_id UUID NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    receiver_id UUID REFERENCES public.users(id) ON DELETE CASCADE, -- NULL for group messages
+    reply_to_id UUID REFERENCES public.messages(id) ON DELETE SET NULL, -- Quoted / thread root message
     
     -- 🔐 ENCRYPTED CONTENT FIELDS
     encrypted_content TEXT NOT NULL, -- AES-GCM encrypted message content (base64)
     content_hash VARCHAR(64) NOT NULL, -- SHA-256 hash for integrity verification
-    encryption_version INTEGER NOT NULL DEFAULT 1, -- Version for future encryption upgrades
-    nonce VARCHAR(24) NOT NULL, -- AES nonce (base64)
-    session_key_id UUID NOT NULL, -- ID of the session key used
+    encryption_version INTEGER NOT NULL DEFAULT 1, -- Format: see encryption::ENCRYPTION_VERSION (3 binds ids as AAD, 0 is unencrypted)
+    nonce VARCHAR(24), -- This message's AES-GCM nonce (base64); version 1 rows share the session's; NULL for version 0
+    session_key_id UUID, -- ID of the session key used; NULL for version 0
+    session_key_generation INTEGER NOT NULL DEFAULT 1, -- That session's generation (conversation_sessions.generation)
+    signature TEXT, -- Sender's RSA-PSS-SHA256 signature over ids + plaintext hash (base64); NULL for unsigned legacy rows
+    signature_key_version INTEGER, -- Which of the sender's encryption_keys signed it
     
     -- 📝 MESSAGE METADATA
     message_type public.message_type NOT NULL DEFAULT 'text',
     is_read BOOLEAN NOT NULL DEFAULT false,
+    status public.message_status NOT NULL DEFAULT 'sent',
+    delivered_at TIMESTAMPTZ, -- First time a recipient device received it
+    read_at TIMESTAMPTZ, -- Set together with is_read; replayed as read receipts on sync
+    client_msg_id UUID, -- Sender-generated ID, echoed back in the WebSocket `ack`
+    edited_at TIMESTAMPTZ, -- Last content edit (prior revisions live in message_edits)
+    deleted_at TIMESTAMPTZ, -- Tombstone: deleted for everyone, content wiped
+    deleted_by UUID REFERENCES public.users(id) ON DELETE SET NULL,
     file_url VARCHAR, -- For file/image messages
     file_size BIGINT, -- File size in bytes
     mime_type VARCHAR, -- MIME type for files
     
     -- ⏰ TIMESTAMPS
     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
-    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    
+    -- 🔐 Only unencrypted (version 0) rows go without a session key and nonce
+    CONSTRAINT messages_session_key_check CHECK (
+        (encryption_version = 0) = (nonce IS NULL AND session_key_id IS NULL)
+    ),
+    
+    -- 🔁 Retried sends resolve to the original row (NULLs never conflict)
+    CONSTRAINT messages_sender_client_msg_id_key UNIQUE (sender_id, client_msg_id)
 );
 
 -- Enable Row Level Security
 ALTER TABLE public.messages ENABLE ROW LEVEL SECURITY;
 
 -- RLS Policies for messages
-CREATE POLICY "Users can view messages they sent or received" ON public.messages
-    FOR SELECT USING (
-        auth.uid() = sender_id OR auth.uid() = receiver_id
-    );
+CREATE POLICY "Participants can view conversation messages" ON public.messages
+    FOR SELECT USING (public.is_conversation_participant(conversation_id));
 
-CREATE POLICY "Users can insert messages they send" ON public.messages
-    FOR INSERT WITH CHECK (auth.uid() = sender_id);
+CREATE POLICY "Participants can insert messages they send" ON public.messages
+    FOR INSERT WITH CHECK (
+        auth.uid() = sender_id AND public.is_conversation_participant(conversation_id)
+    );
 
-CREATE POLICY "Users can update messages they received" ON public.messages
-    FOR UPDATE USING (auth.uid() = receiver_id);
+-- Only senders update messages directly. Recipients' delivery and read receipts
+-- go through mark_messages_delivered and mark_message_read, which touch the
+-- status columns and nothing else (per-reader reads live in message_reads).
+-- Which columns a sender may change is enforced by freeze_message_columns, and
+-- edits are limited to the window and recorded by record_message_edit.
+CREATE POLICY "Senders can edit their messages" ON public.messages
+    FOR UPDATE USING (auth.uid() = sender_id) WITH CHECK (auth.uid() = sender_id);
 
 -- Indexes for better performance
 CREATE INDEX idx_messages_participants ON public.messages(sender_id, receiver_id);
+CREATE INDEX idx_messages_conversation ON public.messages(conversation_id, created_at DESC);
 CREATE INDEX idx_messages_created_at ON public.messages(created_at DESC);
 CREATE INDEX idx_messages_unread ON public.messages(receiver_id, is_read) WHERE is_read = false;
+CREATE INDEX idx_messages_read_receipts ON public.messages(sender_id, read_at) WHERE read_at IS NOT NULL;
+CREATE INDEX idx_messages_delivery_receipts ON public.messages(sender_id, delivered_at) WHERE delivered_at IS NOT NULL;
 CREATE INDEX idx_messages_session_key ON public.messages(session_key_id);
+CREATE INDEX idx_messages_thread ON public.messages(reply_to_id, created_at) WHERE reply_to_id IS NOT NULL;
+CREATE INDEX idx_messages_edited_at ON public.messages(edited_at) WHERE edited_at IS NOT NULL;
+CREATE INDEX idx_messages_deleted_at ON public.messages(deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- 🙈 HIDDEN MESSAGES TABLE
+-- "Delete for me": the message stays for everyone else
+CREATE TABLE public.hidden_messages (
+    message_id UUID NOT NULL REFERENCES public.messages(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    conversation_id UUID NOT NULL REFERENCES public.conversations(id) ON DELETE CASCADE,
+    hidden_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (message_id, user_id)
+);
+
+ALTER TABLE public.hidden_messages ENABLE ROW LEVEL SECURITY;
+
+-- Each user only ever sees their own hidden list
+CREATE POLICY "Users can view messages they hid" ON public.hidden_messages
+    FOR SELECT USING (auth.uid() = user_id);
+
+CREATE POLICY "Users can hide messages in their conversations" ON public.hidden_messages
+    FOR INSERT WITH CHECK (
+        auth.uid() = user_id AND public.is_conversation_participant(conversation_id)
+    );
+
+CREATE INDEX idx_hidden_messages_user ON public.hidden_messages(user_id, hidden_at);
+
+-- 👁️ MESSAGE READS TABLE
+-- Who has read what: one row per (message, reader), so group messages get a
+-- read receipt from every member. Written by mark_message_read only.
+CREATE TABLE public.message_reads (
+    message_id UUID NOT NULL REFERENCES public.messages(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    conversation_id UUID NOT NULL REFERENCES public.conversations(id) ON DELETE CASCADE,
+    read_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (message_id, user_id)
+);
+
+ALTER TABLE public.message_reads ENABLE ROW LEVEL SECURITY;
+
+CREATE POLICY "Participants can view read receipts" ON public.message_reads
+    FOR SELECT USING (public.is_conversation_participant(conversation_id));
+
+CREATE INDEX idx_message_reads_read_at ON public.message_reads(read_at, message_id);
+
+-- 😀 MESSAGE REACTIONS TABLE
+-- One row per (message, user, emoji); a user can add several different emoji
+CREATE TABLE public.message_reactions (
+    message_id UUID NOT NULL REFERENCES public.messages(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    emoji TEXT NOT NULL CHECK (char_length(emoji) BETWEEN 1 AND 16),
+    conversation_id UUID NOT NULL REFERENCES public.conversations(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (message_id, user_id, emoji)
+);
+
+ALTER TABLE public.message_reactions ENABLE ROW LEVEL SECURITY;
+
+CREATE POLICY "Participants can view reactions" ON public.message_reactions
+    FOR SELECT USING (public.is_conversation_participant(conversation_id));
+
+-- Only on live messages, and conversation_id must match the message's
+CREATE POLICY "Participants can react to messages" ON public.message_reactions
+    FOR INSERT WITH CHECK (
+        auth.uid() = user_id
+        AND public.is_conversation_participant(conversation_id)
+        AND EXISTS (
+            SELECT 1 FROM public.messages m
+            WHERE m.id = message_id
+              AND m.conversation_id = message_reactions.conversation_id
+              AND m.deleted_at IS NULL
+        )
+    );
+
+CREATE POLICY "Users can remove their own reactions" ON public.message_reactions
+    FOR DELETE USING (auth.uid() = user_id);
+
+CREATE INDEX idx_message_reactions_message ON public.message_reactions(message_id, created_at);
+
+-- ✏️ MESSAGE EDITS TABLE
+-- Prior revisions of edited messages, written by the record_message_edit trigger
+CREATE TABLE public.message_edits (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    message_id UUID NOT NULL REFERENCES public.messages(id) ON DELETE CASCADE,
+    encrypted_content TEXT NOT NULL, -- Content before this edit
+    content_hash VARCHAR(64) NOT NULL, -- Hash of that content
+    nonce VARCHAR(24), -- NULL if that content was unencrypted
+    session_key_id UUID,
+    encryption_version INTEGER NOT NULL DEFAULT 1, -- Format of that content
+    signature TEXT, -- Sender's signature over that content, if it was signed
+    signature_key_version INTEGER,
+    edited_by UUID NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW() -- When it was replaced
+);
+
+ALTER TABLE public.message_edits ENABLE ROW LEVEL SECURITY;
+
+-- History is readable by anyone who can read the message; only the trigger writes it
+CREATE POLICY "Participants can view message edits" ON public.message_edits
+    FOR SELECT USING (
+        EXISTS (
+            SELECT 1 FROM public.messages m
+            WHERE m.id = message_id AND public.is_conversation_participant(m.conversation_id)
+        )
+    );
+
+CREATE INDEX idx_message_edits_message ON public.message_edits(message_id, created_at);
 
 -- 🔐 ENCRYPTION KEYS TABLE
 -- Stores encryption keys for users
@@ -95,12 +345,17 @@ CREATE TABLE public.encryption_keys (
     user_id UUID NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
     
     -- 🔑 KEY DATA
-    encrypted_private_key TEXT NOT NULL, -- User's encrypted private key
+    -- PKCS#8 private key sealed with AES-256-GCM under an Argon2id key derived from
+    -- the user's passphrase; the server never sees the passphrase or the plain key
+    encrypted_private_key TEXT NOT NULL,
+    kdf_params JSONB NOT NULL, -- Argon2id cost and salt for the above (encryption::KdfParams)
     public_key TEXT NOT NULL, -- User's public key (PEM format)
     key_version INTEGER NOT NULL DEFAULT 1,
     
     -- 🔧 KEY METADATA
-    algorithm VARCHAR(50) NOT NULL DEFAULT 'RSA-2048',
+    -- How session keys are wrapped for this key (encryption::KeyWrapAlgorithm):
+    -- 'RSA-OAEP-SHA256', or legacy 'RSA-2048' (PKCS#1 v1.5, unwrap-only during migration)
+    algorithm VARCHAR(50) NOT NULL DEFAULT 'RSA-OAEP-SHA256',
     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
     expires_at TIMESTAMPTZ, -- Optional key expiration
     
@@ -115,35 +370,208 @@ CREATE POLICY "Users can only access their own encryption keys" ON public.encryp
     FOR ALL USING (auth.uid() = user_id);
 
 -- 🔑 CONVERSATION SESSIONS TABLE
--- Stores session keys for conversations
+-- One row per generation of a conversation's session key. Only the latest may be
+-- active; older ones stay so their messages can still be decrypted.
 CREATE TABLE public.conversation_sessions (
     id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-    user1_id UUID NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
-    user2_id UUID NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    conversation_id UUID NOT NULL REFERENCES public.conversations(id) ON DELETE CASCADE,
+    generation INTEGER NOT NULL DEFAULT 1, -- 1 for the first key, +1 per rotation
     
     -- 🔐 SESSION ENCRYPTION
-    encrypted_session_key TEXT NOT NULL, -- Encrypted session key
-    session_key_hash VARCHAR(64) NOT NULL, -- Hash of session key for verification
+    -- The key itself is only stored wrapped per participant, in session_key_shares
+    session_key_hash VARCHAR(64) NOT NULL, -- SHA-256 of the raw session key, checked after unwrapping
     
     -- 📊 SESSION METADATA
-    is_active BOOLEAN NOT NULL DEFAULT true,
+    is_active BOOLEAN NOT NULL DEFAULT true, -- False once rotated out
+    message_count INTEGER NOT NULL DEFAULT 0, -- Messages sent under this key (kept by trigger)
     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
     last_used TIMESTAMPTZ NOT NULL DEFAULT NOW(),
     
-    -- Ensure unique conversations
-    UNIQUE(user1_id, user2_id),
-    UNIQUE(user2_id, user1_id)
+    UNIQUE(conversation_id, generation)
 );
 
 -- Enable Row Level Security
 ALTER TABLE public.conversation_sessions ENABLE ROW LEVEL SECURITY;
 
 -- RLS Policies for conversation sessions
-CREATE POLICY "Users can access their conversation sessions" ON public.conversation_sessions
-    FOR ALL USING (
-        auth.uid() = user1_id OR auth.uid() = user2_id
+CREATE POLICY "Participants can access their conversation sessions" ON public.conversation_sessions
+    FOR ALL USING (public.is_conversation_participant(conversation_id));
+
+-- 🔄 Retire the active session key whenever membership changes: a leaver must not
+-- read what comes next, and a joiner has no share of the current key
+CREATE OR REPLACE FUNCTION retire_session_on_membership_change()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE public.conversation_sessions
+    SET is_active = false
+    WHERE conversation_id = COALESCE(NEW.conversation_id, OLD.conversation_id) AND is_active;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+CREATE TRIGGER retire_session_after_membership_change AFTER INSERT OR DELETE ON public.conversation_participants
+    FOR EACH ROW EXECUTE FUNCTION retire_session_on_membership_change();
+
+-- 🔑 SESSION KEY SHARES TABLE
+-- A conversation session key wrapped with one participant's public key
+CREATE TABLE public.session_key_shares (
+    session_id UUID NOT NULL REFERENCES public.conversation_sessions(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    
+    -- 🔐 WRAPPED KEY
+    encrypted_session_key TEXT NOT NULL, -- Session key wrapped for this user
+    key_version INTEGER NOT NULL, -- Which of the user's encryption_keys wrapped it
+    
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    
+    PRIMARY KEY (session_id, user_id)
+);
+
+-- Enable Row Level Security
+ALTER TABLE public.session_key_shares ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policies for session key shares
+CREATE POLICY "Users can read their own session key shares" ON public.session_key_shares
+    FOR SELECT USING (auth.uid() = user_id);
+
+-- 🔐 A participant shares the key with the conversation's participants only
+-- (conversation_sessions RLS already limits cs to the caller's conversations)
+CREATE POLICY "Session participants can share session keys" ON public.session_key_shares
+    FOR INSERT WITH CHECK (
+        EXISTS (
+            SELECT 1 FROM public.conversation_sessions cs
+            JOIN public.conversation_participants cp ON cp.conversation_id = cs.conversation_id
+            WHERE cs.id = session_id AND cp.user_id = session_key_shares.user_id
+        )
     );
 
+-- 🤝 PREKEY IDENTITIES TABLE
+-- Each user's published X3DH identity and current signed prekey (encryption::x3dh)
+-- Public keys only; the matching secrets never leave the user's devices
+CREATE TABLE public.prekey_identities (
+    user_id UUID PRIMARY KEY REFERENCES public.users(id) ON DELETE CASCADE,
+    identity_key TEXT NOT NULL, -- X25519 identity key (base64)
+    signing_key TEXT NOT NULL, -- Ed25519 key that signs prekeys (base64)
+    signed_prekey_id INTEGER NOT NULL,
+    signed_prekey TEXT NOT NULL, -- X25519 signed prekey (base64)
+    signed_prekey_signature TEXT NOT NULL, -- Ed25519 signature (base64), checked by the API and by clients
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Enable Row Level Security
+ALTER TABLE public.prekey_identities ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policies for prekey identities
+CREATE POLICY "Authenticated users can read prekey identities" ON public.prekey_identities
+    FOR SELECT USING (auth.uid() IS NOT NULL);
+
+CREATE POLICY "Users can publish their own prekey identity" ON public.prekey_identities
+    FOR INSERT WITH CHECK (auth.uid() = user_id);
+
+CREATE POLICY "Users can update their own prekey identity" ON public.prekey_identities
+    FOR UPDATE USING (auth.uid() = user_id);
+
+-- 🎟️ ONE-TIME PREKEYS TABLE
+-- Handed out at most once each, by claim_prekey_bundle
+CREATE TABLE public.one_time_prekeys (
+    user_id UUID NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    key_id INTEGER NOT NULL,
+    public_key TEXT NOT NULL, -- X25519 (base64)
+    claimed_by UUID REFERENCES public.users(id) ON DELETE SET NULL,
+    claimed_at TIMESTAMPTZ, -- NULL while still available
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    
+    PRIMARY KEY (user_id, key_id)
+);
+
+-- Enable Row Level Security
+ALTER TABLE public.one_time_prekeys ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policies for one-time prekeys
+-- Other users never read these directly; they claim them through claim_prekey_bundle
+CREATE POLICY "Users can read their own one-time prekeys" ON public.one_time_prekeys
+    FOR SELECT USING (auth.uid() = user_id);
+
+CREATE POLICY "Users can upload their own one-time prekeys" ON public.one_time_prekeys
+    FOR INSERT WITH CHECK (auth.uid() = user_id AND claimed_at IS NULL);
+
+CREATE POLICY "Users can delete their own one-time prekeys" ON public.one_time_prekeys
+    FOR DELETE USING (auth.uid() = user_id);
+
+-- 🔄 One-time prekeys belong to an identity: a new identity key makes them all
+-- useless, since their secrets went with the old device. Claimed ones go too,
+-- so the new identity is free to reuse their key IDs.
+CREATE OR REPLACE FUNCTION drop_prekeys_on_identity_change()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.identity_key IS DISTINCT FROM OLD.identity_key THEN
+        DELETE FROM public.one_time_prekeys
+        WHERE user_id = NEW.user_id;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+CREATE TRIGGER drop_prekeys_after_identity_change AFTER UPDATE ON public.prekey_identities
+    FOR EACH ROW EXECUTE FUNCTION drop_prekeys_on_identity_change();
+
+-- 🤝 Claim a user's prekey bundle: their identity, signed prekey and (while any
+-- are left) one one-time prekey, which is marked claimed so nobody gets it again.
+-- SKIP LOCKED lets concurrent claims each take a different key.
+-- Returns NULL if the user hasn't published prekeys.
+CREATE OR REPLACE FUNCTION claim_prekey_bundle(p_user_id UUID)
+RETURNS JSON AS $$
+DECLARE
+    v_identity public.prekey_identities%ROWTYPE;
+    v_key_id INTEGER;
+    v_public_key TEXT;
+    v_remaining BIGINT;
+BEGIN
+    -- Each claim burns a one-time prekey, so only signed-in users may make one
+    IF auth.uid() IS NULL THEN
+        RAISE EXCEPTION 'Not authenticated';
+    END IF;
+    
+    SELECT * INTO v_identity FROM public.prekey_identities WHERE user_id = p_user_id;
+    IF NOT FOUND THEN
+        RETURN NULL;
+    END IF;
+    
+    SELECT key_id, public_key INTO v_key_id, v_public_key
+    FROM public.one_time_prekeys
+    WHERE user_id = p_user_id AND claimed_at IS NULL
+    ORDER BY key_id
+    LIMIT 1
+    FOR UPDATE SKIP LOCKED;
+    
+    IF v_key_id IS NOT NULL THEN
+        UPDATE public.one_time_prekeys
+        SET claimed_by = auth.uid(), claimed_at = NOW()
+        WHERE user_id = p_user_id AND key_id = v_key_id;
+    END IF;
+    
+    SELECT COUNT(*) INTO v_remaining
+    FROM public.one_time_prekeys
+    WHERE user_id = p_user_id AND claimed_at IS NULL;
+    
+    RETURN json_build_object(
+        'bundle', json_build_object(
+            'user_id', v_identity.user_id,
+            'identity_key', v_identity.identity_key,
+            'signing_key', v_identity.signing_key,
+            'signed_prekey', json_build_object(
+                'key_id', v_identity.signed_prekey_id,
+                'public_key', v_identity.signed_prekey,
+                'signature', v_identity.signed_prekey_signature
+            ),
+            'one_time_prekey', CASE WHEN v_key_id IS NULL THEN NULL
+                ELSE json_build_object('key_id', v_key_id, 'public_key', v_public_key) END
+        ),
+        'remaining', v_remaining
+    );
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
 -- 📎 MESSAGE ATTACHMENTS TABLE
 -- Stores file attachments for messages
 CREATE TABLE public.message_attachments (
@@ -160,6 +588,14 @@ CREATE TABLE public.message_attachments (
     encrypted_file_key TEXT, -- Encrypted file encryption key
     file_hash VARCHAR(64) NOT NULL, -- File integrity hash
     
+    -- 🖼️ MEDIA (images only, written by the server's thumbnail job)
+    media_status public.media_status, -- NULL for files that aren't processed
+    width INTEGER,
+    height INTEGER,
+    blurhash VARCHAR(64),
+    thumbnails JSONB NOT NULL DEFAULT '[]', -- [{"name", "width", "height", "file_size"}]
+    processed_at TIMESTAMPTZ,
+    
     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
 );
 
@@ -167,15 +603,30 @@ CREATE TABLE public.message_attachments (
 ALTER TABLE public.message_attachments ENABLE ROW LEVEL SECURITY;
 
 -- RLS Policies for attachments
+-- Participants can see them; only the sender can add them, and only to live messages.
+-- There is no UPDATE/DELETE policy: rows are removed by wipe_deleted_message,
+-- and the thumbnail job updates them with the service role.
 CREATE POLICY "Users can access attachments of their messages" ON public.message_attachments
-    FOR ALL USING (
+    FOR SELECT USING (
         EXISTS (
             SELECT 1 FROM public.messages m 
             WHERE m.id = message_attachments.message_id 
-            AND (m.sender_id = auth.uid() OR m.receiver_id = auth.uid())
+            AND public.is_conversation_participant(m.conversation_id)
         )
     );
 
+CREATE POLICY "Senders can attach files to their messages" ON public.message_attachments
+    FOR INSERT WITH CHECK (
+        EXISTS (
+            SELECT 1 FROM public.messages m
+            WHERE m.id = message_attachments.message_id
+            AND m.sender_id = auth.uid()
+            AND m.deleted_at IS NULL
+        )
+        -- Media results are only ever written by the server's job (service role)
+        AND (media_status IS NULL OR media_status = 'pending')
+    );
+
 -- 🔧 DATABASE FUNCTIONS
 
 -- Function to update updated_at timestamp
@@ -194,6 +645,154 @@ CREATE TRIGGER update_users_updated_at BEFORE UPDATE ON public.users
 CREATE TRIGGER update_messages_updated_at BEFORE UPDATE ON public.messages
     FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
 
+-- Keep conversation lists sorted by latest activity
+CREATE OR REPLACE FUNCTION touch_conversation_on_message()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE public.conversations SET updated_at = NOW() WHERE id = NEW.conversation_id;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+CREATE TRIGGER touch_conversation_after_message AFTER INSERT ON public.messages
+    FOR EACH ROW EXECUTE FUNCTION touch_conversation_on_message();
+
+-- 🔑 Count messages per session key, so clients know when to rotate it
+CREATE OR REPLACE FUNCTION count_session_key_message()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE public.conversation_sessions
+    SET message_count = message_count + 1, last_used = NOW()
+    WHERE id = NEW.session_key_id;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+CREATE TRIGGER count_session_key_message_after_insert AFTER INSERT ON public.messages
+    FOR EACH ROW EXECUTE FUNCTION count_session_key_message();
+
+-- 🔒 Senders update their messages directly through PostgREST, so everything but
+-- the content, its encryption metadata, deletion and a first file is frozen
+-- for them. Runs first (triggers fire in name order), on what the client sent.
+-- Not SECURITY DEFINER: it needs the caller's role, and the database functions
+-- and the service role may change the other columns.
+CREATE OR REPLACE FUNCTION freeze_message_columns()
+RETURNS TRIGGER AS $$
+DECLARE
+    v_frozen public.messages%ROWTYPE := NEW;
+BEGIN
+    IF current_user <> 'authenticated' THEN
+        RETURN NEW;
+    END IF;
+    
+    v_frozen.encrypted_content = OLD.encrypted_content;
+    v_frozen.content_hash = OLD.content_hash;
+    v_frozen.nonce = OLD.nonce;
+    v_frozen.session_key_id = OLD.session_key_id;
+    v_frozen.session_key_generation = OLD.session_key_generation;
+    v_frozen.encryption_version = OLD.encryption_version;
+    v_frozen.signature = OLD.signature;
+    v_frozen.signature_key_version = OLD.signature_key_version;
+    v_frozen.updated_at = OLD.updated_at;
+    v_frozen.deleted_at = OLD.deleted_at;
+    -- wipe_deleted_message sets deleted_by itself when a message is deleted
+    IF OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN
+        v_frozen.deleted_by = OLD.deleted_by;
+    END IF;
+    -- A message's file is set once, from its first attachment
+    IF OLD.file_url IS NULL THEN
+        v_frozen.file_url = OLD.file_url;
+        v_frozen.file_size = OLD.file_size;
+        v_frozen.mime_type = OLD.mime_type;
+    END IF;
+    
+    IF v_frozen IS DISTINCT FROM OLD THEN
+        RAISE EXCEPTION 'Only the content of a message can be changed';
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER freeze_message_columns_before_update BEFORE UPDATE ON public.messages
+    FOR EACH ROW EXECUTE FUNCTION freeze_message_columns();
+
+-- ✏️ Keep the previous revision whenever message content changes
+-- Edits must fall within the edit window, which the database reads from the
+-- app.message_edit_window_seconds setting; keep it equal to the server's
+-- MESSAGE_EDIT_WINDOW_SECONDS (unset means no limit):
+--   ALTER DATABASE postgres SET app.message_edit_window_seconds = '900';
+-- Re-encrypting the same plaintext under a newer encryption_version (same
+-- content_hash) is not an edit: it is allowed at any time and leaves no revision,
+-- and the message's signature still covers the plaintext hash. Any other content
+-- change is an edit, whether or not content_hash changed.
+CREATE OR REPLACE FUNCTION record_message_edit()
+RETURNS TRIGGER AS $$
+DECLARE
+    v_window_seconds INTEGER;
+BEGIN
+    IF NEW.encrypted_content IS DISTINCT FROM OLD.encrypted_content THEN
+        IF auth.uid() IS DISTINCT FROM OLD.sender_id THEN
+            RAISE EXCEPTION 'Only the sender can edit a message';
+        END IF;
+        
+        IF NEW.content_hash = OLD.content_hash AND NEW.encryption_version > OLD.encryption_version THEN
+            RETURN NEW;
+        END IF;
+        
+        v_window_seconds = NULLIF(current_setting('app.message_edit_window_seconds', true), '')::INTEGER;
+        IF v_window_seconds IS NOT NULL AND NOW() > OLD.created_at + make_interval(secs => v_window_seconds) THEN
+            RAISE EXCEPTION 'Messages can only be edited within % seconds', v_window_seconds;
+        END IF;
+        
+        INSERT INTO public.message_edits (message_id, encrypted_content, content_hash, nonce, session_key_id, encryption_version, signature, signature_key_version, edited_by)
+        VALUES (OLD.id, OLD.encrypted_content, OLD.content_hash, OLD.nonce, OLD.session_key_id, OLD.encryption_version, OLD.signature, OLD.signature_key_version, OLD.sender_id);
+        NEW.edited_at = NOW();
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+CREATE TRIGGER record_message_edit_before_update BEFORE UPDATE ON public.messages
+    FOR EACH ROW EXECUTE FUNCTION record_message_edit();
+
+-- 🪦 Turn a message into a tombstone when it is deleted for everyone
+-- Runs after record_message_edit (triggers fire in name order), so the wipe
+-- itself is not recorded as an edit. Tombstones can't be revived or edited.
+CREATE OR REPLACE FUNCTION wipe_deleted_message()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF OLD.deleted_at IS NOT NULL THEN
+        IF NEW.deleted_at IS DISTINCT FROM OLD.deleted_at
+            OR NEW.encrypted_content IS DISTINCT FROM OLD.encrypted_content THEN
+            RAISE EXCEPTION 'Deleted messages cannot be changed';
+        END IF;
+        RETURN NEW;
+    END IF;
+    
+    IF NEW.deleted_at IS NOT NULL THEN
+        IF auth.uid() IS DISTINCT FROM OLD.sender_id THEN
+            RAISE EXCEPTION 'Only the sender can delete a message for everyone';
+        END IF;
+        
+        NEW.deleted_by = OLD.sender_id;
+        NEW.encrypted_content = '';
+        NEW.content_hash = '';
+        NEW.signature = NULL;
+        NEW.signature_key_version = NULL;
+        NEW.file_url = NULL;
+        NEW.file_size = NULL;
+        NEW.mime_type = NULL;
+        DELETE FROM public.message_edits WHERE message_id = OLD.id;
+        DELETE FROM public.message_attachments WHERE message_id = OLD.id;
+        DELETE FROM public.message_reactions WHERE message_id = OLD.id;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+CREATE TRIGGER wipe_deleted_message_before_update BEFORE UPDATE ON public.messages
+    FOR EACH ROW EXECUTE FUNCTION wipe_deleted_message();
+
 -- Function to get conversation between two users
 CREATE OR REPLACE FUNCTION get_conversation_messages(
     p_user1_id UUID,
@@ -245,12 +844,13 @@ END;
 $$ LANGUAGE plpgsql SECURITY DEFINER;
 
 -- Function to create conversation ID (deterministic)
+-- Must match `encryption::create_conversation_id`: SHA-256 over both user IDs'
+-- bytes, smaller first, truncated to 16 bytes with the UUID v4 bits set
 CREATE OR REPLACE FUNCTION create_conversation_id(user1_id UUID, user2_id UUID)
 RETURNS UUID AS $$
 DECLARE
     smaller_id UUID;
     larger_id UUID;
-    hash_input TEXT;
     hash_result BYTEA;
 BEGIN
     -- Sort the UUIDs to ensure consistent conversation ID
@@ -262,17 +862,145 @@ BEGIN
         larger_id := user1_id;
     END IF;
     
-    -- Create hash input
-    hash_input := smaller_id::text || larger_id::text;
+    -- Generate hash, keeping the first 16 bytes
+    hash_result := substring(digest(uuid_send(smaller_id) || uuid_send(larger_id), 'sha256') FROM 1 FOR 16);
     
-    -- Generate hash
-    hash_result := digest(hash_input, 'sha256');
+    -- Set version (4) and variant bits
+    hash_result := set_byte(hash_result, 6, (get_byte(hash_result, 6) & 15) | 64);
+    hash_result := set_byte(hash_result, 8, (get_byte(hash_result, 8) & 63) | 128);
     
-    -- Use first 16 bytes as UUID
     RETURN encode(hash_result, 'hex')::uuid;
 END;
 $$ LANGUAGE plpgsql IMMUTABLE;
 
+-- 🧑‍🤝‍🧑 Get or create the caller's direct conversation with another user
+-- The ID is derived here rather than taken from the client, and the conversation
+-- only ever holds the two of them.
+CREATE OR REPLACE FUNCTION create_direct_conversation(p_other_user_id UUID)
+RETURNS public.conversations AS $$
+DECLARE
+    v_conversation public.conversations%ROWTYPE;
+BEGIN
+    IF auth.uid() IS NULL OR p_other_user_id = auth.uid() THEN
+        RAISE EXCEPTION 'A direct conversation needs two different users';
+    END IF;
+    
+    INSERT INTO public.conversations (id, is_group, created_by)
+    VALUES (create_conversation_id(auth.uid(), p_other_user_id), false, auth.uid())
+    ON CONFLICT (id) DO NOTHING;
+    
+    SELECT * INTO v_conversation
+    FROM public.conversations
+    WHERE id = create_conversation_id(auth.uid(), p_other_user_id);
+    
+    -- Either of them may have left; talking again brings both back
+    INSERT INTO public.conversation_participants (conversation_id, user_id)
+    VALUES (v_conversation.id, auth.uid()), (v_conversation.id, p_other_user_id)
+    ON CONFLICT DO NOTHING;
+    
+    RETURN v_conversation;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+-- 👥 Create a group with its participants in one transaction, so a failed
+-- insert never leaves a conversation without members behind
+CREATE OR REPLACE FUNCTION create_group_conversation(p_name VARCHAR, p_participant_ids UUID[])
+RETURNS public.conversations AS $$
+DECLARE
+    v_conversation public.conversations%ROWTYPE;
+BEGIN
+    IF auth.uid() IS NULL THEN
+        RAISE EXCEPTION 'Not authenticated';
+    END IF;
+    
+    INSERT INTO public.conversations (name, is_group, created_by)
+    VALUES (p_name, true, auth.uid())
+    RETURNING * INTO v_conversation;
+    
+    -- The creator is always a participant
+    INSERT INTO public.conversation_participants (conversation_id, user_id)
+    SELECT DISTINCT v_conversation.id, participant_id
+    FROM unnest(array_prepend(auth.uid(), p_participant_ids)) AS participant_id;
+    
+    RETURN v_conversation;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+-- 📬 Mark messages delivered to the caller
+-- Only moves `sent` messages forward, and only touches the status columns
+CREATE OR REPLACE FUNCTION mark_messages_delivered(p_message_ids UUID[])
+RETURNS SETOF public.messages AS $$
+BEGIN
+    RETURN QUERY
+    UPDATE public.messages
+    SET status = 'delivered', delivered_at = NOW()
+    WHERE id = ANY(p_message_ids)
+      AND status = 'sent'
+      AND sender_id <> auth.uid()
+      AND public.is_conversation_participant(conversation_id)
+    RETURNING *;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+-- 👁️ Record that the caller read someone else's message in one of their
+-- conversations. 1:1 messages also carry the read state themselves.
+-- Returns the message, or nothing if the caller had already read it.
+CREATE OR REPLACE FUNCTION mark_message_read(p_message_id UUID)
+RETURNS SETOF public.messages AS $$
+BEGIN
+    INSERT INTO public.message_reads (message_id, user_id, conversation_id)
+    SELECT m.id, auth.uid(), m.conversation_id
+    FROM public.messages m
+    WHERE m.id = p_message_id
+      AND m.sender_id <> auth.uid()
+      AND public.is_conversation_participant(m.conversation_id)
+    ON CONFLICT DO NOTHING;
+    
+    IF NOT FOUND THEN
+        RETURN;
+    END IF;
+    
+    UPDATE public.messages
+    SET is_read = true, status = 'read', read_at = NOW()
+    WHERE id = p_message_id AND receiver_id = auth.uid();
+    
+    RETURN QUERY SELECT * FROM public.messages WHERE id = p_message_id;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+-- 🔑 Add the caller's next key pair and expire the one it replaces, in one
+-- transaction. Runs as the caller, so the owner-only policy still applies; a
+-- concurrent rotation to the same version fails on UNIQUE(user_id, key_version).
+CREATE OR REPLACE FUNCTION rotate_encryption_key(
+    p_encrypted_private_key TEXT,
+    p_kdf_params JSONB,
+    p_public_key TEXT,
+    p_key_version INTEGER,
+    p_algorithm VARCHAR,
+    p_replaces INTEGER DEFAULT NULL
+)
+RETURNS public.encryption_keys AS $$
+DECLARE
+    v_key public.encryption_keys%ROWTYPE;
+BEGIN
+    IF auth.uid() IS NULL THEN
+        RAISE EXCEPTION 'Not authenticated';
+    END IF;
+
+    INSERT INTO public.encryption_keys (user_id, encrypted_private_key, kdf_params, public_key, key_version, algorithm)
+    VALUES (auth.uid(), p_encrypted_private_key, p_kdf_params, p_public_key, p_key_version, p_algorithm)
+    RETURNING * INTO v_key;
+
+    IF p_replaces IS NOT NULL THEN
+        UPDATE public.encryption_keys
+        SET expires_at = NOW()
+        WHERE user_id = auth.uid() AND key_version = p_replaces;
+    END IF;
+
+    RETURN v_key;
+END;
+$$ LANGUAGE plpgsql;
+
 -- Function to get unread message count
 CREATE OR REPLACE FUNCTION get_unread_count(p_user_id UUID)
 RETURNS BIGINT AS $$
@@ -295,9 +1023,9 @@ INSERT INTO public.users (id, email, username, is_online) VALUES
     ('550e8400-e29b-41d4-a716-446655440001', 'bob@example.com', 'Bob', false);
 
 -- Insert sample encryption keys (replace with actual encrypted keys)
-INSERT INTO public.encryption_keys (user_id, encrypted_private_key, public_key, algorithm) VALUES
-    ('550e8400-e29b-41d4-a716-446655440000', 'encrypted_private_key_1', 'public_key_1', 'RSA-2048'),
-    ('550e8400-e29b-41d4-a716-446655440001', 'encrypted_private_key_2', 'public_key_2', 'RSA-2048');
+INSERT INTO public.encryption_keys (user_id, encrypted_private_key, kdf_params, public_key, algorithm) VALUES
+    ('550e8400-e29b-41d4-a716-446655440000', 'encrypted_private_key_1', '{}', 'public_key_1', 'RSA-2048'),
+    ('550e8400-e29b-41d4-a716-446655440001', 'encrypted_private_key_2', '{}', 'public_key_2', 'RSA-2048');
 */
 
 -- 📊 VIEWS FOR EASIER QUERYING
@@ -329,6 +1057,14 @@ ORDER BY last_message_at DESC;
 -- Grant access to the view
 GRANT SELECT ON user_conversations TO authenticated;
 
+-- View of everyone's public keys, for wrapping session keys to other users
+-- encryption_keys itself stays owner-only, so private key material never leaves it
+CREATE VIEW user_public_keys AS
+SELECT user_id, key_version, public_key, algorithm, created_at, expires_at
+FROM public.encryption_keys;
+
+GRANT SELECT ON user_public_keys TO authenticated;
+
 -- 🎯 FINAL SETUP
 
 -- Grant necessary permissions
@@ -338,16 +1074,16 @@ GRANT ALL ON ALL SEQUENCES IN SCHEMA public TO authenticated;
 
 -- Create indexes for better performance
 CREATE INDEX idx_encryption_keys_user_version ON public.encryption_keys(user_id, key_version);
-CREATE INDEX idx_conversation_sessions_users ON public.conversation_sessions(user1_id, user2_id);
-CREATE INDEX idx_conversation_sessions_active ON public.conversation_sessions(is_active) WHERE is_active = true;
+CREATE UNIQUE INDEX idx_conversation_sessions_active ON public.conversation_sessions(conversation_id) WHERE is_active = true;
 CREATE INDEX idx_attachments_message ON public.message_attachments(message_id);
+CREATE INDEX idx_one_time_prekeys_unclaimed ON public.one_time_prekeys(user_id, key_id) WHERE claimed_at IS NULL;
 
 -- Log successful schema creation
 DO $$
 BEGIN
     RAISE NOTICE '✅ OChat database schema created successfully!';
-    RAISE NOTICE '📊 Tables created: users, messages, encryption_keys, conversation_sessions, message_attachments';
-    RAISE NOTICE '🔐 Encryption support: AES-GCM for messages, RSA for key exchange';
+    RAISE NOTICE '📊 Tables created: users, conversations, conversation_participants, messages, message_edits, hidden_messages, message_reactions, encryption_keys, conversation_sessions, session_key_shares, prekey_identities, one_time_prekeys, message_attachments';
+    RAISE NOTICE '🔐 Encryption support: AES-GCM for messages, RSA-OAEP for key exchange, X3DH + Double Ratchet for 1:1 sessions';
     RAISE NOTICE '🛡️ Row Level Security (RLS) enabled on all tables';
     RAISE NOTICE '📈 Performance indexes created for optimal query performance';
 END $$; 
\ No newline at end of file
@@ -0,0 +1,576 @@
+/*
+📎 ATTACHMENTS MODULE
+=====================
+
+Upload and download of files attached to messages.
+
+FLOW:
+1. The sender creates the message as usual (WebSocket or REST)
+2. They upload files to it: POST /api/v1/messages/{messageId}/attachments (multipart, field `file`)
+3. The bytes are streamed to the blob store while we hash and size-check them
+4. Participants list them on the message and download them from /api/v1/attachments/{id}
+
+The client never chooses where a file lives or what its URL is: the blob key
+is the server-generated attachment ID and the message's `file_url` points at
+the authorized download endpoint.
+
+ENCRYPTED ATTACHMENTS:
+Clients encrypt files themselves (see `encryption::FileEncryptor`) and send the
+wrapped file key as an `encrypted_file_key` field before `file`. We store and
+hash the ciphertext without being able to read it; `mime_type` is then only a
+hint for the recipient, and downloads are served as application/octet-stream.
+
+IMAGES:
+Plain JPEG, PNG, WebP and GIF uploads start out `pending` and a background job
+(see `spawn_media_job`) takes over once the upload has been answered:
+1. EXIF/GPS and other metadata are stripped from the stored original
+2. JPEG thumbnails are stored next to it (`media::THUMBNAIL_SIZES`)
+3. Dimensions and a blurhash placeholder are recorded on the row
+4. Participants get an `attachment_processed` event
+Pending images can't be downloaded, so the original metadata never leaves the
+server. Files that turn out not to decode are marked `failed` and served as uploaded.
+*/
+
+use actix_multipart::{Field, Multipart};
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::auth::{AccessToken, Claims};
+use crate::blob_store::{BlobStore, BlobWriter};
+use crate::config::Config;
+use crate::database::{MediaStatus, MessageAttachment, Thumbnail};
+use crate::encryption::WRAPPED_FILE_KEY_LEN;
+use crate::errors::{AppError, AppResult};
+use crate::media;
+use crate::supabase_api::SupabaseClient;
+use crate::websocket::{OutgoingMessage, SessionManager};
+
+const MAX_FILE_NAME_LENGTH: usize = 255;
+
+// Image types the media job can decode and strip
+const PROCESSED_IMAGE_TYPES: &[&str] = &["image/jpeg", "image/png", "image/webp", "image/gif"];
+
+// 📄 ATTACHMENT RESPONSE
+// The blob key stays server-side; clients get the download URL instead
+#[derive(Debug, Serialize)]
+pub struct AttachmentResponse {
+    pub id: Uuid,
+    pub message_id: Uuid,
+    pub file_name: String,
+    pub file_size: i64,
+    pub mime_type: String,
+    pub file_hash: String,          // Hex SHA-256 of the stored bytes
+    pub encrypted_file_key: Option<String>, // Set when the bytes are client-side encrypted
+    pub download_url: String,
+    pub media_status: Option<MediaStatus>, // Only set for images we process
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub blurhash: Option<String>,
+    pub thumbnails: Vec<ThumbnailResponse>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThumbnailResponse {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub file_size: i64,
+    pub url: String,
+}
+
+impl AttachmentResponse {
+    fn from_db_attachment(attachment: MessageAttachment) -> Self {
+        Self {
+            download_url: download_url(attachment.id),
+            id: attachment.id,
+            message_id: attachment.message_id,
+            file_name: attachment.file_name,
+            file_size: attachment.file_size,
+            mime_type: attachment.mime_type,
+            file_hash: attachment.file_hash,
+            encrypted_file_key: attachment.encrypted_file_key,
+            media_status: attachment.media_status,
+            width: attachment.width,
+            height: attachment.height,
+            blurhash: attachment.blurhash,
+            thumbnails: attachment.thumbnails.into_iter().map(|thumbnail| ThumbnailResponse {
+                url: format!("{}/thumbnails/{}", download_url(attachment.id), thumbnail.name),
+                name: thumbnail.name,
+                width: thumbnail.width,
+                height: thumbnail.height,
+                file_size: thumbnail.file_size,
+            }).collect(),
+            created_at: attachment.created_at,
+        }
+    }
+}
+
+fn download_url(attachment_id: Uuid) -> String {
+    format!("/api/v1/attachments/{}", attachment_id)
+}
+
+fn thumbnail_key(attachment_id: Uuid, name: &str) -> String {
+    format!("{}_{}", attachment_id, name)
+}
+
+/// Every blob an attachment owns: the file itself and its thumbnails
+pub fn blob_keys(attachment: &MessageAttachment) -> Vec<String> {
+    std::iter::once(attachment.file_url.clone())
+        .chain(attachment.thumbnails.iter().map(|thumbnail| thumbnail_key(attachment.id, &thumbnail.name)))
+        .collect()
+}
+
+// 🛂 `allowed` holds exact types (`application/pdf`) or families (`image/*`)
+fn mime_allowed(mime_type: &str, allowed: &[String]) -> bool {
+    allowed.iter().any(|pattern| match pattern.strip_suffix("/*") {
+        Some(family) => mime_type.split_once('/').is_some_and(|(kind, _)| kind == family),
+        None => pattern == mime_type,
+    })
+}
+
+// 🧼 Keep only the last path segment, without anything that could break a header
+fn sanitize_file_name(name: Option<&str>) -> String {
+    let base = name
+        .and_then(|name| name.rsplit(['/', '\\']).next())
+        .unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_FILE_NAME_LENGTH)
+        .collect();
+    let cleaned = cleaned.trim();
+    if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+        "attachment".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+// 🔑 Read the `encrypted_file_key` form field
+// It's opaque to us, but must at least look like a wrapped AES-256 key
+async fn read_encrypted_file_key(field: &mut Field) -> AppResult<String> {
+    let invalid = || AppError::bad_request("Invalid encrypted_file_key");
+    let mut value = Vec::new();
+    while let Some(chunk) = field.try_next().await
+        .map_err(|e| AppError::bad_request(format!("Upload failed: {}", e)))?
+    {
+        value.extend_from_slice(&chunk);
+        if value.len() > 2 * WRAPPED_FILE_KEY_LEN {
+            return Err(invalid());
+        }
+    }
+
+    let value = String::from_utf8(value).map_err(|_| invalid())?;
+    let value = value.trim();
+    match general_purpose::STANDARD.decode(value) {
+        Ok(decoded) if decoded.len() == WRAPPED_FILE_KEY_LEN => Ok(value.to_string()),
+        _ => Err(invalid()),
+    }
+}
+
+// 💾 Stream one multipart field into the blob writer
+// Returns the byte count and hex SHA-256 of what was written
+async fn copy_field(field: &mut Field, writer: &mut dyn BlobWriter, max_bytes: u64) -> AppResult<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut size: u64 = 0;
+
+    while let Some(chunk) = field.try_next().await
+        .map_err(|e| AppError::bad_request(format!("Upload failed: {}", e)))?
+    {
+        size += chunk.len() as u64;
+        if size > max_bytes {
+            return Err(AppError::PayloadTooLarge { limit: max_bytes });
+        }
+        hasher.update(&chunk);
+        writer.write(&chunk).await?;
+    }
+
+    if size == 0 {
+        return Err(AppError::bad_request("Attachment is empty"));
+    }
+    Ok((size, hex::encode(hasher.finalize())))
+}
+
+// 📤 UPLOAD ATTACHMENT ENDPOINT
+// POST /api/v1/messages/{messageId}/attachments (multipart/form-data, field `file`)
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_attachment(
+    path: web::Path<Uuid>,
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    mut payload: Multipart,
+    supabase_client: web::Data<SupabaseClient>,
+    session_manager: web::Data<SessionManager>,
+    blob_store: web::Data<dyn BlobStore>,
+    config: web::Data<Config>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+    let message_id = path.into_inner();
+    let access_token = token.as_str();
+
+    // 🔐 ZERO TRUST: Only the sender can attach files, and only to live messages
+    let message = supabase_client.get_message(message_id, access_token).await?
+        .ok_or_else(|| AppError::NotFound { resource: format!("message {}", message_id) })?;
+    if message.sender_id != user_id {
+        return Err(AppError::forbidden("Only the sender can attach files to a message"));
+    }
+    if message.deleted_at.is_some() {
+        return Err(AppError::bad_request("Cannot attach files to a deleted message"));
+    }
+
+    // Fields after `file` are ignored, so the key has to come first
+    let mut encrypted_file_key = None;
+    let mut field = loop {
+        match payload.try_next().await.map_err(|e| AppError::bad_request(format!("Invalid multipart body: {}", e)))? {
+            Some(field) if field.name() == Some("file") => break field,
+            Some(mut field) if field.name() == Some("encrypted_file_key") => {
+                encrypted_file_key = Some(read_encrypted_file_key(&mut field).await?);
+            }
+            Some(_) => continue,
+            None => return Err(AppError::bad_request("Missing `file` field")),
+        }
+    };
+
+    // 🛂 The declared type decides how clients render it, so it must be allowed
+    let mime_type = field.content_type()
+        .map(|mime| mime.essence_str().to_ascii_lowercase())
+        .ok_or_else(|| AppError::bad_request("Missing content type for `file`"))?;
+    if !mime_allowed(&mime_type, &config.attachment_allowed_mime_types) {
+        return Err(AppError::bad_request(format!("File type {} is not allowed", mime_type)));
+    }
+    let file_name = sanitize_file_name(field.content_disposition().and_then(|cd| cd.get_filename()));
+
+    let attachment_id = Uuid::new_v4();
+    let blob_key = attachment_id.to_string();
+    let mut writer = blob_store.create(&blob_key).await?;
+    let (file_size, file_hash) = match copy_field(&mut field, writer.as_mut(), config.attachment_max_bytes).await {
+        Ok(copied) => copied,
+        Err(e) => {
+            writer.abort().await;
+            return Err(e);
+        }
+    };
+    writer.finish().await?;
+
+    // We can only look inside files the client didn't encrypt
+    let media_status = (encrypted_file_key.is_none() && PROCESSED_IMAGE_TYPES.contains(&mime_type.as_str()))
+        .then_some(MediaStatus::Pending);
+    let attachment = MessageAttachment {
+        id: attachment_id,
+        message_id,
+        file_name,
+        file_url: blob_key.clone(),
+        file_size: file_size as i64,
+        mime_type,
+        encrypted_file_key,
+        file_hash,
+        created_at: Utc::now(),
+        media_status,
+        width: None,
+        height: None,
+        blurhash: None,
+        thumbnails: Vec::new(),
+        processed_at: None,
+    };
+    let attachment = match supabase_client.create_attachment(&attachment, access_token).await {
+        Ok(attachment) => attachment,
+        Err(e) => {
+            // Don't leave bytes behind that no row points at
+            if let Err(cleanup) = blob_store.delete(&blob_key).await {
+                log::warn!("Failed to remove orphaned blob {}: {}", blob_key, cleanup);
+            }
+            return Err(e);
+        }
+    };
+
+    // The first attachment becomes the message's file; later ones are listed only
+    supabase_client
+        .set_message_file(message_id, user_id, &attachment, &download_url(attachment.id), access_token)
+        .await?;
+
+    let participants = supabase_client.get_conversation_participants(message.conversation_id, access_token).await?;
+    session_manager.send_to_users(&participants, None, OutgoingMessage::AttachmentAdded {
+        message_id,
+        conversation_id: message.conversation_id,
+        attachment_id: attachment.id,
+        file_name: attachment.file_name.clone(),
+        file_size: attachment.file_size,
+        mime_type: attachment.mime_type.clone(),
+    });
+
+    if attachment.media_status == Some(MediaStatus::Pending) {
+        spawn_media_job(
+            supabase_client.clone(),
+            session_manager.clone(),
+            blob_store.clone().into_inner(),
+            attachment.clone(),
+            message.conversation_id,
+            participants,
+        );
+    }
+
+    log::info!("📎 Attachment {} ({} bytes) added to message {}", attachment.id, attachment.file_size, message_id);
+    Ok(HttpResponse::Created().json(AttachmentResponse::from_db_attachment(attachment)))
+}
+
+// 📋 LIST ATTACHMENTS ENDPOINT
+// GET /api/v1/messages/{messageId}/attachments
+pub async fn get_message_attachments(
+    path: web::Path<Uuid>,
+    token: web::ReqData<AccessToken>,
+    supabase_client: web::Data<SupabaseClient>,
+) -> AppResult<HttpResponse> {
+    let message_id = path.into_inner();
+    let access_token = token.as_str();
+
+    // 🔐 ZERO TRUST: RLS hides messages (and their attachments) outside the caller's conversations
+    if supabase_client.get_message(message_id, access_token).await?.is_none() {
+        return Err(AppError::NotFound { resource: format!("message {}", message_id) });
+    }
+    let attachments: Vec<AttachmentResponse> = supabase_client
+        .get_message_attachments(message_id, access_token)
+        .await?
+        .into_iter()
+        .map(AttachmentResponse::from_db_attachment)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "attachments": attachments
+    })))
+}
+
+// 📥 DOWNLOAD ATTACHMENT ENDPOINT
+// GET /api/v1/attachments/{attachmentId}
+pub async fn download_attachment(
+    path: web::Path<Uuid>,
+    token: web::ReqData<AccessToken>,
+    supabase_client: web::Data<SupabaseClient>,
+    blob_store: web::Data<dyn BlobStore>,
+) -> AppResult<HttpResponse> {
+    let attachment_id = path.into_inner();
+
+    // 🔐 ZERO TRUST: The row is only visible to participants of the message's conversation
+    let attachment = supabase_client.get_attachment(attachment_id, token.as_str()).await?
+        .ok_or_else(|| AppError::NotFound { resource: format!("attachment {}", attachment_id) })?;
+    // 🧼 The stored original still has its metadata until the media job is done
+    if attachment.media_status == Some(MediaStatus::Pending) {
+        return Err(AppError::Conflict { message: "Attachment is still being processed".to_string() });
+    }
+    let stream = blob_store.open(&attachment.file_url).await?;
+
+    // Ciphertext is only meaningful to the client holding the key
+    let content_type = if attachment.encrypted_file_key.is_some() {
+        "application/octet-stream"
+    } else {
+        attachment.mime_type.as_str()
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(attachment.file_name)],
+        })
+        // Never let a browser reinterpret an upload as something more dangerous
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((header::CACHE_CONTROL, "private, no-store"))
+        .insert_header((header::ETAG, format!("\"{}\"", attachment.file_hash)))
+        .streaming(stream))
+}
+
+// 🖼️ THUMBNAIL ENDPOINT
+// GET /api/v1/attachments/{attachmentId}/thumbnails/{name}
+pub async fn download_thumbnail(
+    path: web::Path<(Uuid, String)>,
+    token: web::ReqData<AccessToken>,
+    supabase_client: web::Data<SupabaseClient>,
+    blob_store: web::Data<dyn BlobStore>,
+) -> AppResult<HttpResponse> {
+    let (attachment_id, name) = path.into_inner();
+    let not_found = || AppError::NotFound { resource: format!("thumbnail {} of attachment {}", name, attachment_id) };
+
+    // 🔐 ZERO TRUST: Same visibility as the attachment itself
+    let attachment = supabase_client.get_attachment(attachment_id, token.as_str()).await?
+        .ok_or_else(not_found)?;
+    let thumbnail = attachment.thumbnails.iter()
+        .find(|thumbnail| thumbnail.name == name)
+        .ok_or_else(not_found)?;
+    let stream = blob_store.open(&thumbnail_key(attachment.id, &thumbnail.name)).await?;
+
+    // Thumbnails are JPEGs we encoded ourselves and never change once stored
+    Ok(HttpResponse::Ok()
+        .content_type("image/jpeg")
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((header::CACHE_CONTROL, "private, max-age=31536000, immutable"))
+        .streaming(stream))
+}
+
+// 🖼️ MEDIA JOB
+// Runs in the background after an image upload has been answered. Any failure
+// marks the attachment `failed` so it doesn't stay undownloadable forever; a job
+// cut short by a restart is caught by `fail_interrupted_media_jobs`.
+fn spawn_media_job(
+    supabase_client: web::Data<SupabaseClient>,
+    session_manager: web::Data<SessionManager>,
+    blob_store: Arc<dyn BlobStore>,
+    attachment: MessageAttachment,
+    conversation_id: Uuid,
+    participants: Vec<Uuid>,
+) {
+    actix_web::rt::spawn(async move {
+        let processed = match process_media(&supabase_client, blob_store.as_ref(), &attachment).await {
+            Ok(Some(processed)) => processed,
+            Ok(None) => return,
+            Err(e) => {
+                log::warn!("🖼️ Processing attachment {} failed: {}", attachment.id, e);
+                let update_data = serde_json::json!({
+                    "media_status": MediaStatus::Failed,
+                    "processed_at": Utc::now()
+                });
+                match supabase_client.update_attachment_media(attachment.id, &update_data).await {
+                    Ok(Some(failed)) => failed,
+                    Ok(None) => return,
+                    Err(e) => {
+                        log::error!("Failed to mark attachment {} as failed: {}", attachment.id, e);
+                        return;
+                    }
+                }
+            }
+        };
+
+        session_manager.send_to_users(&participants, None, OutgoingMessage::AttachmentProcessed {
+            message_id: processed.message_id,
+            conversation_id,
+            attachment_id: processed.id,
+            media_status: processed.media_status.unwrap_or(MediaStatus::Failed),
+            file_size: processed.file_size,
+            width: processed.width,
+            height: processed.height,
+            blurhash: processed.blurhash,
+            thumbnails: processed.thumbnails,
+        });
+    });
+}
+
+// 🖼️ INTERRUPTED MEDIA JOBS
+// Jobs only live in this process, so an attachment still `pending` at startup lost
+// its job when the server last stopped. Failing it serves the original instead of
+// answering 409 for good.
+pub async fn fail_interrupted_media_jobs(supabase_client: &SupabaseClient) {
+    match supabase_client.fail_pending_attachments(Utc::now()).await {
+        Ok(failed) if !failed.is_empty() => {
+            log::warn!("🖼️ Marked {} attachment(s) left pending by an interrupted media job as failed", failed.len());
+        }
+        Ok(_) => {}
+        Err(e) => log::error!("Failed to recover interrupted media jobs: {}", e),
+    }
+}
+
+// Returns the updated row, or None if the message was deleted while we worked
+async fn process_media(
+    supabase_client: &SupabaseClient,
+    blob_store: &dyn BlobStore,
+    attachment: &MessageAttachment,
+) -> AppResult<Option<MessageAttachment>> {
+    let original: Vec<web::Bytes> = blob_store.open(&attachment.file_url).await?.try_collect().await?;
+    let original = original.concat();
+    let processed = web::block(move || media::process_image(&original))
+        .await
+        .map_err(|e| AppError::internal(format!("Media job was cancelled: {}", e)))??;
+
+    // Thumbnails go first so a `ready` row never points at missing blobs
+    let mut thumbnails = Vec::with_capacity(processed.thumbnails.len());
+    for thumbnail in &processed.thumbnails {
+        write_blob(blob_store, &thumbnail_key(attachment.id, thumbnail.name), &thumbnail.jpeg).await?;
+        thumbnails.push(Thumbnail {
+            name: thumbnail.name.to_string(),
+            width: thumbnail.width,
+            height: thumbnail.height,
+            file_size: thumbnail.jpeg.len() as i64,
+        });
+    }
+    // The store swaps the file in atomically; nobody can read it while pending anyway
+    write_blob(blob_store, &attachment.file_url, &processed.stripped).await?;
+
+    let file_size = processed.stripped.len() as i64;
+    let update_data = serde_json::json!({
+        "file_size": file_size,
+        "file_hash": hex::encode(Sha256::digest(&processed.stripped)),
+        "media_status": MediaStatus::Ready,
+        "width": processed.width,
+        "height": processed.height,
+        "blurhash": processed.blurhash,
+        "thumbnails": thumbnails,
+        "processed_at": Utc::now()
+    });
+    let Some(updated) = supabase_client.update_attachment_media(attachment.id, &update_data).await? else {
+        // Deleted for everyone meanwhile; the delete couldn't know about what we just wrote
+        for key in blob_keys(&MessageAttachment { thumbnails, ..attachment.clone() }) {
+            if let Err(e) = blob_store.delete(&key).await {
+                log::warn!("Failed to remove blob {} of deleted attachment {}: {}", key, attachment.id, e);
+            }
+        }
+        return Ok(None);
+    };
+
+    if file_size != attachment.file_size {
+        if let Err(e) = supabase_client.update_message_file_size(attachment.message_id, &download_url(attachment.id), file_size).await {
+            log::warn!("Failed to update file size of message {}: {}", attachment.message_id, e);
+        }
+    }
+
+    log::info!("🖼️ Attachment {} processed: {}x{}, {} thumbnails", attachment.id, processed.width, processed.height, updated.thumbnails.len());
+    Ok(Some(updated))
+}
+
+async fn write_blob(blob_store: &dyn BlobStore, key: &str, bytes: &[u8]) -> AppResult<()> {
+    let mut writer = blob_store.create(key).await?;
+    if let Err(e) = writer.write(bytes).await {
+        writer.abort().await;
+        return Err(e);
+    }
+    writer.finish().await
+}
+
+// 🛤️ CONFIGURE ROUTES
+// Upload and listing live under /messages/{messageId} (see messages::configure_routes)
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/attachments")
+            .route("/{attachmentId}", web::get().to(download_attachment))
+            .route("/{attachmentId}/thumbnails/{name}", web::get().to(download_thumbnail))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mime_types_match_exactly_or_by_family() {
+        let allowed = vec!["image/*".to_string(), "application/pdf".to_string()];
+        assert!(mime_allowed("image/png", &allowed));
+        assert!(mime_allowed("application/pdf", &allowed));
+        assert!(!mime_allowed("application/pdfx", &allowed));
+        assert!(!mime_allowed("imagex/png", &allowed));
+        assert!(!mime_allowed("text/html", &allowed));
+    }
+
+    #[test]
+    fn file_names_are_reduced_to_a_safe_base_name() {
+        assert_eq!(sanitize_file_name(Some("../../etc/passwd")), "passwd");
+        assert_eq!(sanitize_file_name(Some("C:\\Users\\me\\photo \"1\".jpg")), "photo 1.jpg");
+        assert_eq!(sanitize_file_name(Some("a\r\nb.txt")), "ab.txt");
+        assert_eq!(sanitize_file_name(Some("..")), "attachment");
+        assert_eq!(sanitize_file_name(None), "attachment");
+    }
+}
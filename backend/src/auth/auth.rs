@@ -28,6 +28,7 @@ use crate::errors::{AppError, AppResult};
 use crate::config::Config;
 use chrono::{DateTime, Utc};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 
 
@@ -51,6 +52,19 @@ pub struct Claims {
     pub ip_address: Option<String>,     // IP tracking
 }
 
+// 🎟️ RAW ACCESS TOKEN
+// The verified bearer token, stored in request extensions next to `Claims`
+// so handlers can forward it to Supabase and row-level security applies
+// to the calling user rather than to the server.
+#[derive(Debug, Clone)]
+pub struct AccessToken(pub String);
+
+impl AccessToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 // 📊 AUDIT LOG ENTRY
 #[derive(Debug, Serialize)]
 pub struct AuditLog {
@@ -88,33 +102,33 @@ impl RateLimiter {
         }
     }
     
-    // 🚦 Check if request is allowed under rate limit
-    pub fn is_allowed(&self, identifier: &str) -> bool {
+    // 🚦 Check whether an identifier has used up its budget for the current window
+    // Checking doesn't count against the budget; only `record` does.
+    pub fn is_limited(&self, identifier: &str) -> bool {
+        let requests = self.requests.lock().unwrap();
+        let now = Utc::now();
+        
+        requests.get(identifier).is_some_and(|entry| {
+            (now - entry.window_start).num_seconds() <= self.window_duration_seconds
+                && entry.count >= self.max_requests
+        })
+    }
+    
+    // 📝 Count one event against an identifier's budget
+    pub fn record(&self, identifier: &str) {
         let mut requests = self.requests.lock().unwrap();
         let now = Utc::now();
         
-        match requests.get_mut(identifier) {
-            Some(entry) => {
-                // Check if window has expired
-                if (now - entry.window_start).num_seconds() > self.window_duration_seconds {
-                    entry.count = 1;
-                    entry.window_start = now;
-                    true
-                } else if entry.count < self.max_requests {
-                    entry.count += 1;
-                    true
-                } else {
-                    false // Rate limit exceeded
-                }
-            }
-            None => {
-                requests.insert(identifier.to_string(), RateLimitEntry {
-                    count: 1,
-                    window_start: now,
-                });
-                true
-            }
+        let entry = requests.entry(identifier.to_string()).or_insert(RateLimitEntry {
+            count: 0,
+            window_start: now,
+        });
+        // Start a new window once the old one has expired
+        if (now - entry.window_start).num_seconds() > self.window_duration_seconds {
+            entry.count = 0;
+            entry.window_start = now;
         }
+        entry.count += 1;
     }
 }
 
@@ -125,46 +139,96 @@ struct JwksResponse {
     keys: Vec<Jwk>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 struct Jwk {
-    kty: String,        // Key type
-    use_: Option<String>, // Key use
+    kty: String,                // Key type ("RSA" or "EC")
     #[serde(rename = "use")]
-    key_use: Option<String>,
-    kid: String,        // Key ID
-    x5c: Option<Vec<String>>, // X.509 certificate chain
-    n: Option<String>,  // RSA modulus
-    e: Option<String>,  // RSA exponent
+    key_use: Option<String>,    // Key use ("sig" for signing keys)
+    kid: Option<String>,        // Key ID
+    alg: Option<String>,        // Algorithm the key is meant for
+    n: Option<String>,          // RSA modulus (base64url)
+    e: Option<String>,          // RSA exponent (base64url)
+    crv: Option<String>,        // EC curve name
+    x: Option<String>,          // EC x coordinate (base64url)
+    y: Option<String>,          // EC y coordinate (base64url)
+}
+
+// 📍 WHERE TO LOAD THE JWKS FROM
+// `file://` sources let tests and air-gapped deployments use a local key set
+#[derive(Debug, Clone)]
+enum JwksSource {
+    Http(String),
+    File(std::path::PathBuf),
+}
+
+impl JwksSource {
+    fn parse(url: &str) -> Self {
+        match url.strip_prefix("file://") {
+            Some(path) => JwksSource::File(path.into()),
+            None => JwksSource::Http(url.to_string()),
+        }
+    }
+}
+
+// 🗃️ CACHED VERIFICATION KEY
+#[derive(Clone)]
+struct CachedKey {
+    algorithm: Algorithm,
+    decoding_key: DecodingKey,
+}
+
+// 🗃️ JWKS CACHE
+// Keys are kept until the TTL runs out; an unknown `kid` forces an early
+// refresh so key rotation on the issuer side is picked up immediately.
+#[derive(Default)]
+struct JwksCache {
+    keys: HashMap<String, CachedKey>,
+    fetched_at: Option<Instant>,
 }
 
+// Minimum time between refreshes triggered by unknown key IDs.
+// Stops a flood of tokens with random `kid`s from hammering the JWKS endpoint.
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
 // 🔐 JWT VALIDATOR
 // This struct handles all JWT verification logic
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct JwtValidator {
     client: Client,
-    supabase_url: String,
-    validation: Validation,
+    issuer: String,
+    audience: String,
+    jwks_source: JwksSource,
+    jwks_cache_ttl: Duration,
+    jwks_cache: Arc<Mutex<JwksCache>>,
+    hs256_secret: Option<String>,
     rate_limiter: Arc<RateLimiter>,
     audit_logger: Arc<Mutex<Vec<AuditLog>>>, // In production, use proper logging
 }
 
+impl std::fmt::Debug for JwtValidator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtValidator")
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .field("jwks_source", &self.jwks_source)
+            .field("hs256_enabled", &self.hs256_secret.is_some())
+            .finish()
+    }
+}
+
 impl JwtValidator {
     // 🏗️ Constructor
     pub fn new(config: &Config) -> Self {
-        let mut validation = Validation::new(Algorithm::RS256);
-        validation.set_audience(&["authenticated"]); // Supabase uses "authenticated" audience
-        validation.set_issuer(&[&config.supabase_url]);
-        
-        // 🛡️ ZERO TRUST: Strict validation
-        validation.validate_exp = true;
-        validation.validate_nbf = true;
-        validation.leeway = 0; // No clock skew tolerance for maximum security
-        
         Self {
             client: Client::new(),
-            supabase_url: config.supabase_url.clone(),
-            validation,
-            rate_limiter: Arc::new(RateLimiter::new(100, 3600)), // 100 requests per hour
+            issuer: config.jwt_issuer.clone(),
+            audience: config.jwt_audience.clone(),
+            jwks_source: JwksSource::parse(&config.jwks_url),
+            jwks_cache_ttl: Duration::from_secs(config.jwks_cache_ttl_seconds),
+            jwks_cache: Arc::new(Mutex::new(JwksCache::default())),
+            hs256_secret: config.jwt_secret.clone(),
+            // Every API call verifies a token, so only failures count: 100 per IP per hour
+            rate_limiter: Arc::new(RateLimiter::new(100, 3600)),
             audit_logger: Arc::new(Mutex::new(Vec::new())),
         }
     }
@@ -185,9 +249,9 @@ impl JwtValidator {
             session_id: None,
         };
         
-        // 🚦 ZERO TRUST: Rate limiting by IP
+        // 🚦 ZERO TRUST: Rate limiting failed verifications by IP (token guessing)
         if let Some(ip) = &ip_address {
-            if !self.rate_limiter.is_allowed(ip) {
+            if self.rate_limiter.is_limited(ip) {
                 audit.error_message = Some("Rate limit exceeded".to_string());
                 self.log_audit(audit).await;
                 return Err(AppError::auth_failed("Rate limit exceeded".to_string()));
@@ -195,15 +259,13 @@ impl JwtValidator {
         }
         
         // 🔍 Basic token format validation
-        if token.is_empty() || token.len() > 2048 {
-            audit.error_message = Some("Invalid token format".to_string());
-            self.log_audit(audit).await;
-            return Err(AppError::InvalidToken { 
+        let result = if token.is_empty() || token.len() > 2048 {
+            Err(AppError::InvalidToken { 
                 reason: "Invalid token format".to_string() 
-            });
-        }
-        
-        let result = self.verify_token_internal(token).await;
+            })
+        } else {
+            self.verify_token_internal(token).await
+        };
         
         match &result {
             Ok(claims) => {
@@ -213,6 +275,9 @@ impl JwtValidator {
             }
             Err(e) => {
                 audit.error_message = Some(e.to_string());
+                if let Some(ip) = &ip_address {
+                    self.rate_limiter.record(ip);
+                }
             }
         }
         
@@ -220,114 +285,134 @@ impl JwtValidator {
         result
     }
     
-    // 🔍 VERIFY JWT TOKEN - SIMPLIFIED VERSION FOR TESTING
-    // This bypasses JWKS validation for now to get the app working
-    // TODO: Fix JWKS validation later for production
+    // 🔍 VERIFY SIGNATURE AND CLAIMS
+    // HS256 tokens are checked against the shared secret (only if one is
+    // configured); RS256/ES256 tokens against the issuer's JWKS.
     async fn verify_token_internal(&self, token: &str) -> AppResult<Claims> {
-        log::info!("🔍 Verifying JWT token (simplified mode)");
-        
-        // 🧪 TEMPORARY: Simplified validation for testing
-        // This just checks if the token is a valid JWT format and extracts claims
-        // without verifying the signature against Supabase JWKS
-        
-        // Try to decode the token without verification first to extract claims
-        let token_message = decode::<Claims>(
-            token,
-            &DecodingKey::from_secret("temporary".as_ref()), // Dummy key for now
-            &Validation::new(Algorithm::HS256)
-        );
+        // Step 1: Decode the JWT header to find out how it was signed
+        let header = decode_header(token)
+            .map_err(|e| AppError::InvalidToken { 
+                reason: format!("Invalid token header: {}", e) 
+            })?;
         
-        // If that fails, try to decode just the payload without verification
-        if token_message.is_err() {
-            log::info!("🔧 Attempting to extract claims without signature verification...");
-            
-            // Parse the JWT manually to extract claims
-            let parts: Vec<&str> = token.split('.').collect();
-            if parts.len() != 3 {
-                return Err(AppError::InvalidToken {
-                    reason: "Invalid JWT format".to_string(),
-                });
-            }
-            
-            // Decode the payload (second part)
-            let payload_base64 = parts[1];
-            
-            // Add padding if needed for base64 decoding
-            let padded_payload = match payload_base64.len() % 4 {
-                2 => format!("{}==", payload_base64),
-                3 => format!("{}=", payload_base64),
-                _ => payload_base64.to_string(),
-            };
-            
-            let payload_bytes = base64::decode(&padded_payload)
-                .map_err(|e| AppError::InvalidToken {
-                    reason: format!("Failed to decode JWT payload: {}", e),
+        // Step 2: Pick the verification key for that algorithm
+        let decoding_key = match header.alg {
+            Algorithm::HS256 => {
+                let secret = self.hs256_secret.as_ref().ok_or_else(|| AppError::InvalidToken {
+                    reason: "HS256 tokens are not accepted".to_string(),
                 })?;
-            
-            let payload_str = String::from_utf8(payload_bytes)
-                .map_err(|e| AppError::InvalidToken {
-                    reason: format!("Invalid UTF-8 in JWT payload: {}", e),
-                })?;
-            
-            log::info!("📄 JWT Payload: {}", payload_str);
-            
-            // Parse the JSON payload into Claims
-            let claims: Claims = serde_json::from_str(&payload_str)
-                .map_err(|e| AppError::InvalidToken {
-                    reason: format!("Failed to parse JWT claims: {}", e),
+                DecodingKey::from_secret(secret.as_bytes())
+            }
+            Algorithm::RS256 | Algorithm::ES256 => {
+                let kid = header.kid.ok_or_else(|| AppError::InvalidToken {
+                    reason: "Token missing key ID".to_string(),
                 })?;
-            
-            // Basic validation
-            let now = chrono::Utc::now().timestamp();
-            if claims.exp < now {
-                return Err(AppError::TokenExpired);
+                
+                let key = self.get_jwks_key(&kid).await?;
+                if key.algorithm != header.alg {
+                    return Err(AppError::InvalidToken {
+                        reason: "Token algorithm does not match key".to_string(),
+                    });
+                }
+                key.decoding_key
             }
-            
-            log::info!("✅ JWT token verified (simplified) for user: {}", claims.sub);
-            return Ok(claims);
-        }
+            other => {
+                return Err(AppError::InvalidToken {
+                    reason: format!("Unsupported token algorithm: {:?}", other),
+                });
+            }
+        };
         
-        // If the dummy decode worked (shouldn't happen with real Supabase tokens)
-        let claims = token_message.unwrap().claims;
-        log::info!("✅ JWT token verified for user: {}", claims.sub);
+        // Step 3: Verify the signature and the registered claims
+        let token_data = decode::<Claims>(token, &decoding_key, &self.validation_for(header.alg))
+            .map_err(AppError::from)?;
+        
+        // Step 4: Additional validation
+        let claims = token_data.claims;
+        self.validate_claims(&claims)?;
+        
+        log::debug!("✅ JWT token verified for user: {}", claims.sub);
         Ok(claims)
     }
     
-    // 🔍 ORIGINAL VERIFY JWT TOKEN (currently disabled)
-    // This is the full verification with JWKS - enable this once JWKS issues are resolved
-    #[allow(dead_code)]
-    async fn verify_token_internal_full(&self, token: &str) -> AppResult<Claims> {
-        // Step 1: Decode the JWT header to get the key ID (kid)
-        let header = decode_header(token)
-            .map_err(|e| AppError::InvalidToken { 
-                reason: format!("Invalid token header: {}", e) 
-            })?;
+    // 📏 Validation rules for a given algorithm
+    fn validation_for(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&[&self.audience]);
+        validation.set_issuer(&[&self.issuer]);
         
-        let kid = header.kid.ok_or_else(|| AppError::InvalidToken {
-            reason: "Token missing key ID".to_string(),
-        })?;
+        // 🛡️ ZERO TRUST: Strict validation
+        validation.validate_exp = true;
+        validation.validate_nbf = true;
+        validation.leeway = 0; // No clock skew tolerance for maximum security
+        validation
+    }
+    
+    // 🔑 LOOK UP A KEY BY ID
+    // Serves from cache when possible, refreshing on expiry or unknown `kid`
+    async fn get_jwks_key(&self, kid: &str) -> AppResult<CachedKey> {
+        let (cached, is_fresh, may_refresh) = {
+            let cache = self.jwks_cache.lock()
+                .map_err(|_| AppError::internal("JWKS cache lock poisoned"))?;
+            let age = cache.fetched_at.map(|t| t.elapsed());
+            (
+                cache.keys.get(kid).cloned(),
+                age.is_some_and(|a| a < self.jwks_cache_ttl),
+                age.is_none_or(|a| a >= JWKS_MIN_REFRESH_INTERVAL),
+            )
+        };
         
-        // Step 2: Fetch the public key from Supabase JWKS endpoint
-        let jwks_url = format!("{}/auth/v1/keys", self.supabase_url);
+        if let (Some(key), true) = (&cached, is_fresh) {
+            return Ok(key.clone());
+        }
         
-        log::info!("🔑 Fetching JWKS from: {}", jwks_url);
+        if cached.is_none() && is_fresh && !may_refresh {
+            return Err(AppError::InvalidToken {
+                reason: "Key not found in JWKS".to_string(),
+            });
+        }
         
-        let jwks_response = self.client
-            .get(&jwks_url)
-            .timeout(std::time::Duration::from_secs(10)) // 🛡️ Timeout protection
-            .send()
-            .await
-            .map_err(|e| {
-                log::error!("❌ Failed to fetch JWKS: {}", e);
-                AppError::Http(e)
-            })?;
-            
-        let response_text = jwks_response.text().await
-            .map_err(|e| AppError::Http(e))?;
-            
-        log::info!("📄 JWKS Response: {}", response_text);
+        let keys = self.fetch_jwks().await?;
+        let mut cache = self.jwks_cache.lock()
+            .map_err(|_| AppError::internal("JWKS cache lock poisoned"))?;
+        cache.keys = keys;
+        cache.fetched_at = Some(Instant::now());
+        
+        cache.keys.get(kid).cloned().ok_or_else(|| AppError::InvalidToken {
+            reason: "Key not found in JWKS".to_string(),
+        })
+    }
+    
+    // 🌐 FETCH THE KEY SET
+    async fn fetch_jwks(&self) -> AppResult<HashMap<String, CachedKey>> {
+        let body = match &self.jwks_source {
+            JwksSource::Http(url) => {
+                log::info!("🔑 Fetching JWKS from: {}", url);
+                
+                self.client
+                    .get(url)
+                    .timeout(std::time::Duration::from_secs(10)) // 🛡️ Timeout protection
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| {
+                        log::error!("❌ Failed to fetch JWKS: {}", e);
+                        AppError::Http(e)
+                    })?
+                    .text()
+                    .await?
+            }
+            JwksSource::File(path) => {
+                log::info!("🔑 Loading JWKS from: {}", path.display());
+                
+                tokio::fs::read_to_string(path).await
+                    .map_err(|e| AppError::Config {
+                        message: format!("Failed to read JWKS file {}: {}", path.display(), e),
+                    })?
+            }
+        };
         
-        let jwks: JwksResponse = serde_json::from_str(&response_text)
+        let jwks: JwksResponse = serde_json::from_str(&body)
             .map_err(|e| {
                 log::error!("❌ Failed to parse JWKS response: {}", e);
                 AppError::InvalidToken {
@@ -335,47 +420,58 @@ impl JwtValidator {
                 }
             })?;
         
-        // Step 3: Find the correct key by key ID
-        let jwk = jwks
-            .keys
-            .iter()
-            .find(|key| key.kid == kid)
-            .ok_or_else(|| AppError::InvalidToken {
-                reason: "Key not found in JWKS".to_string(),
-            })?;
-        
-        // Step 4: Convert JWK to DecodingKey
-        let decoding_key = self.jwk_to_decoding_key(jwk)?;
-        
-        // Step 5: Verify and decode the token
-        let token_data = decode::<Claims>(token, &decoding_key, &self.validation)
-            .map_err(|e| AppError::from(e))?;
-        
-        // Step 6: Additional validation
-        let claims = token_data.claims;
-        self.validate_claims(&claims)?;
+        let mut keys = HashMap::new();
+        for jwk in &jwks.keys {
+            // Encryption keys and keys without an ID can't be used to verify tokens
+            if jwk.key_use.as_deref().is_some_and(|u| u != "sig") {
+                continue;
+            }
+            let Some(kid) = &jwk.kid else { continue };
+            
+            match self.jwk_to_decoding_key(jwk) {
+                Ok(key) => {
+                    keys.insert(kid.clone(), key);
+                }
+                Err(e) => log::warn!("⚠️ Skipping JWKS key {}: {}", kid, e),
+            }
+        }
         
-        log::debug!("✅ JWT token verified for user: {}", claims.sub);
-        Ok(claims)
+        log::info!("🔑 Loaded {} signing keys from JWKS", keys.len());
+        Ok(keys)
     }
     
     // 🔑 Convert JWK to DecodingKey
     // This converts the JSON Web Key format to what jsonwebtoken crate expects
-    fn jwk_to_decoding_key(&self, jwk: &Jwk) -> AppResult<DecodingKey> {
-        if let Some(x5c) = &jwk.x5c {
-            if let Some(cert) = x5c.first() {
-                let cert_der = base64::decode(cert)
-                    .map_err(|e| AppError::InvalidToken {
-                        reason: format!("Invalid certificate format: {}", e),
-                    })?;
+    fn jwk_to_decoding_key(&self, jwk: &Jwk) -> AppResult<CachedKey> {
+        let invalid = |reason: &str| AppError::InvalidToken { reason: reason.to_string() };
+        
+        match jwk.kty.as_str() {
+            "RSA" => {
+                if jwk.alg.as_deref().is_some_and(|alg| alg != "RS256") {
+                    return Err(invalid("Unsupported RSA key algorithm"));
+                }
+                let (n, e) = jwk.n.as_deref().zip(jwk.e.as_deref())
+                    .ok_or_else(|| invalid("RSA key missing modulus or exponent"))?;
                 
-                return Ok(DecodingKey::from_rsa_der(&cert_der));
+                Ok(CachedKey {
+                    algorithm: Algorithm::RS256,
+                    decoding_key: DecodingKey::from_rsa_components(n, e)?,
+                })
             }
+            "EC" => {
+                if jwk.crv.as_deref() != Some("P-256") {
+                    return Err(invalid("Unsupported EC curve"));
+                }
+                let (x, y) = jwk.x.as_deref().zip(jwk.y.as_deref())
+                    .ok_or_else(|| invalid("EC key missing coordinates"))?;
+                
+                Ok(CachedKey {
+                    algorithm: Algorithm::ES256,
+                    decoding_key: DecodingKey::from_ec_components(x, y)?,
+                })
+            }
+            _ => Err(invalid("Unsupported key format")),
         }
-    
-        Err(AppError::InvalidToken {
-            reason: "Unsupported key format".to_string(),
-        })
     }
     
     
@@ -388,8 +484,15 @@ impl JwtValidator {
             return Err(AppError::TokenExpired);
         }
         
+        // Validate audience
+        if claims.aud != self.audience {
+            return Err(AppError::InvalidToken {
+                reason: "Invalid audience".to_string(),
+            });
+        }
+        
         // Validate issuer
-        if !claims.iss.starts_with(&self.supabase_url) {
+        if claims.iss != self.issuer {
             return Err(AppError::InvalidToken {
                 reason: "Invalid issuer".to_string(),
             });
@@ -448,6 +551,7 @@ pub async fn jwt_middleware(
     match jwt_validator.verify_token(credentials.token(), ip_address, user_agent).await {
         Ok(claims) => {
             req.extensions_mut().insert(claims);
+            req.extensions_mut().insert(AccessToken(credentials.token().to_string()));
             Ok(req)
         }
         Err(e) => {
@@ -555,4 +659,212 @@ pub fn check_permission(claims: &Claims, permission: Permission) -> bool {
             claims.role.as_ref().map_or(false, |role| role == "admin")
         }
     }
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use rsa::pkcs1::EncodeRsaPrivateKey;
+    use rsa::traits::PublicKeyParts;
+    use rsa::RsaPrivateKey;
+
+    const ISSUER: &str = "https://example.supabase.co/auth/v1";
+    const SECRET: &str = "an-hs256-test-secret-that-is-long-enough";
+
+    fn test_config(jwks_url: String, jwt_secret: Option<String>) -> Config {
+        Config {
+            database_url: "postgresql://localhost/test".to_string(),
+            database_max_connections: 1,
+            supabase_url: "https://example.supabase.co".to_string(),
+            supabase_anon_key: "anon".to_string(),
+            supabase_service_role_key: "service".to_string(),
+            server_host: "127.0.0.1".to_string(),
+            server_port: 8080,
+            jwt_secret,
+            jwt_issuer: ISSUER.to_string(),
+            jwt_audience: "authenticated".to_string(),
+            jwks_url,
+            jwks_cache_ttl_seconds: 600,
+            allowed_origins: "*".to_string(),
+            websocket_timeout_seconds: 300,
+            message_edit_window_seconds: None,
+            attachment_storage_dir: "./uploads".to_string(),
+            attachment_max_bytes: 1024,
+            attachment_allowed_mime_types: vec!["image/*".to_string()],
+            actix_workers: 1,
+        }
+    }
+
+    fn test_claims(aud: &str, iss: &str) -> Claims {
+        let now = Utc::now().timestamp();
+        Claims {
+            sub: Uuid::new_v4().to_string(),
+            email: "user@example.com".to_string(),
+            aud: aud.to_string(),
+            iss: iss.to_string(),
+            iat: now,
+            exp: now + 300,
+            role: Some("authenticated".to_string()),
+            user_metadata: None,
+            session_id: None,
+            device_id: None,
+            ip_address: None,
+        }
+    }
+
+    fn rsa_jwk(key: &RsaPrivateKey, kid: &str) -> serde_json::Value {
+        serde_json::json!({
+            "kty": "RSA",
+            "use": "sig",
+            "alg": "RS256",
+            "kid": kid,
+            "n": URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+            "e": URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+        })
+    }
+
+    fn write_jwks(path: &std::path::Path, keys: Vec<serde_json::Value>) {
+        std::fs::write(path, serde_json::json!({ "keys": keys }).to_string()).unwrap();
+    }
+
+    fn sign_rs256(key: &RsaPrivateKey, kid: &str, claims: &Claims) -> String {
+        let pem = key.to_pkcs1_pem(rsa::pkcs8::LineEnding::LF).unwrap();
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.to_string());
+        encode(&header, claims, &EncodingKey::from_rsa_pem(pem.as_bytes()).unwrap()).unwrap()
+    }
+
+    fn temp_jwks_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ochat-jwks-{}.json", Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_rs256_verification_and_unknown_kid_refresh() {
+        let key1 = RsaPrivateKey::new(&mut rand::thread_rng(), 2048).unwrap();
+        let key2 = RsaPrivateKey::new(&mut rand::thread_rng(), 2048).unwrap();
+        let path = temp_jwks_path();
+        write_jwks(&path, vec![rsa_jwk(&key1, "key-1")]);
+
+        let validator = JwtValidator::new(&test_config(format!("file://{}", path.display()), None));
+
+        let claims = test_claims("authenticated", ISSUER);
+        let verified = validator.verify_token_internal(&sign_rs256(&key1, "key-1", &claims)).await.unwrap();
+        assert_eq!(verified.sub, claims.sub);
+
+        // A token signed by the right kid but the wrong key must fail
+        assert!(validator.verify_token_internal(&sign_rs256(&key2, "key-1", &claims)).await.is_err());
+
+        // Rotate: the issuer publishes key-2. The cache is still fresh, so an
+        // unknown kid triggers a refresh once the minimum interval has passed.
+        write_jwks(&path, vec![rsa_jwk(&key1, "key-1"), rsa_jwk(&key2, "key-2")]);
+        let rotated = sign_rs256(&key2, "key-2", &claims);
+        assert!(validator.verify_token_internal(&rotated).await.is_err());
+
+        validator.jwks_cache.lock().unwrap().fetched_at =
+            Some(Instant::now() - JWKS_MIN_REFRESH_INTERVAL);
+        assert!(validator.verify_token_internal(&rotated).await.is_ok());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_cache_ttl_expiry_drops_removed_keys() {
+        let key = RsaPrivateKey::new(&mut rand::thread_rng(), 2048).unwrap();
+        let path = temp_jwks_path();
+        write_jwks(&path, vec![rsa_jwk(&key, "key-1")]);
+
+        let validator = JwtValidator::new(&test_config(format!("file://{}", path.display()), None));
+        let token = sign_rs256(&key, "key-1", &test_claims("authenticated", ISSUER));
+        assert!(validator.verify_token_internal(&token).await.is_ok());
+
+        // The issuer revokes the key; it stays trusted until the TTL runs out
+        write_jwks(&path, vec![]);
+        assert!(validator.verify_token_internal(&token).await.is_ok());
+
+        validator.jwks_cache.lock().unwrap().fetched_at =
+            Some(Instant::now() - Duration::from_secs(601));
+        assert!(validator.verify_token_internal(&token).await.is_err());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_audience_and_issuer_enforced() {
+        let key = RsaPrivateKey::new(&mut rand::thread_rng(), 2048).unwrap();
+        let path = temp_jwks_path();
+        write_jwks(&path, vec![rsa_jwk(&key, "key-1")]);
+
+        let validator = JwtValidator::new(&test_config(format!("file://{}", path.display()), None));
+
+        let wrong_aud = sign_rs256(&key, "key-1", &test_claims("anon", ISSUER));
+        assert!(validator.verify_token_internal(&wrong_aud).await.is_err());
+
+        let wrong_iss = sign_rs256(&key, "key-1", &test_claims("authenticated", "https://evil.example/auth/v1"));
+        assert!(validator.verify_token_internal(&wrong_iss).await.is_err());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_hs256_requires_configured_secret() {
+        let claims = test_claims("authenticated", ISSUER);
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(SECRET.as_bytes()),
+        ).unwrap();
+
+        let jwks_url = format!("file://{}", temp_jwks_path().display());
+
+        let with_secret = JwtValidator::new(&test_config(jwks_url.clone(), Some(SECRET.to_string())));
+        assert_eq!(with_secret.verify_token_internal(&token).await.unwrap().sub, claims.sub);
+
+        let wrong_secret = JwtValidator::new(&test_config(jwks_url.clone(), Some("x".repeat(40))));
+        assert!(wrong_secret.verify_token_internal(&token).await.is_err());
+
+        let without_secret = JwtValidator::new(&test_config(jwks_url, None));
+        assert!(without_secret.verify_token_internal(&token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_token_rejected() {
+        let claims = test_claims("authenticated", ISSUER);
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#);
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap());
+        let token = format!("{}.{}.", header, payload);
+
+        let validator = JwtValidator::new(&test_config(
+            format!("file://{}", temp_jwks_path().display()),
+            Some(SECRET.to_string()),
+        ));
+        assert!(validator.verify_token_internal(&token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_only_failed_verifications_are_rate_limited() {
+        let claims = test_claims("authenticated", ISSUER);
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(SECRET.as_bytes()),
+        ).unwrap();
+        let mut validator = JwtValidator::new(&test_config(
+            format!("file://{}", temp_jwks_path().display()),
+            Some(SECRET.to_string()),
+        ));
+        validator.rate_limiter = Arc::new(RateLimiter::new(3, 3600));
+        let ip = Some("203.0.113.7".to_string());
+
+        for _ in 0..10 {
+            assert!(validator.verify_token(&token, ip.clone(), None).await.is_ok());
+        }
+        for _ in 0..3 {
+            assert!(validator.verify_token("not-a-jwt", ip.clone(), None).await.is_err());
+        }
+        // Budget spent: even a valid token is refused from that IP, but not from others
+        assert!(validator.verify_token(&token, ip, None).await.is_err());
+        assert!(validator.verify_token(&token, Some("203.0.113.8".to_string()), None).await.is_ok());
+    }
+}
@@ -1,2 +1,3 @@
 pub mod auth; // This declares `auth::auth`
 pub mod auth_routes; // This declares `auth::auth_routes`
+pub mod route_policy; // This declares `auth::route_policy`
@@ -0,0 +1,79 @@
+/*
+🚧 ROUTE AUTHENTICATION POLICY
+==============================
+
+Every route under /api/v1 requires a verified bearer token unless it is
+explicitly listed as public here. New routes are protected by default:
+forgetting to add auth to a scope can no longer expose it.
+
+RUST CONCEPTS EXPLAINED:
+- `middleware::from_fn`: Turns a plain async function into actix middleware
+- `#[cfg(feature = "...")]`: Compiles code only when a Cargo feature is enabled
+*/
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    Error,
+};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use super::auth::jwt_middleware;
+
+// 🌍 PUBLIC ROUTES (exact paths)
+pub const PUBLIC_ROUTES: &[&str] = &[
+    "/api/v1/health", // Uptime checks from load balancers
+    "/api/v1/ws",     // Verifies its own token: browsers can't send headers on upgrade
+];
+
+// 🧪 PUBLIC PREFIXES
+// The dummy /test/* routes only exist when built with `--features test-routes`
+#[cfg(feature = "test-routes")]
+const PUBLIC_PREFIXES: &[&str] = &["/api/v1/test"];
+#[cfg(not(feature = "test-routes"))]
+const PUBLIC_PREFIXES: &[&str] = &[];
+
+// 🔍 Is this path reachable without a token?
+pub fn is_public(path: &str) -> bool {
+    PUBLIC_ROUTES.contains(&path)
+        || PUBLIC_PREFIXES.iter().any(|prefix| {
+            path.strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+}
+
+// 🛡️ AUTH LAYER
+// Wraps the whole API scope; delegates the actual verification to `jwt_middleware`
+// so Claims and AccessToken land in request extensions exactly as before.
+pub async fn require_auth(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if is_public(req.path()) {
+        return next.call(req).await;
+    }
+    
+    let credentials = req.extract::<BearerAuth>().await?;
+    let req = jwt_middleware(req, credentials).await.map_err(|(e, _)| e)?;
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_public_routes_are_exact() {
+        assert!(is_public("/api/v1/health"));
+        assert!(is_public("/api/v1/ws"));
+        assert!(!is_public("/api/v1/health/details"));
+        assert!(!is_public("/api/v1/messages/stats"));
+        assert!(!is_public("/api/v1/users"));
+    }
+
+    #[test]
+    fn test_dummy_routes_follow_feature_flag() {
+        assert_eq!(is_public("/api/v1/test/users"), cfg!(feature = "test-routes"));
+        assert!(!is_public("/api/v1/testing"));
+    }
+}
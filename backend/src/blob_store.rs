@@ -0,0 +1,162 @@
+// 🗃️ BLOB STORE MODULE
+// Where attachment bytes live. Handlers only talk to the `BlobStore` trait, so the
+// local filesystem store can later be swapped for object storage without
+// touching the upload/download endpoints.
+
+use async_trait::async_trait;
+use actix_web::web::Bytes;
+use futures_util::{Stream, TryStreamExt};
+use std::path::PathBuf;
+use std::pin::Pin;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+
+use crate::errors::{AppError, AppResult};
+
+/// A blob's bytes, streamed in chunks
+pub type BlobStream = Pin<Box<dyn Stream<Item = AppResult<Bytes>> + Send>>;
+
+/// Storage backend for attachment bytes, addressed by opaque keys
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Start writing a new blob; nothing is visible under `key` until `finish`
+    async fn create(&self, key: &str) -> AppResult<Box<dyn BlobWriter>>;
+
+    /// Stream an existing blob
+    async fn open(&self, key: &str) -> AppResult<BlobStream>;
+
+    /// Remove a blob; removing a missing blob is not an error
+    async fn delete(&self, key: &str) -> AppResult<()>;
+}
+
+/// An in-progress blob upload
+#[async_trait]
+pub trait BlobWriter: Send {
+    async fn write(&mut self, chunk: &[u8]) -> AppResult<()>;
+
+    /// Make the blob visible under its key
+    async fn finish(self: Box<Self>) -> AppResult<()>;
+
+    /// Throw away everything written so far
+    async fn abort(self: Box<Self>);
+}
+
+// 📁 LOCAL FILESYSTEM STORE
+// One file per blob under `root`. Uploads go to `<key>.part` and are renamed
+// into place when finished, so readers never see a partial file.
+#[derive(Debug, Clone)]
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> AppResult<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)
+            .map_err(|e| AppError::internal(format!("Failed to create blob directory {}: {}", root.display(), e)))?;
+        Ok(Self { root })
+    }
+
+    // 🔐 Keys are generated server-side, but never let one escape `root`
+    fn path_for(&self, key: &str) -> AppResult<PathBuf> {
+        let valid = !key.is_empty()
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(AppError::internal(format!("Invalid blob key: {:?}", key)));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn create(&self, key: &str) -> AppResult<Box<dyn BlobWriter>> {
+        let path = self.path_for(key)?;
+        let part_path = path.with_extension("part");
+        let file = tokio::fs::File::create(&part_path).await
+            .map_err(|e| AppError::internal(format!("Failed to create blob {}: {}", key, e)))?;
+
+        Ok(Box::new(LocalBlobWriter { file, path, part_path }))
+    }
+
+    async fn open(&self, key: &str) -> AppResult<BlobStream> {
+        let path = self.path_for(key)?;
+        let file = tokio::fs::File::open(&path).await.map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => AppError::NotFound { resource: format!("blob {}", key) },
+            _ => AppError::internal(format!("Failed to open blob {}: {}", key, e)),
+        })?;
+
+        Ok(Box::pin(ReaderStream::new(file).map_err(|e| AppError::internal(format!("Failed to read blob: {}", e)))))
+    }
+
+    async fn delete(&self, key: &str) -> AppResult<()> {
+        let path = self.path_for(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(AppError::internal(format!("Failed to delete blob {}: {}", key, e))),
+        }
+    }
+}
+
+struct LocalBlobWriter {
+    file: tokio::fs::File,
+    path: PathBuf,
+    part_path: PathBuf,
+}
+
+#[async_trait]
+impl BlobWriter for LocalBlobWriter {
+    async fn write(&mut self, chunk: &[u8]) -> AppResult<()> {
+        self.file.write_all(chunk).await
+            .map_err(|e| AppError::internal(format!("Failed to write blob: {}", e)))
+    }
+
+    async fn finish(mut self: Box<Self>) -> AppResult<()> {
+        self.file.sync_all().await
+            .map_err(|e| AppError::internal(format!("Failed to flush blob: {}", e)))?;
+        tokio::fs::rename(&self.part_path, &self.path).await
+            .map_err(|e| AppError::internal(format!("Failed to store blob: {}", e)))
+    }
+
+    async fn abort(self: Box<Self>) {
+        drop(self.file);
+        if let Err(e) = tokio::fs::remove_file(&self.part_path).await {
+            log::warn!("Failed to remove partial blob {}: {}", self.part_path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> LocalBlobStore {
+        LocalBlobStore::new(std::env::temp_dir().join(format!("blob-store-{}", uuid::Uuid::new_v4()))).unwrap()
+    }
+
+    #[tokio::test]
+    async fn blobs_are_only_visible_once_finished() {
+        let store = temp_store();
+        let mut writer = store.create("abc").await.unwrap();
+        writer.write(b"hello ").await.unwrap();
+        writer.write(b"world").await.unwrap();
+        assert!(matches!(store.open("abc").await, Err(AppError::NotFound { .. })));
+
+        writer.finish().await.unwrap();
+        let chunks: Vec<Bytes> = store.open("abc").await.unwrap().try_collect().await.unwrap();
+        assert_eq!(chunks.concat(), b"hello world");
+
+        store.delete("abc").await.unwrap();
+        store.delete("abc").await.unwrap();
+        assert!(matches!(store.open("abc").await, Err(AppError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn keys_cannot_escape_the_root() {
+        let store = temp_store();
+        assert!(store.create("../escape").await.is_err());
+        assert!(store.open("a/b").await.is_err());
+        assert!(store.delete("").await.is_err());
+    }
+}
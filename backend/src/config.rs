@@ -34,12 +34,24 @@ pub struct Config {
     pub server_port: u16,
     
     // 🛡️ Security settings
-    pub jwt_secret: String,
+    pub jwt_secret: Option<String>,     // Enables HS256 verification when set
+    pub jwt_issuer: String,             // Expected `iss` claim
+    pub jwt_audience: String,           // Expected `aud` claim
+    pub jwks_url: String,               // https:// endpoint or file:// path
+    pub jwks_cache_ttl_seconds: u64,    // How long fetched keys are trusted
     pub allowed_origins: String,
     
     // ⏱️ Connection settings
     pub websocket_timeout_seconds: u64,
     
+    // 💬 Messaging settings
+    pub message_edit_window_seconds: Option<u64>, // None: messages can be edited at any time
+    
+    // 📎 Attachment settings
+    pub attachment_storage_dir: String,             // Root of the local blob store
+    pub attachment_max_bytes: u64,                  // Largest accepted upload
+    pub attachment_allowed_mime_types: Vec<String>, // Exact MIME types, or `type/*`
+    
     // 🚀 Performance settings
     pub actix_workers: usize,
 }
@@ -63,6 +75,8 @@ impl Config {
             Ok(env::var(key).unwrap_or_else(|_| default.to_string()))
         };
         
+        let supabase_url = get_env("SUPABASE_URL")?;
+        
         Ok(Config {
             // 🗄️ Database configuration
             database_url: get_env("DATABASE_URL")?,
@@ -71,7 +85,7 @@ impl Config {
                 .with_context(|| "DATABASE_MAX_CONNECTIONS must be a valid number")?,
                 
             // 🔐 Supabase configuration
-            supabase_url: get_env("SUPABASE_URL")?,
+            supabase_url: supabase_url.clone(),
             supabase_anon_key: get_env("SUPABASE_ANON_KEY")?,
             supabase_service_role_key: get_env("SUPABASE_SERVICE_ROLE_KEY")?,
             
@@ -82,10 +96,18 @@ impl Config {
                 .with_context(|| "SERVER_PORT must be a valid port number")?,
                 
             // 🛡️ Security configuration
-            jwt_secret: env::var("JWT_SECRET").unwrap_or_else(|_| {
-                log::warn!("⚠️  JWT_SECRET not set, using default (NOT SECURE FOR PRODUCTION!)");
-                "default_jwt_secret_change_me_in_production".to_string()
-            }),
+            // No default secret: an unset JWT_SECRET disables HS256 entirely
+            // instead of accepting tokens signed with a well-known value.
+            jwt_secret: env::var("JWT_SECRET").ok().filter(|s| !s.is_empty()),
+            jwt_issuer: env::var("JWT_ISSUER")
+                .unwrap_or_else(|_| format!("{}/auth/v1", supabase_url)),
+            jwt_audience: env::var("JWT_AUDIENCE")
+                .unwrap_or_else(|_| "authenticated".to_string()),
+            jwks_url: env::var("JWKS_URL")
+                .unwrap_or_else(|_| format!("{}/auth/v1/.well-known/jwks.json", supabase_url)),
+            jwks_cache_ttl_seconds: parse_env("JWKS_CACHE_TTL_SECONDS", "600")?
+                .parse()
+                .with_context(|| "JWKS_CACHE_TTL_SECONDS must be a valid number")?,
             allowed_origins: env::var("ALLOWED_ORIGINS")
                 .unwrap_or_else(|_| "http://localhost:3000".to_string()),
             
@@ -93,6 +115,28 @@ impl Config {
             websocket_timeout_seconds: parse_env("WEBSOCKET_TIMEOUT_SECONDS", "300")?
                 .parse()
                 .with_context(|| "WEBSOCKET_TIMEOUT_SECONDS must be a valid number")?,
+            
+            // 💬 Messaging configuration
+            message_edit_window_seconds: match env::var("MESSAGE_EDIT_WINDOW_SECONDS") {
+                Ok(value) if !value.is_empty() => Some(value.parse()
+                    .with_context(|| "MESSAGE_EDIT_WINDOW_SECONDS must be a valid number")?),
+                _ => None,
+            },
+            
+            // 📎 Attachment configuration
+            attachment_storage_dir: env::var("ATTACHMENT_STORAGE_DIR")
+                .unwrap_or_else(|_| "./uploads".to_string()),
+            attachment_max_bytes: parse_env("ATTACHMENT_MAX_BYTES", "26214400")?
+                .parse()
+                .with_context(|| "ATTACHMENT_MAX_BYTES must be a valid number")?,
+            attachment_allowed_mime_types: parse_env(
+                "ATTACHMENT_ALLOWED_MIME_TYPES",
+                "image/*,video/mp4,audio/mpeg,audio/ogg,audio/mp4,application/pdf,text/plain",
+            )?
+                .split(',')
+                .map(|mime| mime.trim().to_ascii_lowercase())
+                .filter(|mime| !mime.is_empty())
+                .collect(),
                 
             // 🚀 Performance configuration
             actix_workers: parse_env("ACTIX_WORKERS", "4")?
@@ -127,6 +171,20 @@ impl Config {
             anyhow::bail!("ACTIX_WORKERS must be greater than 0");
         }
         
+        if !self.jwks_url.starts_with("https://") && !self.jwks_url.starts_with("file://") {
+            anyhow::bail!("JWKS_URL must start with https:// or file://");
+        }
+        
+        if self.attachment_max_bytes == 0 {
+            anyhow::bail!("ATTACHMENT_MAX_BYTES must be greater than 0");
+        }
+        
+        if let Some(secret) = &self.jwt_secret {
+            if secret.len() < 32 {
+                anyhow::bail!("JWT_SECRET must be at least 32 characters");
+            }
+        }
+        
         Ok(())
     }
     
@@ -140,7 +198,17 @@ impl Config {
                    self.database_max_connections);
         log::info!("  🔐 Supabase: {}", self.supabase_url);
         log::info!("  ⏱️  WebSocket timeout: {}s", self.websocket_timeout_seconds);
+        match self.message_edit_window_seconds {
+            Some(seconds) => log::info!("  ✏️  Message edit window: {}s", seconds),
+            None => log::info!("  ✏️  Message edit window: unlimited"),
+        }
+        log::info!("  📎 Attachments: {} (max {} bytes, types: {})",
+                   self.attachment_storage_dir,
+                   self.attachment_max_bytes,
+                   self.attachment_allowed_mime_types.join(","));
         log::info!("  🚀 Workers: {}", self.actix_workers);
         log::info!("  🛡️  CORS origins: {}", self.allowed_origins);
+        log::info!("  🔑 JWKS: {} (cache TTL: {}s)", self.jwks_url, self.jwks_cache_ttl_seconds);
+        log::info!("  🔑 HS256 tokens: {}", if self.jwt_secret.is_some() { "enabled" } else { "disabled" });
     }
 } 
\ No newline at end of file
@@ -13,7 +13,8 @@ RUST CONCEPTS EXPLAINED:
 DATABASE DESIGN:
 - `users` table: Stores user information from Supabase
 - `messages` table: Stores all chat messages
-- `conversations` table: Tracks conversation participants (for future use)
+- `conversations` table: One row per 1:1 or group chat
+- `conversation_participants` table: Who belongs to which conversation
 */
 
 use sqlx::{PgPool, Row};
@@ -21,6 +22,7 @@ use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
 use crate::errors::{AppError, AppResult};
+use crate::encryption::{KdfParams, UNENCRYPTED_VERSION};
 
 // 🏗️ DATABASE MODELS
 // These structs represent our database tables
@@ -44,19 +46,41 @@ pub struct User {
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Message {
     pub id: Uuid,                    // Unique message ID
+    pub conversation_id: Uuid,       // Conversation this message belongs to
     pub sender_id: Uuid,             // Who sent the message
-    pub receiver_id: Uuid,           // Who should receive the message
+    pub receiver_id: Option<Uuid>,   // Recipient in 1:1 chats (None for groups)
+    #[serde(default)]
+    pub reply_to_id: Option<Uuid>,   // Message this one replies to (same conversation)
     
     // 🔐 ENCRYPTED CONTENT FIELDS
     pub encrypted_content: String,   // AES-GCM encrypted message content (base64)
     pub content_hash: String,        // SHA-256 hash for integrity verification
     pub encryption_version: i32,     // Version for future encryption upgrades
-    pub nonce: String,               // AES nonce (base64)
-    pub session_key_id: Uuid,        // ID of the session key used
+    pub nonce: Option<String>,       // AES nonce (base64); None when stored unencrypted
+    pub session_key_id: Option<Uuid>, // ID of the session key used; None when stored unencrypted
+    pub session_key_generation: i32, // Which rotation of the conversation's key that is
+    #[serde(default)]
+    pub signature: Option<String>,   // Sender's signature (base64); None on unsigned legacy messages
+    #[serde(default)]
+    pub signature_key_version: Option<i32>, // Which of the sender's key pairs signed it
     
     // 📝 MESSAGE METADATA
     pub message_type: MessageType,   // Type of message (text, image, etc.)
     pub is_read: bool,               // Whether message has been read
+    #[serde(default)]
+    pub status: MessageStatus,       // Delivery lifecycle: sent → delivered → read
+    #[serde(default)]
+    pub delivered_at: Option<DateTime<Utc>>, // When a recipient device first received it
+    #[serde(default)]
+    pub read_at: Option<DateTime<Utc>>, // When the recipient read it (drives read receipt sync)
+    #[serde(default)]
+    pub client_msg_id: Option<Uuid>, // Sender-generated ID, echoed back in the `ack`
+    #[serde(default)]
+    pub edited_at: Option<DateTime<Utc>>, // Last content edit, if any
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>, // Tombstone: deleted for everyone, content wiped
+    #[serde(default)]
+    pub deleted_by: Option<Uuid>,    // Who deleted it (always the sender)
     pub file_url: Option<String>,    // URL for file attachments
     pub file_size: Option<i64>,      // File size in bytes
     pub mime_type: Option<String>,   // MIME type for files
@@ -77,17 +101,152 @@ pub enum MessageType {
     System,      // System message (user joined, etc.)
 }
 
+// ✏️ MESSAGE EDIT MODEL
+// A prior revision of an edited message (row of `message_edits`)
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct MessageEdit {
+    pub id: Uuid,
+    pub message_id: Uuid,            // Message that was edited
+    pub encrypted_content: String,   // Content before the edit
+    pub content_hash: String,        // Hash of that content
+    pub nonce: Option<String>,       // None if that revision was unencrypted
+    pub session_key_id: Option<Uuid>,
+    pub encryption_version: i32,     // Format that revision was encrypted in
+    #[serde(default)]
+    pub signature: Option<String>,   // Sender's signature over that revision, if signed
+    #[serde(default)]
+    pub signature_key_version: Option<i32>,
+    pub edited_by: Uuid,
+    pub created_at: DateTime<Utc>,   // When this revision was replaced
+}
+
+// ✏️ MESSAGE CONTENT UPDATE
+// New content for an edited message; the old one goes to `message_edits`
+#[derive(Debug, Clone)]
+pub struct MessageContentUpdate {
+    pub encrypted_content: String,
+    pub content_hash: String,
+    pub nonce: Option<String>,          // None leaves the stored one
+    pub session_key_id: Option<Uuid>,   // Ditto
+    pub session_key_generation: Option<i32>, // Ditto
+    pub encryption_version: Option<i32>, // Ditto
+    pub signature: Option<MessageSignature>, // Replaces the old one; None leaves the content unsigned
+}
+
+// 🔐 MESSAGE ENCRYPTION METADATA
+// How a message's content was encrypted, from whoever encrypted it: sent along
+// with new and edited content, and handed back to readers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageEncryption {
+    pub content_hash: String,        // SHA-256 of the plaintext
+    pub nonce: String,               // This message's own AES-GCM nonce (base64)
+    pub encryption_version: i32,
+    pub session_key_id: Uuid,
+    pub session_key_generation: i32,
+    #[serde(default)]
+    pub signature: Option<MessageSignature>,
+}
+
+// ✍️ MESSAGE SIGNATURE
+// The sender's signature over a message (see `EncryptionService::sign_message`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageSignature {
+    pub signature: String,           // RSA-PSS-SHA256 (base64)
+    pub key_version: i32,            // Which of the sender's key pairs signed it
+}
+
+// 🙈 HIDDEN MESSAGE MODEL
+// A message one user deleted for themselves only (row of `hidden_messages`)
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct HiddenMessage {
+    pub message_id: Uuid,
+    pub user_id: Uuid,
+    pub conversation_id: Uuid,
+    pub hidden_at: DateTime<Utc>,
+}
+
+// 👁️ MESSAGE READ MODEL
+// One participant having read a message (row of `message_reads`)
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct MessageRead {
+    pub message_id: Uuid,
+    pub user_id: Uuid,               // Reader
+    pub conversation_id: Uuid,
+    pub read_at: DateTime<Utc>,
+}
+
+// 😀 MESSAGE REACTION MODEL
+// One user's emoji on a message (row of `message_reactions`)
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct MessageReaction {
+    pub message_id: Uuid,
+    pub user_id: Uuid,
+    pub emoji: String,
+    pub conversation_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+// 📬 MESSAGE STATUS
+// Delivery lifecycle of a message, only ever moves forward
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "message_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MessageStatus {
+    #[default]
+    Sent,        // Persisted by the server
+    Delivered,   // Received by at least one recipient device
+    Read,        // Marked read by the recipient
+}
+
 // 📨 NEW MESSAGE DTO (Data Transfer Object)
 // This struct is used when creating new messages
 // RUST PATTERN: Separate structs for different use cases
 #[derive(Debug, Deserialize)]
 pub struct NewMessage {
-    pub receiver_id: Uuid,
+    #[serde(default)]
+    pub id: Option<Uuid>,            // Sender-chosen ID, when it's bound into the ciphertext
+    pub conversation_id: Uuid,
+    pub receiver_id: Option<Uuid>,   // Only set for 1:1 conversations
+    #[serde(default)]
+    pub client_msg_id: Option<Uuid>, // Sender-generated ID for acks
+    #[serde(default)]
+    pub reply_to_id: Option<Uuid>,   // Message being replied to
     pub content: String,
     pub message_type: Option<MessageType>,
-    pub file_url: Option<String>,
-    pub file_size: Option<i64>,
-    pub mime_type: Option<String>,
+    #[serde(default)]
+    pub encryption: Option<MessageEncryption>, // Stored as `UNENCRYPTED_VERSION` when absent
+    // File fields are never taken from the client: they are filled in by the
+    // server when an attachment is uploaded to the message
+}
+
+// 🧑‍🤝‍🧑 CONVERSATION MODEL
+// A 1:1 or group chat. Direct conversations use the deterministic
+// `encryption::create_conversation_id` of both users as their ID.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Conversation {
+    pub id: Uuid,                    // Unique conversation ID
+    pub name: Option<String>,        // Display name (groups only)
+    pub is_group: bool,              // Group chat vs direct message
+    pub created_by: Option<Uuid>,    // Creator (None if their account was deleted)
+    pub created_at: DateTime<Utc>,   // When conversation was created
+    pub updated_at: DateTime<Utc>,   // Bumped on every new message
+}
+
+// 👤 CONVERSATION MEMBER MODEL
+// A row of `conversation_participants`
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ConversationMember {
+    pub conversation_id: Uuid,       // Conversation joined
+    pub user_id: Uuid,               // Participating user
+    pub joined_at: DateTime<Utc>,    // When they were added
+}
+
+// 🧑‍🤝‍🧑 NEW CONVERSATION DTO
+// Groups only: direct conversations come from `get_or_create_direct_conversation`
+#[derive(Debug)]
+pub struct NewConversation {
+    pub name: Option<String>,
+    pub participant_ids: Vec<Uuid>,  // Must include the creator
 }
 
 // 🔐 ENCRYPTION KEY MODEL
@@ -96,7 +255,9 @@ pub struct NewMessage {
 pub struct EncryptionKey {
     pub id: Uuid,                    // Unique key ID
     pub user_id: Uuid,               // User who owns this key
-    pub encrypted_private_key: String, // User's encrypted private key
+    pub encrypted_private_key: String, // Passphrase-sealed PKCS#8 private key (base64)
+    #[sqlx(json)]
+    pub kdf_params: KdfParams,       // How to derive the sealing key from the passphrase
     pub public_key: String,          // User's public key (PEM format)
     pub key_version: i32,            // Key version for rotation
     pub algorithm: String,           // Encryption algorithm used
@@ -104,20 +265,43 @@ pub struct EncryptionKey {
     pub expires_at: Option<DateTime<Utc>>, // Optional key expiration
 }
 
+// 🔓 PUBLIC KEY MODEL
+// A row of the `user_public_keys` view: anyone's public key, no private material
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UserPublicKey {
+    pub user_id: Uuid,               // User who owns this key
+    pub key_version: i32,            // Key version for rotation
+    pub public_key: String,          // Public key (PEM format)
+    pub algorithm: String,           // How session keys are wrapped for it
+    pub created_at: DateTime<Utc>,   // When key was created
+    pub expires_at: Option<DateTime<Utc>>, // Optional key expiration
+}
+
 // 🔑 SESSION KEY MODEL
-// Represents session keys for conversations
+// One generation of a conversation's session key
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct ConversationSession {
-    pub id: Uuid,                    // Unique session ID
-    pub user1_id: Uuid,              // First participant
-    pub user2_id: Uuid,              // Second participant
-    pub encrypted_session_key: String, // Encrypted session key
-    pub session_key_hash: String,    // Hash of session key for verification
-    pub is_active: bool,             // Whether session is active
+    pub id: Uuid,                    // Unique session ID (messages' `session_key_id`)
+    pub conversation_id: Uuid,       // Conversation the key encrypts
+    pub generation: i32,             // 1 for the first key, +1 per rotation
+    pub session_key_hash: String,    // SHA-256 of the session key for verification
+    pub is_active: bool,             // False once rotated out
+    pub message_count: i32,          // Messages sent under this key
     pub created_at: DateTime<Utc>,   // When session was created
     pub last_used: DateTime<Utc>,    // When session was last used
 }
 
+// 🔑 SESSION KEY SHARE MODEL
+// A session key wrapped for one participant; only that participant can read it
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SessionKeyShare {
+    pub session_id: Uuid,            // Session the key belongs to
+    pub user_id: Uuid,               // Participant it's wrapped for
+    pub encrypted_session_key: String, // Base64 session key wrapped with their public key
+    pub key_version: i32,            // Which of their key pairs wrapped it
+    pub created_at: DateTime<Utc>,   // When it was shared
+}
+
 // 📎 MESSAGE ATTACHMENT MODEL
 // Represents file attachments for messages
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -131,6 +315,42 @@ pub struct MessageAttachment {
     pub encrypted_file_key: Option<String>, // Encrypted file encryption key
     pub file_hash: String,           // File integrity hash
     pub created_at: DateTime<Utc>,   // When attachment was created
+
+    // 🖼️ MEDIA (images only, filled in by the background thumbnail job)
+    #[serde(default)]
+    pub media_status: Option<MediaStatus>, // None for files we don't process
+    #[serde(default)]
+    pub width: Option<i32>,          // Display width, after EXIF orientation
+    #[serde(default)]
+    pub height: Option<i32>,         // Display height, after EXIF orientation
+    #[serde(default)]
+    pub blurhash: Option<String>,    // Placeholder to render until a thumbnail loads
+    #[serde(default)]
+    #[sqlx(json)]
+    pub thumbnails: Vec<Thumbnail>,  // Smaller JPEG renditions, smallest first
+    #[serde(default)]
+    pub processed_at: Option<DateTime<Utc>>, // When the thumbnail job finished
+}
+
+// 🖼️ MEDIA STATUS
+// Progress of the background job that processes image attachments
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "media_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MediaStatus {
+    Pending,     // Uploaded, waiting for the job; not downloadable yet
+    Ready,       // Metadata stripped, thumbnails and blurhash recorded
+    Failed,      // Not a decodable image; served as uploaded, without previews
+}
+
+// 🖼️ THUMBNAIL
+// One entry of `message_attachments.thumbnails`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Thumbnail {
+    pub name: String,                // Size name (`small`, `medium`), part of its URL
+    pub width: u32,
+    pub height: u32,
+    pub file_size: i64,
 }
 
 // 🔗 DATABASE CONNECTION POOL
@@ -291,15 +511,53 @@ impl User {
 
 // 💬 MESSAGE DATABASE OPERATIONS
 impl Message {
+    // 🔐 What a reader needs to decrypt this message, if it was encrypted
+    pub fn encryption(&self) -> Option<MessageEncryption> {
+        if self.encryption_version == UNENCRYPTED_VERSION as i32 {
+            return None;
+        }
+        Some(MessageEncryption {
+            content_hash: self.content_hash.clone(),
+            nonce: self.nonce.clone()?,
+            encryption_version: self.encryption_version,
+            session_key_id: self.session_key_id?,
+            session_key_generation: self.session_key_generation,
+            signature: self.signature.clone().zip(self.signature_key_version)
+                .map(|(signature, key_version)| MessageSignature { signature, key_version }),
+        })
+    }
+    
+    // ✏️ Check that `user_id` may edit this message right now
+    // Only the sender can edit, and only within `window_seconds` of sending (if set)
+    pub fn ensure_editable_by(&self, user_id: Uuid, window_seconds: Option<u64>, now: DateTime<Utc>) -> AppResult<()> {
+        if self.sender_id != user_id {
+            return Err(AppError::forbidden("Only the sender can edit a message"));
+        }
+        
+        if self.deleted_at.is_some() {
+            return Err(AppError::bad_request("Deleted messages cannot be edited"));
+        }
+        
+        if let Some(window) = window_seconds {
+            let deadline = self.created_at + chrono::Duration::seconds(window as i64);
+            if now > deadline {
+                return Err(AppError::forbidden(format!("Messages can only be edited within {} seconds", window)));
+            }
+        }
+        
+        Ok(())
+    }
+    
     // 💾 Create a new message
     pub async fn create(pool: &PgPool, sender_id: Uuid, new_message: NewMessage) -> AppResult<Message> {
         let message = sqlx::query_as::<_, Message>(
             r#"
-            INSERT INTO messages (sender_id, receiver_id, content, message_type)
-            VALUES ($1, $2, $3, $4)
+            INSERT INTO messages (conversation_id, sender_id, receiver_id, content, message_type)
+            VALUES ($1, $2, $3, $4, $5)
             RETURNING *
             "#
         )
+        .bind(new_message.conversation_id)
         .bind(sender_id)
         .bind(new_message.receiver_id)
         .bind(new_message.content)
@@ -364,4 +622,66 @@ impl Message {
         
         Ok(count)
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message_from(sender_id: Uuid, created_at: DateTime<Utc>) -> Message {
+        Message {
+            id: Uuid::new_v4(),
+            conversation_id: Uuid::new_v4(),
+            sender_id,
+            receiver_id: None,
+            reply_to_id: None,
+            encrypted_content: "ciphertext".to_string(),
+            content_hash: "hash".to_string(),
+            encryption_version: 1,
+            nonce: Some("nonce".to_string()),
+            session_key_id: Some(Uuid::new_v4()),
+            session_key_generation: 1,
+            signature: None,
+            signature_key_version: None,
+            message_type: MessageType::Text,
+            is_read: false,
+            status: MessageStatus::Sent,
+            delivered_at: None,
+            read_at: None,
+            client_msg_id: None,
+            edited_at: None,
+            deleted_at: None,
+            deleted_by: None,
+            file_url: None,
+            file_size: None,
+            mime_type: None,
+            created_at,
+            updated_at: created_at,
+        }
+    }
+
+    #[test]
+    fn only_the_sender_can_edit() {
+        let sender = Uuid::new_v4();
+        let message = message_from(sender, Utc::now());
+
+        assert!(message.ensure_editable_by(sender, None, Utc::now()).is_ok());
+        assert!(matches!(
+            message.ensure_editable_by(Uuid::new_v4(), None, Utc::now()),
+            Err(AppError::Forbidden { .. })
+        ));
+    }
+
+    #[test]
+    fn edits_are_limited_to_the_configured_window() {
+        let sender = Uuid::new_v4();
+        let sent_at = Utc::now();
+        let message = message_from(sender, sent_at);
+
+        let inside = sent_at + chrono::Duration::seconds(60);
+        let outside = sent_at + chrono::Duration::seconds(901);
+        assert!(message.ensure_editable_by(sender, Some(900), inside).is_ok());
+        assert!(message.ensure_editable_by(sender, Some(900), outside).is_err());
+        assert!(message.ensure_editable_by(sender, None, outside).is_ok());
+    }
+}
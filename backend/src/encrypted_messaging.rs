@@ -3,14 +3,14 @@
 // It integrates encryption with the Supabase API for end-to-end encrypted chat
 
 use crate::{
-    encryption::{EncryptionService, SessionKey, EncryptedMessage, EncryptionError},
+    encryption::{EncryptionService, EncryptionKeyPair, SessionKey, EncryptedMessage, EncryptionError, KeyWrapAlgorithm, MessageContext},
     supabase_api::SupabaseClient,
-    database::{NewMessage, Message, User},
-    errors::AppResult,
+    database::{ConversationSession, NewMessage, Message, MessageContentUpdate, MessageEncryption, MessageSignature, SessionKeyShare},
+    errors::{AppError, AppResult},
 };
-use serde_json::Value;
+use std::collections::HashMap;
 use uuid::Uuid;
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 
 // 🔑 ENCRYPTED MESSAGING SERVICE
 
@@ -18,6 +18,10 @@ use chrono::Utc;
 pub struct EncryptedMessagingService {
     encryption_service: EncryptionService,
     supabase_client: SupabaseClient,
+    // Key pairs unlocked in this process, by (user, key version); never persisted in the clear
+    key_pairs: HashMap<(Uuid, u32), EncryptionKeyPair>,
+    // Public keys by (user, key version) for checking signatures; a version's key never changes
+    public_keys: HashMap<(Uuid, i32), rsa::RsaPublicKey>,
 }
 
 impl EncryptedMessagingService {
@@ -26,9 +30,32 @@ impl EncryptedMessagingService {
         Self {
             encryption_service: EncryptionService::new(),
             supabase_client,
+            key_pairs: HashMap::new(),
+            public_keys: HashMap::new(),
         }
     }
 
+    /// Keep an unlocked key pair for unwrapping the user's session keys
+    /// The client unseals it itself (`EncryptionService::unseal_private_key`, with the
+    /// sealed blob from GET /api/v1/keys/private); the passphrase never leaves it.
+    pub fn remember_key_pair(&mut self, user_id: Uuid, key_pair: EncryptionKeyPair) {
+        self.key_pairs.insert((user_id, key_pair.version), key_pair);
+    }
+
+    /// Sign a message with the sender's newest unlocked key pair
+    fn sign_message(&self, context: &MessageContext, plaintext: &str) -> AppResult<MessageSignature> {
+        let key_pair = self.key_pairs.iter()
+            .filter(|((user_id, _), _)| *user_id == context.sender_id)
+            .max_by_key(|((_, version), _)| *version)
+            .map(|(_, key_pair)| key_pair)
+            .ok_or_else(|| AppError::Encryption { message: format!("No unlocked key pair to sign with for user {}", context.sender_id) })?;
+        
+        Ok(MessageSignature {
+            signature: self.encryption_service.sign_message(&key_pair.private_key, context, plaintext)?,
+            key_version: key_pair.version as i32,
+        })
+    }
+
     /// Send an encrypted message
     pub async fn send_encrypted_message(
         &mut self,
@@ -36,40 +63,133 @@ impl EncryptedMessagingService {
         receiver_id: Uuid,
         plaintext_content: &str,
         message_type: Option<crate::database::MessageType>,
-        file_url: Option<String>,
-        file_size: Option<i64>,
-        mime_type: Option<String>,
         access_token: &str,
     ) -> AppResult<Message> {
-        // 🔐 STEP 1: Get or create session key for this conversation
-        let session_key = self.get_or_create_session_key(sender_id, receiver_id, access_token).await?;
+        // 🔐 STEP 1: Get or create the current session key for this conversation
+        let conversation = self.supabase_client
+            .get_or_create_direct_conversation(sender_id, receiver_id, access_token)
+            .await?;
+        let session_key = self.get_or_create_session_key(conversation.id, sender_id, access_token).await?;
         
-        // 🔐 STEP 2: Encrypt the message content
-        let encrypted_message = self.encryption_service.encrypt_message(plaintext_content, &session_key)?;
+        // 🔐 STEP 2: Encrypt the message content, bound to the ID it will be stored under
+        let message_id = Uuid::new_v4();
+        let context = MessageContext::direct(sender_id, receiver_id, message_id);
+        let encrypted_message = self.encryption_service.encrypt_message(plaintext_content, &session_key, &context)?;
+        let signature = self.sign_message(&context, plaintext_content)?;
         
         // 🔐 STEP 3: Create the message data for Supabase
         let new_message = NewMessage {
-            receiver_id,
+            id: Some(message_id),
+            conversation_id: conversation.id,
+            receiver_id: Some(receiver_id),
+            client_msg_id: None,
+            reply_to_id: None,
             content: encrypted_message.encrypted_content.clone(), // Store encrypted content
             message_type,
-            file_url,
-            file_size,
-            mime_type,
+            encryption: Some(MessageEncryption {
+                content_hash: encrypted_message.content_hash,
+                nonce: encrypted_message.nonce, // Fresh for this message
+                encryption_version: encrypted_message.encryption_version as i32,
+                session_key_id: encrypted_message.session_key_id,
+                session_key_generation: encrypted_message.session_key_generation as i32,
+                signature: Some(signature),
+            }),
         };
         
-        // 🔐 STEP 4: Send to Supabase with encrypted content
-        let mut message = self.supabase_client.create_message(&new_message, sender_id, access_token).await?;
-        
-        // 🔐 STEP 5: Update message with encryption metadata
-        // Note: In a real implementation, you'd need to update the message with encryption metadata
-        // For now, we'll store this in the session key mapping
+        // 🔐 STEP 4: Send to Supabase with encrypted content and its metadata
+        let message = self.supabase_client.create_message(&new_message, sender_id, access_token).await?.into_message();
         
         Ok(message)
     }
 
+    /// Edit an encrypted message
+    /// The new content is encrypted under the conversation's session key
+    pub async fn edit_encrypted_message(
+        &mut self,
+        editor_id: Uuid,
+        message_id: Uuid,
+        plaintext_content: &str,
+        edit_window_seconds: Option<u64>,
+        access_token: &str,
+    ) -> AppResult<Message> {
+        // 🔐 STEP 1: Only the sender may edit, within the configured window
+        let not_found = || AppError::NotFound { resource: format!("message {}", message_id) };
+        let message = self.supabase_client.get_message(message_id, access_token).await?
+            .ok_or_else(not_found)?;
+        message.ensure_editable_by(editor_id, edit_window_seconds, Utc::now())?;
+        
+        // 🔐 STEP 2: Re-encrypt under the conversation's session key, for the same message
+        let context = message_context(&message);
+        let session_key = self.get_or_create_session_key(message.conversation_id, editor_id, access_token).await?;
+        let encrypted_message = self.encryption_service.encrypt_message(plaintext_content, &session_key, &context)?;
+        let signature = self.sign_message(&context, plaintext_content)?;
+        
+        // 🔐 STEP 3: Store it; the previous revision is kept in message_edits
+        let update = MessageContentUpdate {
+            encrypted_content: encrypted_message.encrypted_content,
+            content_hash: encrypted_message.content_hash,
+            nonce: Some(encrypted_message.nonce),
+            session_key_id: Some(encrypted_message.session_key_id),
+            session_key_generation: Some(encrypted_message.session_key_generation as i32),
+            encryption_version: Some(encrypted_message.encryption_version as i32),
+            signature: Some(signature),
+        };
+        self.supabase_client.update_message_content(message_id, editor_id, &update, access_token).await?
+            .ok_or_else(not_found)
+    }
+
+    /// Re-encrypt the user's own older-format messages in a 1:1 conversation
+    /// Version 1 messages share their session key's nonce; this moves them to the
+    /// current format. The plaintext is unchanged, so no edit revision is recorded.
+    /// Returns how many messages were migrated.
+    pub async fn migrate_legacy_messages(
+        &mut self,
+        user_id: Uuid,
+        other_user_id: Uuid,
+        limit: i64,
+        access_token: &str,
+    ) -> AppResult<usize> {
+        let messages = self.supabase_client.get_conversation(user_id, other_user_id, limit, access_token).await?;
+        let mut migrated = 0;
+        
+        for message in messages {
+            // 🔐 Only the sender can rewrite a message's ciphertext
+            if message.sender_id != user_id || message.deleted_at.is_some() {
+                continue;
+            }
+            let encrypted_message = self.convert_to_encrypted_message(&message)?;
+            if !self.encryption_service.needs_reencryption(&encrypted_message) {
+                continue;
+            }
+            let Some(session_key) = self.get_session_key_for_message(user_id, &message, access_token).await? else {
+                log::warn!("No session key found to migrate message {}", message.id);
+                continue;
+            };
+            
+            let context = message_context(&message);
+            let reencrypted = self.encryption_service.reencrypt_message(&encrypted_message, &session_key, &context)?;
+            let update = MessageContentUpdate {
+                encrypted_content: reencrypted.encrypted_content,
+                content_hash: reencrypted.content_hash,
+                nonce: Some(reencrypted.nonce),
+                session_key_id: Some(reencrypted.session_key_id),
+                session_key_generation: Some(reencrypted.session_key_generation as i32),
+                encryption_version: Some(reencrypted.encryption_version as i32),
+                // Same plaintext and ids, so the existing signature still holds
+                signature: message.signature.clone().zip(message.signature_key_version)
+                    .map(|(signature, key_version)| MessageSignature { signature, key_version }),
+            };
+            if self.supabase_client.update_message_content(message.id, user_id, &update, access_token).await?.is_some() {
+                migrated += 1;
+            }
+        }
+        
+        Ok(migrated)
+    }
+
     /// Receive and decrypt messages
     pub async fn receive_encrypted_messages(
-        &self,
+        &mut self,
         user_id: Uuid,
         other_user_id: Uuid,
         limit: i64,
@@ -83,12 +203,18 @@ impl EncryptedMessagingService {
         
         for encrypted_msg in encrypted_messages {
             // Get session key for this message
-            if let Some(session_key) = self.get_session_key_for_message(&encrypted_msg).await? {
-                // Decrypt the message content
-                match self.encryption_service.decrypt_message(&self.convert_to_encrypted_message(&encrypted_msg)?, &session_key) {
+            if let Some(session_key) = self.get_session_key_for_message(user_id, &encrypted_msg, access_token).await? {
+                // Decrypt the message content; fails if the row was moved or tampered with
+                let context = message_context(&encrypted_msg);
+                match self.encryption_service.decrypt_message(&self.convert_to_encrypted_message(&encrypted_msg)?, &session_key, &context) {
                     Ok(decrypted_content) => {
+                        let integrity = self.verify_message_integrity(&encrypted_msg, &decrypted_content, access_token).await?;
+                        if integrity == MessageIntegrity::Invalid {
+                            log::warn!("Message {} failed integrity verification", encrypted_msg.id);
+                        }
                         decrypted_messages.push(DecryptedMessage {
                             id: encrypted_msg.id,
+                            conversation_id: encrypted_msg.conversation_id,
                             sender_id: encrypted_msg.sender_id,
                             receiver_id: encrypted_msg.receiver_id,
                             content: decrypted_content,
@@ -99,6 +225,7 @@ impl EncryptedMessagingService {
                             mime_type: encrypted_msg.mime_type.clone(),
                             created_at: encrypted_msg.created_at,
                             updated_at: encrypted_msg.updated_at,
+                            integrity,
                         });
                     }
                     Err(e) => {
@@ -114,128 +241,224 @@ impl EncryptedMessagingService {
         Ok(decrypted_messages)
     }
 
-    /// Get or create a session key for a conversation
+    /// Get the conversation's current session key, rotating it first if it's due
     async fn get_or_create_session_key(
         &mut self,
-        user1_id: Uuid,
-        user2_id: Uuid,
+        conversation_id: Uuid,
+        user_id: Uuid,
         access_token: &str,
     ) -> AppResult<SessionKey> {
-        // 🔐 STEP 1: Try to get existing session key
-        if let Some(session_data) = self.supabase_client.get_conversation_session(user1_id, user2_id, access_token).await? {
-            // Parse session data and recreate session key
-            // This is a simplified version - in reality you'd need to decrypt the session key
-            return self.recreate_session_key_from_data(session_data);
+        // 🔐 STEP 1: Keep using the active session until it's due for rotation
+        let latest = self.supabase_client.get_latest_conversation_session(conversation_id, access_token).await?;
+        if let Some(session) = &latest {
+            if session.is_active && !session_needs_rotation(session, Utc::now()) {
+                return self.get_session_key(session.id, user_id, access_token).await?
+                    .ok_or_else(|| AppError::Encryption {
+                        message: format!("Session {} has no key shared with user {}", session.id, user_id),
+                    });
+            }
+            if session.is_active {
+                self.supabase_client.deactivate_conversation_session(session.id, access_token).await?;
+            }
         }
         
-        // 🔐 STEP 2: Create new session key
-        let session_key = self.encryption_service.generate_session_key()?;
+        // 🔐 STEP 2: Create the next generation's key
+        let generation = latest.map_or(1, |session| session.generation + 1);
+        let session_key = self.encryption_service.generate_session_key(generation as u32)?;
         
-        // 🔐 STEP 3: Get both users' public keys
-        let user1_key = self.get_user_public_key(user1_id, access_token).await?;
-        let user2_key = self.get_user_public_key(user2_id, access_token).await?;
-        
-        // 🔐 STEP 4: Encrypt session key for both users
-        let encrypted_for_user1 = self.encryption_service.encrypt_session_key(&session_key, &user1_key)?;
-        let encrypted_for_user2 = self.encryption_service.encrypt_session_key(&session_key, &user2_key)?;
+        // 🔐 STEP 3: Wrap it for each current participant with their latest public key
+        let participants = self.supabase_client.get_conversation_participants(conversation_id, access_token).await?;
+        let mut shares = Vec::new();
+        for participant_id in participants {
+            let (public_key, algorithm, key_version) = self.get_user_public_key(participant_id, access_token).await?;
+            shares.push(SessionKeyShare {
+                session_id: session_key.session_id,
+                user_id: participant_id,
+                encrypted_session_key: self.encryption_service.encrypt_session_key(&session_key, &public_key, algorithm)?,
+                key_version,
+                created_at: Utc::now(),
+            });
+        }
         
-        // 🔐 STEP 5: Store session key in database
+        // 🔐 STEP 4: Store the session under the key's ID, then the wrapped copies
         let session_data = serde_json::json!({
-            "user1_id": user1_id,
-            "user2_id": user2_id,
-            "encrypted_session_key": encrypted_for_user1, // Store for user1
-            "session_key_hash": self.encryption_service.generate_content_hash(&encrypted_for_user1),
+            "id": session_key.session_id,
+            "conversation_id": conversation_id,
+            "generation": generation,
+            "session_key_hash": self.encryption_service.session_key_hash(&session_key),
             "is_active": true,
-            "created_at": Utc::now(),
+            "created_at": session_key.created_at,
             "last_used": Utc::now()
         });
         
         self.supabase_client.store_conversation_session(&session_data, access_token).await?;
+        self.supabase_client.store_session_key_shares(&shares, access_token).await?;
         
-        // 🔐 STEP 6: Store session key in memory for quick access
+        // 🔐 STEP 5: Store session key in memory for quick access
         self.encryption_service.store_session_key(session_key.clone());
         
         Ok(session_key)
     }
 
-    /// Get session key for a specific message
-    async fn get_session_key_for_message(&self, message: &Message) -> AppResult<Option<SessionKey>> {
+    /// Get the session key a message was encrypted with, whichever generation that was
+    async fn get_session_key_for_message(
+        &mut self,
+        user_id: Uuid,
+        message: &Message,
+        access_token: &str,
+    ) -> AppResult<Option<SessionKey>> {
+        let session_key_id = message.session_key_id.ok_or_else(|| not_encrypted(message))?;
+        let Some(session_key) = self.get_session_key(session_key_id, user_id, access_token).await? else {
+            return Ok(None);
+        };
+        
+        if session_key.generation != message.session_key_generation as u32 {
+            return Err(AppError::Encryption {
+                message: format!(
+                    "Message {} claims session key generation {}, but {} is generation {}",
+                    message.id, message.session_key_generation, session_key.session_id, session_key.generation
+                ),
+            });
+        }
+        Ok(Some(session_key))
+    }
+
+    /// Get a session key from memory, or restore it from the user's stored share
+    /// Returns None if the session doesn't exist or was never shared with the user.
+    async fn get_session_key(
+        &mut self,
+        session_id: Uuid,
+        user_id: Uuid,
+        access_token: &str,
+    ) -> AppResult<Option<SessionKey>> {
         // Try to get from memory first
-        if let Some(session_key) = self.encryption_service.get_session_key(message.session_key_id) {
+        if let Some(session_key) = self.encryption_service.get_session_key(session_id) {
             return Ok(Some(session_key.clone()));
         }
         
-        // If not in memory, try to get from database
-        // This would require implementing session key retrieval from the database
-        // For now, return None
-        Ok(None)
-    }
-
-    /// Get user's public key from database
-    async fn get_user_public_key(&self, user_id: Uuid, access_token: &str) -> AppResult<rsa::RsaPublicKey> {
-        if let Some(key_data) = self.supabase_client.get_encryption_key(user_id, access_token).await? {
-            if let Some(public_key_pem) = key_data.get("public_key").and_then(|v| v.as_str()) {
-                return self.encryption_service.import_public_key_pem(public_key_pem)
-                    .map_err(|e| crate::errors::AppError::Internal { message: format!("Failed to import public key: {}", e) });
-            }
+        // 🔐 STEP 1: Fetch the session and the user's wrapped copy of its key
+        let Some(session) = self.supabase_client.get_conversation_session_by_id(session_id, access_token).await? else {
+            return Ok(None);
+        };
+        let Some(share) = self.supabase_client.get_session_key_share(session_id, user_id, access_token).await? else {
+            return Ok(None);
+        };
+        
+        // 🔐 STEP 2: Unwrap it with the key pair it was wrapped for
+        let key_pair = self.key_pairs.get(&(user_id, share.key_version as u32))
+            .ok_or_else(|| AppError::Encryption {
+                message: format!("Key version {} of user {} is not unlocked", share.key_version, user_id),
+            })?;
+        let key = self.encryption_service.decrypt_session_key(&share.encrypted_session_key, &key_pair.private_key, key_pair.algorithm)?;
+        let session_key = SessionKey {
+            key,
+            session_id,
+            generation: session.generation as u32,
+            created_at: session.created_at,
+        };
+        
+        // 🔐 STEP 3: Never trust an unwrapped key that doesn't match the session
+        if self.encryption_service.session_key_hash(&session_key) != session.session_key_hash {
+            return Err(EncryptionError::HashVerificationFailed(
+                format!("Session key {} does not match its stored hash", session_id),
+            ).into());
         }
         
-        Err(crate::errors::AppError::Internal { 
-            message: format!("No public key found for user {}", user_id) 
-        })
+        self.encryption_service.store_session_key(session_key.clone());
+        Ok(Some(session_key))
     }
 
-    /// Recreate session key from stored data
-    fn recreate_session_key_from_data(&self, session_data: Value) -> AppResult<SessionKey> {
-        // This is a simplified implementation
-        // In reality, you'd need to decrypt the session key using the user's private key
-        Err(crate::errors::AppError::Internal { 
-            message: "Session key recreation not implemented".to_string() 
-        })
+    /// Get user's latest public key, with the wrapping scheme its `algorithm` selects and its version
+    async fn get_user_public_key(&self, user_id: Uuid, access_token: &str) -> AppResult<(rsa::RsaPublicKey, KeyWrapAlgorithm, i32)> {
+        let key = self.supabase_client.get_public_key(user_id, access_token).await?
+            .ok_or_else(|| AppError::Internal { message: format!("No public key found for user {}", user_id) })?;
+        
+        let public_key = self.encryption_service.import_public_key_pem(&key.public_key)
+            .map_err(|e| AppError::Internal { message: format!("Failed to import public key: {}", e) })?;
+        let algorithm = key.algorithm.parse::<KeyWrapAlgorithm>()?;
+        
+        Ok((public_key, algorithm, key.key_version))
     }
 
     /// Convert database Message to EncryptedMessage
     fn convert_to_encrypted_message(&self, message: &Message) -> AppResult<EncryptedMessage> {
+        let (Some(nonce), Some(session_key_id)) = (&message.nonce, message.session_key_id) else {
+            return Err(not_encrypted(message));
+        };
         Ok(EncryptedMessage {
             encrypted_content: message.encrypted_content.clone(),
             encrypted_session_key: String::new(), // Not stored in database
             content_hash: message.content_hash.clone(),
             encryption_version: message.encryption_version as u32,
-            nonce: message.nonce.clone(),
-            session_key_id: message.session_key_id,
+            nonce: nonce.clone(),
+            session_key_id,
+            session_key_generation: message.session_key_generation as u32,
         })
     }
 
-    /// Generate encryption keys for a user
-    pub async fn generate_user_keys(&mut self, user_id: Uuid, access_token: &str) -> AppResult<()> {
-        // 🔐 STEP 1: Generate new key pair
-        let key_pair = self.encryption_service.generate_key_pair()?;
-        
-        // 🔐 STEP 2: Export public key as PEM
-        let public_key_pem = self.encryption_service.export_public_key_pem(&key_pair.public_key)?;
-        
-        // 🔐 STEP 3: Encrypt private key (in real implementation, use user's password)
-        let encrypted_private_key = "TODO: Encrypt with user password"; // Placeholder
-        
-        // 🔐 STEP 4: Store in database
-        let key_data = serde_json::json!({
-            "user_id": user_id,
-            "encrypted_private_key": encrypted_private_key,
-            "public_key": public_key_pem,
-            "key_version": key_pair.version,
-            "algorithm": "RSA-2048",
-            "created_at": Utc::now()
-        });
+    /// Verify message integrity
+    /// The content hash only shows the content is intact; the signature shows who
+    /// wrote it. It's checked against whichever of the sender's key pairs made it,
+    /// so messages signed before a rotation still verify.
+    pub async fn verify_message_integrity(&mut self, message: &Message, decrypted_content: &str, access_token: &str) -> AppResult<MessageIntegrity> {
+        let expected_hash = self.encryption_service.generate_content_hash(decrypted_content);
+        if expected_hash != message.content_hash {
+            return Ok(MessageIntegrity::Invalid);
+        }
+        let (Some(signature), Some(key_version)) = (&message.signature, message.signature_key_version) else {
+            return Ok(MessageIntegrity::Unsigned);
+        };
         
-        self.supabase_client.store_encryption_key(user_id, &key_data, access_token).await?;
+        let Some(public_key) = self.get_sender_public_key(message.sender_id, key_version, access_token).await? else {
+            log::warn!("Message {} is signed with unknown key version {} of user {}", message.id, key_version, message.sender_id);
+            return Ok(MessageIntegrity::Invalid);
+        };
+        let context = message_context(message);
         
-        Ok(())
+        match self.encryption_service.verify_message_signature(&public_key, &context, decrypted_content, signature) {
+            Ok(()) => Ok(MessageIntegrity::Verified),
+            Err(_) => Ok(MessageIntegrity::Invalid),
+        }
     }
 
-    /// Verify message integrity
-    pub fn verify_message_integrity(&self, message: &Message, decrypted_content: &str) -> bool {
-        let expected_hash = self.encryption_service.generate_content_hash(decrypted_content);
-        expected_hash == message.content_hash
+    /// Get a specific version of a user's public key, current or rotated out
+    async fn get_sender_public_key(&mut self, user_id: Uuid, key_version: i32, access_token: &str) -> AppResult<Option<rsa::RsaPublicKey>> {
+        if let Some(public_key) = self.public_keys.get(&(user_id, key_version)) {
+            return Ok(Some(public_key.clone()));
+        }
+        let Some(key) = self.supabase_client.get_public_key_version(user_id, key_version, access_token).await? else {
+            return Ok(None);
+        };
+        
+        let public_key = self.encryption_service.import_public_key_pem(&key.public_key)?;
+        self.public_keys.insert((user_id, key_version), public_key.clone());
+        Ok(Some(public_key))
+    }
+}
+
+// 🔄 SESSION KEY ROTATION
+
+/// A session key is replaced once it's this old...
+pub const SESSION_KEY_MAX_AGE_DAYS: i64 = 7;
+
+/// ...or has encrypted this many messages, whichever comes first.
+/// Membership changes retire it straight away (see the schema's
+/// `retire_session_on_membership_change` trigger).
+pub const SESSION_KEY_MAX_MESSAGES: i32 = 1000;
+
+/// Whether an active session key is due to be rotated
+fn session_needs_rotation(session: &ConversationSession, now: DateTime<Utc>) -> bool {
+    now - session.created_at >= Duration::days(SESSION_KEY_MAX_AGE_DAYS)
+        || session.message_count >= SESSION_KEY_MAX_MESSAGES
+}
+
+/// The context a stored message was encrypted in
+/// 1:1 messages derive it from the participants, never from the row's own
+/// `conversation_id`; group messages have no pair to derive it from, so they use it
+fn message_context(message: &Message) -> MessageContext {
+    match message.receiver_id {
+        Some(receiver_id) => MessageContext::direct(message.sender_id, receiver_id, message.id),
+        None => MessageContext::group(message.conversation_id, message.sender_id, message.id),
     }
 }
 
@@ -245,8 +468,9 @@ impl EncryptedMessagingService {
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct DecryptedMessage {
     pub id: Uuid,
+    pub conversation_id: Uuid,
     pub sender_id: Uuid,
-    pub receiver_id: Uuid,
+    pub receiver_id: Option<Uuid>,
     pub content: String,                    // Decrypted content
     pub message_type: crate::database::MessageType,
     pub is_read: bool,
@@ -255,6 +479,16 @@ pub struct DecryptedMessage {
     pub mime_type: Option<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub integrity: MessageIntegrity,        // Whether the sender's signature checked out
+}
+
+/// Outcome of `verify_message_integrity`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageIntegrity {
+    Verified,    // Content intact and signed by the sender
+    Unsigned,    // Content intact, but sent before messages were signed: proves nothing about the sender
+    Invalid,     // Content hash or signature doesn't match
 }
 
 // 🔧 UTILITY FUNCTIONS
@@ -264,6 +498,11 @@ pub fn create_conversation_id(user1_id: Uuid, user2_id: Uuid) -> Uuid {
     crate::encryption::create_conversation_id(user1_id, user2_id)
 }
 
+// Stored as sent (`UNENCRYPTED_VERSION`), so there is nothing to decrypt
+fn not_encrypted(message: &Message) -> AppError {
+    AppError::Encryption { message: format!("Message {} was not encrypted with a session key", message.id) }
+}
+
 /// Validate encrypted message format
 pub fn validate_encrypted_message(message: &Message) -> AppResult<()> {
     if message.encrypted_content.is_empty() {
@@ -290,7 +529,7 @@ pub fn validate_encrypted_message(message: &Message) -> AppResult<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::database::MessageType;
+    use crate::database::{MessageStatus, MessageType};
 
     #[tokio::test]
     async fn test_encrypted_messaging_workflow() {
@@ -306,19 +545,53 @@ mod tests {
         assert_eq!(conv_id1, conv_id2);
     }
 
+    #[test]
+    fn test_session_rotation_by_age_and_message_count() {
+        let now = Utc::now();
+        let session = ConversationSession {
+            id: Uuid::new_v4(),
+            conversation_id: Uuid::new_v4(),
+            generation: 1,
+            session_key_hash: String::new(),
+            is_active: true,
+            message_count: 0,
+            created_at: now - Duration::days(1),
+            last_used: now,
+        };
+        assert!(!session_needs_rotation(&session, now));
+        
+        let old = ConversationSession { created_at: now - Duration::days(SESSION_KEY_MAX_AGE_DAYS), ..session.clone() };
+        assert!(session_needs_rotation(&old, now));
+        
+        let busy = ConversationSession { message_count: SESSION_KEY_MAX_MESSAGES, ..session };
+        assert!(session_needs_rotation(&busy, now));
+    }
+
     #[test]
     fn test_message_validation() {
         let valid_message = Message {
             id: Uuid::new_v4(),
+            conversation_id: Uuid::new_v4(),
             sender_id: Uuid::new_v4(),
-            receiver_id: Uuid::new_v4(),
+            reply_to_id: None,
+            receiver_id: Some(Uuid::new_v4()),
             encrypted_content: "encrypted_content".to_string(),
             content_hash: "hash".to_string(),
             encryption_version: 1,
-            nonce: "nonce".to_string(),
-            session_key_id: Uuid::new_v4(),
+            nonce: Some("nonce".to_string()),
+            session_key_id: Some(Uuid::new_v4()),
+            session_key_generation: 1,
+            signature: None,
+            signature_key_version: None,
             message_type: MessageType::Text,
             is_read: false,
+            status: MessageStatus::Sent,
+            delivered_at: None,
+            read_at: None,
+            client_msg_id: None,
+            edited_at: None,
+            deleted_at: None,
+            deleted_by: None,
             file_url: None,
             file_size: None,
             mime_type: None,
@@ -327,5 +600,13 @@ mod tests {
         };
         
         assert!(validate_encrypted_message(&valid_message).is_ok());
+        
+        let direct = message_context(&valid_message);
+        assert_eq!(direct.conversation_id, create_conversation_id(valid_message.sender_id, valid_message.receiver_id.unwrap()));
+        
+        let group_message = Message { receiver_id: None, ..valid_message };
+        let group = message_context(&group_message);
+        assert_eq!(group.conversation_id, group_message.conversation_id);
+        assert_eq!(group.message_id, group_message.id);
     }
 } 
\ No newline at end of file
@@ -3,18 +3,28 @@
 // Following Zero Trust principles: never trust, always verify
 
 use aes_gcm::{
-    aead::{Aead, KeyInit, OsRng},
+    aead::{Aead, KeyInit, OsRng, Payload},
     Aes256Gcm, Key, Nonce,
 };
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::{Engine as _, engine::general_purpose};
 use rsa::{
-    pkcs8::{EncodePublicKey, DecodePublicKey, LineEnding},
-    RsaPrivateKey, RsaPublicKey,
+    pkcs8::{EncodePrivateKey, DecodePrivateKey, EncodePublicKey, DecodePublicKey, LineEnding},
+    pss::{BlindedSigningKey, Signature, VerifyingKey},
+    signature::{RandomizedSigner, SignatureEncoding, Verifier},
+    Oaep, Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey,
 };
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use uuid::Uuid;
 use rand::Rng;
+use zeroize::Zeroizing;
+
+use crate::database::MessageEncryption;
+
+pub mod x3dh;     // Prekey bundles and X3DH key agreement for 1:1 sessions
+#[cfg(test)]      // Client-side only; the server relays ratchet messages without reading them
+pub mod ratchet;  // Double Ratchet per-message keys on top of X3DH
 
 // 🔑 ENCRYPTION TYPES AND STRUCTURES
 
@@ -25,6 +35,129 @@ pub struct EncryptionKeyPair {
     pub public_key: RsaPublicKey,
     pub key_id: Uuid,
     pub version: u32,
+    pub algorithm: KeyWrapAlgorithm,
+}
+
+/// How session keys are wrapped for a key pair, named as in `encryption_keys.algorithm`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyWrapAlgorithm {
+    /// RSA with PKCS#1 v1.5 padding, which is padding-oracle prone. Keys already
+    /// wrapped this way can be unwrapped during the migration window; nothing new is.
+    RsaPkcs1v15,
+    /// RSA-OAEP with SHA-256 (and MGF1-SHA-256)
+    RsaOaepSha256,
+}
+
+impl KeyWrapAlgorithm {
+    /// Used for every newly generated key pair
+    pub const CURRENT: Self = Self::RsaOaepSha256;
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::RsaPkcs1v15 => "RSA-2048",
+            Self::RsaOaepSha256 => "RSA-OAEP-SHA256",
+        }
+    }
+}
+
+impl std::str::FromStr for KeyWrapAlgorithm {
+    type Err = EncryptionError;
+
+    fn from_str(algorithm: &str) -> Result<Self, Self::Err> {
+        match algorithm {
+            "RSA-2048" => Ok(Self::RsaPkcs1v15),
+            "RSA-OAEP-SHA256" => Ok(Self::RsaOaepSha256),
+            other => Err(EncryptionError::InvalidKeyFormat(format!("Unsupported key algorithm {}", other))),
+        }
+    }
+}
+
+/// How a private key was sealed, stored as `encryption_keys.kdf_params`
+/// The passphrase goes through Argon2id with these settings to give the AES-256-GCM
+/// key for `encrypted_private_key`. Nothing here is secret.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct KdfParams {
+    pub algorithm: String,       // Always "argon2id"
+    pub version: u32,            // Argon2 version, 0x13
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub salt: String,            // Base64, 16 random bytes per sealing
+}
+
+/// Argon2id cost for newly sealed keys (64 MiB, 3 passes)
+pub const PRIVATE_KEY_KDF_MEMORY_KIB: u32 = 64 * 1024;
+pub const PRIVATE_KEY_KDF_ITERATIONS: u32 = 3;
+pub const PRIVATE_KEY_KDF_PARALLELISM: u32 = 1;
+
+/// Stored parameters above this are refused, so a tampered row can't make unlocking exhaust memory
+const MAX_KDF_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_KDF_ITERATIONS: u32 = 64;
+
+/// Uploaded keys sealed with less than this are refused (OWASP's Argon2id minimum)
+const MIN_KDF_MEMORY_KIB: u32 = 19 * 1024;
+const MIN_KDF_ITERATIONS: u32 = 2;
+
+const KDF_ALGORITHM: &str = "argon2id";
+const PRIVATE_KEY_AAD_LABEL: &[u8] = b"ochat-private-key";
+
+impl KdfParams {
+    /// Current cost settings with a fresh salt; use new params every time a key is sealed
+    pub fn generate() -> Self {
+        let mut salt = [0u8; 16];
+        OsRng.fill(&mut salt);
+
+        Self {
+            algorithm: KDF_ALGORITHM.to_string(),
+            version: Version::V0x13 as u32,
+            memory_kib: PRIVATE_KEY_KDF_MEMORY_KIB,
+            iterations: PRIVATE_KEY_KDF_ITERATIONS,
+            parallelism: PRIVATE_KEY_KDF_PARALLELISM,
+            salt: general_purpose::STANDARD.encode(salt),
+        }
+    }
+
+    /// Check parameters a client sealed a new key with, before the server stores them
+    /// Stricter than unlocking: the cost must be at least the minimum and the salt fresh-sized.
+    pub fn validate(&self) -> Result<(), EncryptionError> {
+        let salt = self.decode_salt()?;
+        if self.memory_kib < MIN_KDF_MEMORY_KIB || self.iterations < MIN_KDF_ITERATIONS || self.parallelism == 0 {
+            return Err(EncryptionError::InvalidKeyFormat("Key derivation cost is too low".to_string()));
+        }
+        if salt.len() != 16 {
+            return Err(EncryptionError::InvalidKeyFormat("Salt must be 16 bytes".to_string()));
+        }
+        Ok(())
+    }
+
+    /// The salt, once the algorithm and cost are known to be supported
+    fn decode_salt(&self) -> Result<Vec<u8>, EncryptionError> {
+        if self.algorithm != KDF_ALGORITHM || self.version != Version::V0x13 as u32 {
+            return Err(EncryptionError::InvalidKeyFormat(format!(
+                "Unsupported key derivation {} version {}",
+                self.algorithm, self.version
+            )));
+        }
+        if self.memory_kib > MAX_KDF_MEMORY_KIB || self.iterations > MAX_KDF_ITERATIONS {
+            return Err(EncryptionError::InvalidKeyFormat("Key derivation cost is out of range".to_string()));
+        }
+        general_purpose::STANDARD
+            .decode(&self.salt)
+            .map_err(|e| EncryptionError::InvalidKeyFormat(format!("Invalid salt: {}", e)))
+    }
+
+    /// Run Argon2id over the passphrase to get the sealing key
+    fn derive_key(&self, passphrase: &str) -> Result<Zeroizing<[u8; 32]>, EncryptionError> {
+        let salt = self.decode_salt()?;
+
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|e| EncryptionError::InvalidKeyFormat(e.to_string()))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| EncryptionError::KeyGenerationFailed(e.to_string()))?;
+        Ok(key)
+    }
 }
 
 /// Represents an encrypted message with all necessary metadata
@@ -36,17 +169,95 @@ pub struct EncryptedMessage {
     pub encryption_version: u32,          // Version for future upgrades
     pub nonce: String,                    // Base64 encoded AES nonce
     pub session_key_id: Uuid,             // ID of the session key used
+    pub session_key_generation: u32,      // Which rotation of the conversation's key that is
+}
+
+/// Message encryption format versions (`messages.encryption_version`)
+/// - 0: stored as the client sent it, with no session key or nonce; the server
+///   never decrypts these
+/// - 1: every message under a session key shared the key's nonce (still recorded
+///   in `messages.nonce`). Reusing a nonce breaks AES-GCM, so version 1 is only
+///   ever decrypted, never written.
+/// - 2: a fresh random 96-bit nonce per message, stored in `messages.nonce`
+/// - 3: as 2, plus AAD binding the ciphertext to its `MessageContext`, so it
+///   can't be moved to another message or conversation without failing to decrypt
+pub const UNENCRYPTED_VERSION: u32 = 0;
+pub const LEGACY_SHARED_NONCE_VERSION: u32 = 1;
+pub const UNBOUND_NONCE_VERSION: u32 = 2;
+pub const ENCRYPTION_VERSION: u32 = 3;
+
+const MESSAGE_AAD_LABEL: &[u8] = b"ochat-message";
+const MESSAGE_SIGNATURE_LABEL: &[u8] = b"ochat-message-signature";
+
+/// Where a message ciphertext belongs; authenticated (not encrypted) as AAD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageContext {
+    pub conversation_id: Uuid,   // `create_conversation_id` of a 1:1 pair, or the group's id
+    pub sender_id: Uuid,
+    pub message_id: Uuid,        // Chosen by the sender before encrypting
+}
+
+impl MessageContext {
+    /// Context of a message in a 1:1 conversation
+    pub fn direct(sender_id: Uuid, receiver_id: Uuid, message_id: Uuid) -> Self {
+        Self {
+            conversation_id: create_conversation_id(sender_id, receiver_id),
+            sender_id,
+            message_id,
+        }
+    }
+
+    /// Context of a message in a group conversation
+    pub fn group(conversation_id: Uuid, sender_id: Uuid, message_id: Uuid) -> Self {
+        Self { conversation_id, sender_id, message_id }
+    }
+
+    /// label || version (u32 BE) || conversation id || sender id || message id
+    fn aad(&self, encryption_version: u32) -> Vec<u8> {
+        let mut aad = Vec::with_capacity(MESSAGE_AAD_LABEL.len() + 4 + 3 * 16);
+        aad.extend_from_slice(MESSAGE_AAD_LABEL);
+        aad.extend_from_slice(&encryption_version.to_be_bytes());
+        aad.extend_from_slice(self.conversation_id.as_bytes());
+        aad.extend_from_slice(self.sender_id.as_bytes());
+        aad.extend_from_slice(self.message_id.as_bytes());
+        aad
+    }
+
+    /// label || conversation id || sender id || message id || SHA-256(plaintext)
+    fn signed_payload(&self, plaintext_hash: &[u8]) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(MESSAGE_SIGNATURE_LABEL.len() + 3 * 16 + 32);
+        payload.extend_from_slice(MESSAGE_SIGNATURE_LABEL);
+        payload.extend_from_slice(self.conversation_id.as_bytes());
+        payload.extend_from_slice(self.sender_id.as_bytes());
+        payload.extend_from_slice(self.message_id.as_bytes());
+        payload.extend_from_slice(plaintext_hash);
+        payload
+    }
 }
 
 /// Represents a session key for a conversation
 #[derive(Debug, Clone)]
 pub struct SessionKey {
     pub key: [u8; 32],           // 256-bit AES key
-    pub nonce: [u8; 12],         // 96-bit nonce
     pub session_id: Uuid,
+    pub generation: u32,         // `conversation_sessions.generation`; bumped on every rotation
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// Two users' safety number, identical on both sides
+/// Each user's half is a fingerprint of their user ID and current public key;
+/// if either key changes (rotation, or a server swapping keys), so does the number.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct SafetyNumber {
+    pub numeric: String,         // 60 digits in 12 groups of 5, for reading aloud
+    pub qr_payload: String,      // "ochat-safety:<version>:<fingerprint>:<fingerprint>", hex
+}
+
+pub const SAFETY_NUMBER_VERSION: u16 = 1;
+
+// Iterated so that finding a key with a chosen fingerprint is expensive
+const FINGERPRINT_ITERATIONS: u32 = 5200;
+
 /// Error types for encryption operations
 #[derive(Debug, thiserror::Error)]
 pub enum EncryptionError {
@@ -89,7 +300,8 @@ impl EncryptionService {
     }
 
     /// Generate a new RSA key pair for a user
-    pub fn generate_key_pair(&self) -> Result<EncryptionKeyPair, EncryptionError> {
+    /// `version` is its `encryption_keys.key_version`: 1, then one more per rotation.
+    pub fn generate_key_pair(&self, version: u32) -> Result<EncryptionKeyPair, EncryptionError> {
         // Generate a new RSA private key (2048 bits for security)
         let private_key = RsaPrivateKey::new(&mut OsRng, 2048)
             .map_err(|e| EncryptionError::KeyGenerationFailed(e.to_string()))?;
@@ -101,43 +313,43 @@ impl EncryptionService {
             private_key,
             public_key,
             key_id: Uuid::new_v4(),
-            version: 1,
+            version,
+            algorithm: KeyWrapAlgorithm::CURRENT,
         })
     }
 
     /// Generate a session key for a conversation
-    pub fn generate_session_key(&self) -> Result<SessionKey, EncryptionError> {
+    /// `generation` counts the conversation's session keys, starting at 1.
+    pub fn generate_session_key(&self, generation: u32) -> Result<SessionKey, EncryptionError> {
         // Generate a random 256-bit AES key
         let mut key = [0u8; 32];
         OsRng.fill(&mut key);
         
-        // Generate a random 96-bit nonce
-        let mut nonce = [0u8; 12];
-        OsRng.fill(&mut nonce);
-        
         Ok(SessionKey {
             key,
-            nonce,
             session_id: Uuid::new_v4(),
+            generation,
             created_at: chrono::Utc::now(),
         })
     }
 
-    /// Encrypt a message using AES-GCM with a session key
+    /// Encrypt a message using AES-GCM with a session key, bound to its context
     pub fn encrypt_message(
         &self,
         content: &str,
         session_key: &SessionKey,
+        context: &MessageContext,
     ) -> Result<EncryptedMessage, EncryptionError> {
         // Create AES-GCM cipher
         let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&session_key.key));
         
-        // Create nonce from session key nonce
-        let nonce = Nonce::from_slice(&session_key.nonce);
+        // Fresh nonce for every message: GCM must never see a (key, nonce) pair twice
+        let nonce = generate_nonce();
         
         // Encrypt the content
+        let aad = context.aad(ENCRYPTION_VERSION);
         let encrypted_content = cipher
-            .encrypt(nonce, content.as_bytes())
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: content.as_bytes(), aad: &aad })
             .map_err(|e| EncryptionError::EncryptionFailed(e.to_string()))?;
         
         // Generate hash of original content for integrity verification
@@ -147,17 +359,20 @@ impl EncryptionService {
             encrypted_content: general_purpose::STANDARD.encode(&encrypted_content),
             encrypted_session_key: String::new(), // Will be set by caller
             content_hash,
-            encryption_version: 1,
-            nonce: general_purpose::STANDARD.encode(&session_key.nonce),
+            encryption_version: ENCRYPTION_VERSION,
+            nonce: general_purpose::STANDARD.encode(nonce),
             session_key_id: session_key.session_id,
+            session_key_generation: session_key.generation,
         })
     }
 
     /// Decrypt a message using AES-GCM with a session key
+    /// From version 3 on this fails unless `context` is exactly what it was encrypted with.
     pub fn decrypt_message(
         &self,
         encrypted_message: &EncryptedMessage,
         session_key: &SessionKey,
+        context: &MessageContext,
     ) -> Result<String, EncryptionError> {
         // Create AES-GCM cipher
         let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&session_key.key));
@@ -167,12 +382,22 @@ impl EncryptionService {
             .decode(&encrypted_message.encrypted_content)
             .map_err(|e| EncryptionError::DecryptionFailed(format!("Invalid base64: {}", e)))?;
         
-        // Create nonce
-        let nonce = Nonce::from_slice(&session_key.nonce);
+        // Where the nonce lives, and whether there's AAD, depends on the format version
+        let nonce = decode_nonce(&encrypted_message.nonce)?;
+        let aad = match encrypted_message.encryption_version {
+            LEGACY_SHARED_NONCE_VERSION | UNBOUND_NONCE_VERSION => Vec::new(),
+            ENCRYPTION_VERSION => context.aad(ENCRYPTION_VERSION),
+            version => {
+                return Err(EncryptionError::InvalidMessageFormat(format!(
+                    "Unsupported encryption version {}",
+                    version
+                )));
+            }
+        };
         
         // Decrypt the content
         let decrypted_content = cipher
-            .decrypt(nonce, encrypted_content.as_ref())
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &encrypted_content, aad: &aad })
             .map_err(|e| EncryptionError::DecryptionFailed(e.to_string()))?;
         
         // Convert to string
@@ -190,29 +415,58 @@ impl EncryptionService {
         Ok(content)
     }
 
+    /// Re-encrypt a message in the current format, e.g. to migrate older ciphertexts
+    /// The plaintext (and so `content_hash`) is unchanged; only the ciphertext and nonce are new.
+    pub fn reencrypt_message(
+        &self,
+        encrypted_message: &EncryptedMessage,
+        session_key: &SessionKey,
+        context: &MessageContext,
+    ) -> Result<EncryptedMessage, EncryptionError> {
+        let content = self.decrypt_message(encrypted_message, session_key, context)?;
+        self.encrypt_message(&content, session_key, context)
+    }
+
+    /// Whether a message was written in an older format and should be re-encrypted
+    pub fn needs_reencryption(&self, encrypted_message: &EncryptedMessage) -> bool {
+        encrypted_message.encryption_version < ENCRYPTION_VERSION
+    }
+
+
     /// Encrypt a session key with a user's public key
+    /// `algorithm` is the recipient key's `encryption_keys.algorithm`.
     pub fn encrypt_session_key(
         &self,
         session_key: &SessionKey,
         public_key: &RsaPublicKey,
+        algorithm: KeyWrapAlgorithm,
     ) -> Result<String, EncryptionError> {
         // Convert session key to bytes
         let session_key_bytes = session_key.key.to_vec();
         
         // Encrypt with RSA public key
-        let encrypted_session_key = public_key
-            .encrypt(&mut OsRng, rsa::Pkcs1v15Encrypt, &session_key_bytes)
-            .map_err(|e| EncryptionError::EncryptionFailed(e.to_string()))?;
+        let encrypted_session_key = match algorithm {
+            KeyWrapAlgorithm::RsaOaepSha256 => public_key
+                .encrypt(&mut OsRng, Oaep::new::<Sha256>(), &session_key_bytes)
+                .map_err(|e| EncryptionError::EncryptionFailed(e.to_string()))?,
+            KeyWrapAlgorithm::RsaPkcs1v15 => {
+                return Err(EncryptionError::EncryptionFailed(
+                    "PKCS#1 v1.5 key wrapping is retired; the recipient needs an RSA-OAEP-SHA256 key".to_string(),
+                ));
+            }
+        };
         
         // Encode as base64
         Ok(general_purpose::STANDARD.encode(&encrypted_session_key))
     }
 
     /// Decrypt a session key with a user's private key
+    /// `algorithm` is the `encryption_keys.algorithm` of that private key.
     pub fn decrypt_session_key(
         &self,
         encrypted_session_key: &str,
         private_key: &RsaPrivateKey,
+        algorithm: KeyWrapAlgorithm,
     ) -> Result<[u8; 32], EncryptionError> {
         // Decode from base64
         let encrypted_bytes = general_purpose::STANDARD
@@ -220,9 +474,11 @@ impl EncryptionService {
             .map_err(|e| EncryptionError::DecryptionFailed(format!("Invalid base64: {}", e)))?;
         
         // Decrypt with RSA private key
-        let decrypted_bytes = private_key
-            .decrypt(rsa::Pkcs1v15Encrypt, &encrypted_bytes)
-            .map_err(|e| EncryptionError::DecryptionFailed(e.to_string()))?;
+        let decrypted_bytes = match algorithm {
+            KeyWrapAlgorithm::RsaOaepSha256 => private_key.decrypt(Oaep::new::<Sha256>(), &encrypted_bytes),
+            KeyWrapAlgorithm::RsaPkcs1v15 => private_key.decrypt(Pkcs1v15Encrypt, &encrypted_bytes),
+        }
+        .map_err(|e| EncryptionError::DecryptionFailed(e.to_string()))?;
         
         // Convert to fixed-size array
         if decrypted_bytes.len() != 32 {
@@ -243,6 +499,56 @@ impl EncryptionService {
         hex::encode(hasher.finalize())
     }
 
+    /// SHA-256 of a session key, stored as `conversation_sessions.session_key_hash`
+    /// so an unwrapped key can be checked before it's trusted
+    pub fn session_key_hash(&self, session_key: &SessionKey) -> String {
+        hex::encode(Sha256::digest(session_key.key))
+    }
+
+    /// Sign a message with the sender's private key (RSA-PSS-SHA256), base64
+    /// Unlike `content_hash`, only the sender can produce this; it covers the
+    /// plaintext and where the message belongs, so it can't be moved either.
+    pub fn sign_message(&self, private_key: &RsaPrivateKey, context: &MessageContext, plaintext: &str) -> Result<String, EncryptionError> {
+        let signing_key = BlindedSigningKey::<Sha256>::new(private_key.clone());
+        let signature = signing_key
+            .try_sign_with_rng(&mut OsRng, &context.signed_payload(&Sha256::digest(plaintext.as_bytes())))
+            .map_err(|e| EncryptionError::EncryptionFailed(format!("Failed to sign message: {}", e)))?;
+        Ok(general_purpose::STANDARD.encode(signature.to_bytes()))
+    }
+
+    /// Check a message signature against the public key of the key pair that made it
+    pub fn verify_message_signature(
+        &self,
+        public_key: &RsaPublicKey,
+        context: &MessageContext,
+        plaintext: &str,
+        signature: &str,
+    ) -> Result<(), EncryptionError> {
+        self.verify_content_signature(public_key, context, &self.generate_content_hash(plaintext), signature)
+    }
+
+    /// Same as `verify_message_signature`, from the message's `content_hash`
+    /// The signature covers the plaintext only through its hash, so whoever holds
+    /// the ciphertext (the server, say) can check it without the session key.
+    pub fn verify_content_signature(
+        &self,
+        public_key: &RsaPublicKey,
+        context: &MessageContext,
+        content_hash: &str,
+        signature: &str,
+    ) -> Result<(), EncryptionError> {
+        let plaintext_hash = decode_content_hash(content_hash)?;
+        let signature = general_purpose::STANDARD
+            .decode(signature)
+            .ok()
+            .and_then(|bytes| Signature::try_from(bytes.as_slice()).ok())
+            .ok_or_else(|| EncryptionError::InvalidMessageFormat("Invalid message signature".to_string()))?;
+
+        VerifyingKey::<Sha256>::new(public_key.clone())
+            .verify(&context.signed_payload(&plaintext_hash), &signature)
+            .map_err(|_| EncryptionError::HashVerificationFailed("Message signature does not verify".to_string()))
+    }
+
     /// Store a session key in memory (in production, use Redis)
     pub fn store_session_key(&mut self, session_key: SessionKey) {
         self.session_keys.insert(session_key.session_id, session_key);
@@ -265,6 +571,431 @@ impl EncryptionService {
         RsaPublicKey::from_public_key_pem(pem)
             .map_err(|e| EncryptionError::InvalidKeyFormat(e.to_string()))
     }
+
+    /// Safety number for two users' public keys, for comparing in person or by QR code
+    /// Both halves are ordered the same way regardless of who asks, so each side
+    /// gets the same digits and payload.
+    pub fn safety_number(
+        &self,
+        user_id: Uuid,
+        public_key: &RsaPublicKey,
+        other_user_id: Uuid,
+        other_public_key: &RsaPublicKey,
+    ) -> Result<SafetyNumber, EncryptionError> {
+        let mut halves = [
+            self.key_fingerprint(user_id, public_key)?,
+            self.key_fingerprint(other_user_id, other_public_key)?,
+        ];
+        halves.sort();
+
+        let digits: String = halves.iter().map(fingerprint_digits).collect();
+        let numeric = digits.as_bytes()
+            .chunks(5)
+            .map(|group| std::str::from_utf8(group).expect("digits are ASCII"))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Ok(SafetyNumber {
+            numeric,
+            qr_payload: format!(
+                "ochat-safety:{}:{}:{}",
+                SAFETY_NUMBER_VERSION, hex::encode(halves[0]), hex::encode(halves[1])
+            ),
+        })
+    }
+
+    /// SHA-256 over version || PEM public key || user ID, re-hashed with the key
+    /// `FINGERPRINT_ITERATIONS` times
+    fn key_fingerprint(&self, user_id: Uuid, public_key: &RsaPublicKey) -> Result<[u8; 32], EncryptionError> {
+        let pem = self.export_public_key_pem(public_key)?;
+        let mut fingerprint: [u8; 32] = Sha256::new()
+            .chain_update(SAFETY_NUMBER_VERSION.to_be_bytes())
+            .chain_update(pem.as_bytes())
+            .chain_update(user_id.as_bytes())
+            .finalize()
+            .into();
+        for _ in 0..FINGERPRINT_ITERATIONS {
+            fingerprint = Sha256::new()
+                .chain_update(fingerprint)
+                .chain_update(pem.as_bytes())
+                .finalize()
+                .into();
+        }
+        Ok(fingerprint)
+    }
+
+    /// Serialize a private key as PKCS#8 DER, e.g. for `seal_private_key`
+    pub fn export_private_key_pkcs8(&self, private_key: &RsaPrivateKey) -> Result<Zeroizing<Vec<u8>>, EncryptionError> {
+        let document = private_key
+            .to_pkcs8_der()
+            .map_err(|e| EncryptionError::InvalidKeyFormat(e.to_string()))?;
+        Ok(Zeroizing::new(document.as_bytes().to_vec()))
+    }
+
+    /// Parse a PKCS#8 DER private key, e.g. from `unseal_private_key`
+    pub fn import_private_key_pkcs8(&self, der: &[u8]) -> Result<RsaPrivateKey, EncryptionError> {
+        RsaPrivateKey::from_pkcs8_der(der)
+            .map_err(|e| EncryptionError::InvalidKeyFormat(e.to_string()))
+    }
+
+    /// Seal a PKCS#8 private key under a passphrase for `encryption_keys.encrypted_private_key`
+    /// Output is base64(nonce || ciphertext || tag). The AAD names the owner and key
+    /// version, so a sealed key can't be passed off as another row's.
+    pub fn seal_private_key(
+        &self,
+        pkcs8: &[u8],
+        passphrase: &str,
+        params: &KdfParams,
+        user_id: Uuid,
+        key_version: u32,
+    ) -> Result<String, EncryptionError> {
+        let key = params.derive_key(passphrase)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_ref()));
+        let nonce = generate_nonce();
+        let aad = private_key_aad(user_id, key_version);
+
+        let sealed = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: pkcs8, aad: &aad })
+            .map_err(|e| EncryptionError::EncryptionFailed(e.to_string()))?;
+
+        let mut output = nonce.to_vec();
+        output.extend_from_slice(&sealed);
+        Ok(general_purpose::STANDARD.encode(output))
+    }
+
+    /// Open a key sealed by `seal_private_key`, giving back its PKCS#8 DER
+    /// A wrong passphrase and a tampered row fail the same way.
+    pub fn unseal_private_key(
+        &self,
+        sealed: &str,
+        passphrase: &str,
+        params: &KdfParams,
+        user_id: Uuid,
+        key_version: u32,
+    ) -> Result<Zeroizing<Vec<u8>>, EncryptionError> {
+        let sealed = decode_sealed_private_key(sealed)?;
+        let key = params.derive_key(passphrase)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_ref()));
+        let (nonce, ciphertext) = sealed.split_at(12);
+        let aad = private_key_aad(user_id, key_version);
+
+        cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+            .map(Zeroizing::new)
+            .map_err(|_| EncryptionError::DecryptionFailed("Wrong passphrase or corrupted private key".to_string()))
+    }
+
+    /// Generate a fresh 256-bit key for encrypting one attachment
+    pub fn generate_file_key(&self) -> [u8; 32] {
+        let mut key = [0u8; 32];
+        OsRng.fill(&mut key);
+        key
+    }
+
+    /// Wrap a file key with the conversation session key
+    /// Output is base64(nonce || ciphertext || tag) with a fresh random nonce.
+    pub fn wrap_file_key(
+        &self,
+        file_key: &[u8; 32],
+        session_key: &SessionKey,
+    ) -> Result<String, EncryptionError> {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&session_key.key));
+        let nonce = generate_nonce();
+
+        let wrapped = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: file_key, aad: FILE_KEY_AAD })
+            .map_err(|e| EncryptionError::EncryptionFailed(e.to_string()))?;
+
+        let mut output = nonce.to_vec();
+        output.extend_from_slice(&wrapped);
+        Ok(general_purpose::STANDARD.encode(output))
+    }
+
+    /// Unwrap a file key produced by `wrap_file_key`
+    pub fn unwrap_file_key(
+        &self,
+        wrapped_file_key: &str,
+        session_key: &SessionKey,
+    ) -> Result<[u8; 32], EncryptionError> {
+        let wrapped = general_purpose::STANDARD
+            .decode(wrapped_file_key)
+            .map_err(|e| EncryptionError::InvalidKeyFormat(format!("Invalid base64: {}", e)))?;
+        if wrapped.len() != WRAPPED_FILE_KEY_LEN {
+            return Err(EncryptionError::InvalidKeyFormat("Invalid wrapped file key length".to_string()));
+        }
+
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&session_key.key));
+        let (nonce, ciphertext) = wrapped.split_at(12);
+        let file_key = cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: FILE_KEY_AAD })
+            .map_err(|e| EncryptionError::DecryptionFailed(e.to_string()))?;
+
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&file_key);
+        Ok(key)
+    }
+}
+
+/// 30 digits from the first 30 bytes of a fingerprint: 5 bytes (u40 BE) mod 100000 per group
+fn fingerprint_digits(fingerprint: &[u8; 32]) -> String {
+    fingerprint[..30]
+        .chunks(5)
+        .map(|chunk| {
+            let value = chunk.iter().fold(0u64, |value, byte| (value << 8) | u64::from(*byte));
+            format!("{:05}", value % 100_000)
+        })
+        .collect()
+}
+
+/// A random 96-bit AES-GCM nonce
+fn generate_nonce() -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    OsRng.fill(&mut nonce);
+    nonce
+}
+
+/// Base64 nonce || ciphertext || tag, as written by `seal_private_key`
+/// The server can only check the shape; it never has the passphrase to open it.
+pub fn decode_sealed_private_key(sealed: &str) -> Result<Vec<u8>, EncryptionError> {
+    let sealed = general_purpose::STANDARD
+        .decode(sealed)
+        .map_err(|e| EncryptionError::InvalidKeyFormat(format!("Invalid base64: {}", e)))?;
+    if sealed.len() < 12 + TAG_LEN {
+        return Err(EncryptionError::InvalidKeyFormat("Sealed private key is too short".to_string()));
+    }
+    Ok(sealed)
+}
+
+/// label || user id || key version (u32 BE)
+fn private_key_aad(user_id: Uuid, key_version: u32) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(PRIVATE_KEY_AAD_LABEL.len() + 16 + 4);
+    aad.extend_from_slice(PRIVATE_KEY_AAD_LABEL);
+    aad.extend_from_slice(user_id.as_bytes());
+    aad.extend_from_slice(&key_version.to_be_bytes());
+    aad
+}
+
+/// A message's stored nonce; version 1 messages carry their session's shared nonce here too
+fn decode_nonce(nonce: &str) -> Result<[u8; 12], EncryptionError> {
+    general_purpose::STANDARD
+        .decode(nonce)
+        .ok()
+        .and_then(|nonce| <[u8; 12]>::try_from(nonce.as_slice()).ok())
+        .ok_or_else(|| EncryptionError::InvalidMessageFormat("Invalid nonce".to_string()))
+}
+
+/// A `content_hash`: hex SHA-256 of the plaintext
+fn decode_content_hash(content_hash: &str) -> Result<[u8; 32], EncryptionError> {
+    hex::decode(content_hash)
+        .ok()
+        .and_then(|hash| <[u8; 32]>::try_from(hash.as_slice()).ok())
+        .ok_or_else(|| EncryptionError::InvalidMessageFormat("Invalid content hash".to_string()))
+}
+
+// 📎 CHUNKED FILE ENCRYPTION
+//
+// Attachments are encrypted as a stream of independently authenticated chunks,
+// so neither side ever holds a whole file in memory:
+//
+//   header: "OCF1" | chunk size (u32 BE) | nonce prefix (8 random bytes)
+//   chunk:  AES-256-GCM(plaintext[chunk size]) || tag   (last chunk may be shorter)
+//
+// Chunk i uses nonce prefix || i (u32 BE), and its AAD is i (u64 BE) plus a
+// final-chunk flag, so chunks can't be reordered, dropped or cut short.
+// `file_hash` is the SHA-256 of everything written (header included), which is
+// exactly what the server computes on upload even though it only sees ciphertext.
+
+/// Plaintext bytes per encrypted chunk
+pub const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Length of a decoded wrapped file key: nonce + key + tag
+pub const WRAPPED_FILE_KEY_LEN: usize = 12 + 32 + TAG_LEN;
+
+const FILE_MAGIC: &[u8; 4] = b"OCF1";
+const FILE_HEADER_LEN: usize = 16;
+const MAX_FILE_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+const TAG_LEN: usize = 16;
+const FILE_KEY_AAD: &[u8] = b"ochat-file-key-v1";
+
+fn chunk_nonce(prefix: &[u8; 8], index: u32) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..8].copy_from_slice(prefix);
+    nonce[8..].copy_from_slice(&index.to_be_bytes());
+    nonce
+}
+
+fn chunk_aad(index: u32, is_final: bool) -> [u8; 9] {
+    let mut aad = [0u8; 9];
+    aad[..8].copy_from_slice(&u64::from(index).to_be_bytes());
+    aad[8] = is_final as u8;
+    aad
+}
+
+/// Encrypts a file chunk by chunk; feed it with `update` and close it with `finish`
+pub struct FileEncryptor {
+    cipher: Aes256Gcm,
+    nonce_prefix: [u8; 8],
+    next_chunk: u32,
+    buffer: Vec<u8>,            // Plaintext not yet sealed into a chunk
+    header_written: bool,
+    hasher: Sha256,             // Over all output, for `file_hash`
+}
+
+impl FileEncryptor {
+    pub fn new(file_key: &[u8; 32]) -> Self {
+        let mut nonce_prefix = [0u8; 8];
+        OsRng.fill(&mut nonce_prefix);
+
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(file_key)),
+            nonce_prefix,
+            next_chunk: 0,
+            buffer: Vec::with_capacity(FILE_CHUNK_SIZE),
+            header_written: false,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Add plaintext; returns the ciphertext that is ready to be written
+    pub fn update(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let mut output = self.take_header();
+        self.buffer.extend_from_slice(plaintext);
+
+        // Hold back a full chunk until we know it isn't the last one
+        while self.buffer.len() > FILE_CHUNK_SIZE {
+            let rest = self.buffer.split_off(FILE_CHUNK_SIZE);
+            let chunk = std::mem::replace(&mut self.buffer, rest);
+            output.extend(self.seal(&chunk, false)?);
+        }
+        self.hasher.update(&output);
+        Ok(output)
+    }
+
+    /// Seal the final chunk; returns the remaining ciphertext and the hex `file_hash`
+    pub fn finish(mut self) -> Result<(Vec<u8>, String), EncryptionError> {
+        let mut output = self.take_header();
+        let chunk = std::mem::take(&mut self.buffer);
+        output.extend(self.seal(&chunk, true)?);
+        self.hasher.update(&output);
+        Ok((output, hex::encode(self.hasher.finalize())))
+    }
+
+    fn take_header(&mut self) -> Vec<u8> {
+        if self.header_written {
+            return Vec::new();
+        }
+        self.header_written = true;
+
+        let mut header = Vec::with_capacity(FILE_HEADER_LEN);
+        header.extend_from_slice(FILE_MAGIC);
+        header.extend_from_slice(&(FILE_CHUNK_SIZE as u32).to_be_bytes());
+        header.extend_from_slice(&self.nonce_prefix);
+        header
+    }
+
+    fn seal(&mut self, chunk: &[u8], is_final: bool) -> Result<Vec<u8>, EncryptionError> {
+        let index = self.next_chunk;
+        self.next_chunk = index.checked_add(1)
+            .ok_or_else(|| EncryptionError::EncryptionFailed("File too large".to_string()))?;
+
+        let nonce = chunk_nonce(&self.nonce_prefix, index);
+        let aad = chunk_aad(index, is_final);
+        self.cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: chunk, aad: &aad })
+            .map_err(|e| EncryptionError::EncryptionFailed(e.to_string()))
+    }
+}
+
+/// Decrypts a file produced by `FileEncryptor`
+/// Each chunk's tag is checked before its plaintext is returned, and the running
+/// SHA-256 is compared with the expected `file_hash` before the last chunk is released.
+pub struct FileDecryptor {
+    cipher: Aes256Gcm,
+    expected_hash: String,
+    header: Option<(usize, [u8; 8])>, // Chunk size and nonce prefix, once read
+    next_chunk: u32,
+    buffer: Vec<u8>,                  // Ciphertext not yet opened
+    hasher: Sha256,
+}
+
+impl FileDecryptor {
+    pub fn new(file_key: &[u8; 32], expected_file_hash: &str) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(file_key)),
+            expected_hash: expected_file_hash.to_ascii_lowercase(),
+            header: None,
+            next_chunk: 0,
+            buffer: Vec::new(),
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Add ciphertext; returns the plaintext of every complete, verified chunk
+    pub fn update(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        self.hasher.update(ciphertext);
+        self.buffer.extend_from_slice(ciphertext);
+        let mut output = Vec::new();
+
+        let Some((chunk_size, _)) = self.read_header()? else {
+            return Ok(output);
+        };
+        // As when encrypting, a full chunk might still turn out to be the last
+        let sealed_len = chunk_size + TAG_LEN;
+        while self.buffer.len() > sealed_len {
+            let rest = self.buffer.split_off(sealed_len);
+            let chunk = std::mem::replace(&mut self.buffer, rest);
+            output.extend(self.open(&chunk, false)?);
+        }
+        Ok(output)
+    }
+
+    /// Verify `file_hash` and open the final chunk
+    pub fn finish(mut self) -> Result<Vec<u8>, EncryptionError> {
+        if self.read_header()?.is_none() {
+            return Err(EncryptionError::InvalidMessageFormat("Encrypted file is truncated".to_string()));
+        }
+
+        let actual_hash = hex::encode(std::mem::take(&mut self.hasher).finalize());
+        if actual_hash != self.expected_hash {
+            return Err(EncryptionError::HashVerificationFailed(
+                "File hash verification failed".to_string(),
+            ));
+        }
+
+        let chunk = std::mem::take(&mut self.buffer);
+        self.open(&chunk, true)
+    }
+
+    fn read_header(&mut self) -> Result<Option<(usize, [u8; 8])>, EncryptionError> {
+        if self.header.is_none() && self.buffer.len() >= FILE_HEADER_LEN {
+            let header: Vec<u8> = self.buffer.drain(..FILE_HEADER_LEN).collect();
+            if &header[..4] != FILE_MAGIC {
+                return Err(EncryptionError::InvalidMessageFormat("Not an encrypted file".to_string()));
+            }
+            let chunk_size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+            if chunk_size == 0 || chunk_size > MAX_FILE_CHUNK_SIZE {
+                return Err(EncryptionError::InvalidMessageFormat("Invalid chunk size".to_string()));
+            }
+            let mut nonce_prefix = [0u8; 8];
+            nonce_prefix.copy_from_slice(&header[8..]);
+            self.header = Some((chunk_size, nonce_prefix));
+        }
+        Ok(self.header)
+    }
+
+    fn open(&mut self, chunk: &[u8], is_final: bool) -> Result<Vec<u8>, EncryptionError> {
+        let (_, nonce_prefix) = self.header
+            .ok_or_else(|| EncryptionError::InvalidMessageFormat("Missing file header".to_string()))?;
+        let index = self.next_chunk;
+        self.next_chunk = index.checked_add(1)
+            .ok_or_else(|| EncryptionError::DecryptionFailed("File too large".to_string()))?;
+
+        let nonce = chunk_nonce(&nonce_prefix, index);
+        let aad = chunk_aad(index, is_final);
+        self.cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: chunk, aad: &aad })
+            .map_err(|_| EncryptionError::DecryptionFailed(format!("Chunk {} failed authentication", index)))
+    }
 }
 
 // 🔧 UTILITY FUNCTIONS
@@ -332,6 +1063,36 @@ pub fn validate_encrypted_message(message: &EncryptedMessage) -> Result<(), Encr
     Ok(())
 }
 
+/// Check the encryption metadata a client sent along with its ciphertext
+/// Only the shape can be checked here: the server never holds session keys. New
+/// messages must use a per-message nonce (version 2 on), never the legacy format.
+pub fn validate_message_encryption(encrypted_content: &str, encryption: &MessageEncryption) -> Result<(), EncryptionError> {
+    let version = u32::try_from(encryption.encryption_version).unwrap_or(UNENCRYPTED_VERSION);
+    if !(UNBOUND_NONCE_VERSION..=ENCRYPTION_VERSION).contains(&version) {
+        return Err(EncryptionError::InvalidMessageFormat(format!(
+            "Unsupported encryption version {}",
+            encryption.encryption_version
+        )));
+    }
+    
+    if !general_purpose::STANDARD.decode(encrypted_content).is_ok_and(|content| content.len() >= TAG_LEN) {
+        return Err(EncryptionError::InvalidMessageFormat(
+            "Encrypted content is not AES-GCM ciphertext".to_string(),
+        ));
+    }
+    
+    decode_nonce(&encryption.nonce)?;
+    decode_content_hash(&encryption.content_hash)?;
+    
+    if encryption.session_key_generation < 1 {
+        return Err(EncryptionError::InvalidMessageFormat(
+            "Invalid session key generation".to_string(),
+        ));
+    }
+    
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -339,37 +1100,341 @@ mod tests {
     #[test]
     fn test_key_generation() {
         let service = EncryptionService::new();
-        let key_pair = service.generate_key_pair().unwrap();
+        let key_pair = service.generate_key_pair(1).unwrap();
         
         assert_eq!(key_pair.version, 1);
         assert_ne!(key_pair.key_id, Uuid::nil());
+        assert_eq!(key_pair.algorithm, KeyWrapAlgorithm::RsaOaepSha256);
+    }
+
+    #[test]
+    fn test_session_key_wrapping_follows_the_key_algorithm() {
+        let service = EncryptionService::new();
+        let key_pair = service.generate_key_pair(1).unwrap();
+        let session_key = service.generate_session_key(1).unwrap();
+
+        let wrapped = service
+            .encrypt_session_key(&session_key, &key_pair.public_key, key_pair.algorithm)
+            .unwrap();
+        let unwrapped = service
+            .decrypt_session_key(&wrapped, &key_pair.private_key, key_pair.algorithm)
+            .unwrap();
+        assert_eq!(unwrapped, session_key.key);
+
+        // Keys wrapped with PKCS#1 v1.5 before the migration still unwrap...
+        let legacy = general_purpose::STANDARD.encode(
+            key_pair.public_key.encrypt(&mut OsRng, Pkcs1v15Encrypt, &session_key.key).unwrap(),
+        );
+        let legacy_algorithm: KeyWrapAlgorithm = "RSA-2048".parse().unwrap();
+        assert_eq!(
+            service.decrypt_session_key(&legacy, &key_pair.private_key, legacy_algorithm).unwrap(),
+            session_key.key
+        );
+        // ...but nothing new is wrapped that way, and schemes don't mix
+        assert!(service.encrypt_session_key(&session_key, &key_pair.public_key, legacy_algorithm).is_err());
+        assert!(service.decrypt_session_key(&legacy, &key_pair.private_key, KeyWrapAlgorithm::RsaOaepSha256).is_err());
+        assert!("RSA-1024".parse::<KeyWrapAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn test_private_key_sealing() {
+        let service = EncryptionService::new();
+        let key_pair = service.generate_key_pair(2).unwrap();
+        let user_id = Uuid::new_v4();
+        // Cheap settings keep the test fast; real keys use `KdfParams::generate()` as is
+        let params = KdfParams { memory_kib: 1024, iterations: 1, ..KdfParams::generate() };
+
+        let pkcs8 = service.export_private_key_pkcs8(&key_pair.private_key).unwrap();
+        let sealed = service.seal_private_key(&pkcs8, "correct horse", &params, user_id, 2).unwrap();
+        let unsealed = service.unseal_private_key(&sealed, "correct horse", &params, user_id, 2).unwrap();
+        assert_eq!(service.import_private_key_pkcs8(&unsealed).unwrap(), key_pair.private_key);
+
+        // Wrong passphrase, another salt, or another row's identity all fail
+        assert!(service.unseal_private_key(&sealed, "wrong horse", &params, user_id, 2).is_err());
+        let resalted = KdfParams { salt: KdfParams::generate().salt, ..params.clone() };
+        assert!(service.unseal_private_key(&sealed, "correct horse", &resalted, user_id, 2).is_err());
+        assert!(service.unseal_private_key(&sealed, "correct horse", &params, user_id, 1).is_err());
+        assert!(service.unseal_private_key(&sealed, "correct horse", &params, Uuid::new_v4(), 2).is_err());
+
+        // A row can't demand absurd Argon2 costs
+        let greedy = KdfParams { memory_kib: u32::MAX, ..params.clone() };
+        assert!(matches!(
+            service.unseal_private_key(&sealed, "correct horse", &greedy, user_id, 2),
+            Err(EncryptionError::InvalidKeyFormat(_))
+        ));
+
+        // Uploads must use at least the minimum cost, and look like a sealed key
+        assert!(KdfParams::generate().validate().is_ok());
+        assert!(params.validate().is_err());
+        assert!(greedy.validate().is_err());
+        assert!(decode_sealed_private_key(&sealed).is_ok());
+        assert!(decode_sealed_private_key("AAAA").is_err());
     }
 
     #[test]
     fn test_session_key_generation() {
         let service = EncryptionService::new();
-        let session_key = service.generate_session_key().unwrap();
+        let session_key = service.generate_session_key(1).unwrap();
         
         assert_ne!(session_key.session_id, Uuid::nil());
         assert_eq!(session_key.key.len(), 32);
-        assert_eq!(session_key.nonce.len(), 12);
     }
 
     #[test]
     fn test_message_encryption_decryption() {
         let mut service = EncryptionService::new();
-        let session_key = service.generate_session_key().unwrap();
+        let session_key = service.generate_session_key(1).unwrap();
         let original_content = "Hello, encrypted world!";
+        let context = test_context();
         
         // Encrypt
-        let encrypted = service.encrypt_message(original_content, &session_key).unwrap();
+        let encrypted = service.encrypt_message(original_content, &session_key, &context).unwrap();
         
         // Decrypt
-        let decrypted = service.decrypt_message(&encrypted, &session_key).unwrap();
+        let decrypted = service.decrypt_message(&encrypted, &session_key, &context).unwrap();
         
         assert_eq!(original_content, decrypted);
     }
 
+    fn test_context() -> MessageContext {
+        MessageContext::direct(Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4())
+    }
+
+    #[test]
+    fn test_every_message_gets_its_own_nonce() {
+        let service = EncryptionService::new();
+        let session_key = service.generate_session_key(1).unwrap();
+        let context = test_context();
+
+        let first = service.encrypt_message("same text", &session_key, &context).unwrap();
+        let second = service.encrypt_message("same text", &session_key, &context).unwrap();
+        assert_eq!(first.encryption_version, ENCRYPTION_VERSION);
+        assert_ne!(first.nonce, second.nonce);
+        assert_ne!(first.encrypted_content, second.encrypted_content);
+
+        // The nonce is read from the message, so a wrong one fails authentication
+        let mut wrong_nonce = first.clone();
+        wrong_nonce.nonce = second.nonce.clone();
+        assert!(service.decrypt_message(&wrong_nonce, &session_key, &context).is_err());
+    }
+
+    #[test]
+    fn test_ciphertext_is_bound_to_its_message_context() {
+        let service = EncryptionService::new();
+        let session_key = service.generate_session_key(1).unwrap();
+        let (sender, receiver) = (Uuid::new_v4(), Uuid::new_v4());
+        let context = MessageContext::direct(sender, receiver, Uuid::new_v4());
+        let encrypted = service.encrypt_message("meet at noon", &session_key, &context).unwrap();
+
+        // Either participant derives the same conversation ID
+        let seen_by_receiver = MessageContext { conversation_id: create_conversation_id(receiver, sender), ..context };
+        assert_eq!(service.decrypt_message(&encrypted, &session_key, &seen_by_receiver).unwrap(), "meet at noon");
+
+        let moved = [
+            MessageContext { message_id: Uuid::new_v4(), ..context },
+            MessageContext { sender_id: receiver, ..context },
+            MessageContext { conversation_id: create_conversation_id(sender, Uuid::new_v4()), ..context },
+        ];
+        for other in moved {
+            assert!(service.decrypt_message(&encrypted, &session_key, &other).is_err());
+        }
+
+        // The version is authenticated too, so a downgrade to the unbound format fails
+        let mut downgraded = encrypted;
+        downgraded.encryption_version = UNBOUND_NONCE_VERSION;
+        assert!(service.decrypt_message(&downgraded, &session_key, &context).is_err());
+    }
+
+    #[test]
+    fn test_legacy_shared_nonce_messages_still_decrypt_and_migrate() {
+        let service = EncryptionService::new();
+        let session_key = service.generate_session_key(1).unwrap();
+        let content = "written by an old client";
+        let context = test_context();
+
+        // Version 1: encrypted under the nonce every message of the session shared
+        let shared_nonce = generate_nonce();
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&session_key.key));
+        let ciphertext = cipher.encrypt(Nonce::from_slice(&shared_nonce), content.as_bytes()).unwrap();
+        let legacy = EncryptedMessage {
+            encrypted_content: general_purpose::STANDARD.encode(ciphertext),
+            encrypted_session_key: String::new(),
+            content_hash: service.generate_content_hash(content),
+            encryption_version: LEGACY_SHARED_NONCE_VERSION,
+            nonce: general_purpose::STANDARD.encode(shared_nonce),
+            session_key_id: session_key.session_id,
+            session_key_generation: session_key.generation,
+        };
+        assert_eq!(service.decrypt_message(&legacy, &session_key, &context).unwrap(), content);
+        assert!(service.needs_reencryption(&legacy));
+
+        let migrated = service.reencrypt_message(&legacy, &session_key, &context).unwrap();
+        assert!(!service.needs_reencryption(&migrated));
+        assert_eq!(migrated.content_hash, legacy.content_hash);
+        assert_eq!(service.decrypt_message(&migrated, &session_key, &context).unwrap(), content);
+
+        let mut unknown = migrated;
+        unknown.encryption_version = 99;
+        assert!(matches!(
+            service.decrypt_message(&unknown, &session_key, &context),
+            Err(EncryptionError::InvalidMessageFormat(_))
+        ));
+    }
+
+    fn encrypt_file(file_key: &[u8; 32], plaintext: &[u8], feed: usize) -> (Vec<u8>, String) {
+        let mut encryptor = FileEncryptor::new(file_key);
+        let mut ciphertext = Vec::new();
+        for piece in plaintext.chunks(feed) {
+            ciphertext.extend(encryptor.update(piece).unwrap());
+        }
+        let (rest, file_hash) = encryptor.finish().unwrap();
+        ciphertext.extend(rest);
+        (ciphertext, file_hash)
+    }
+
+    fn decrypt_file(file_key: &[u8; 32], ciphertext: &[u8], file_hash: &str, feed: usize) -> Result<Vec<u8>, EncryptionError> {
+        let mut decryptor = FileDecryptor::new(file_key, file_hash);
+        let mut plaintext = Vec::new();
+        for piece in ciphertext.chunks(feed) {
+            plaintext.extend(decryptor.update(piece)?);
+        }
+        plaintext.extend(decryptor.finish()?);
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn test_chunked_file_round_trip() {
+        let service = EncryptionService::new();
+        let file_key = service.generate_file_key();
+
+        // Exact multiples of the chunk size and empty files are the edge cases
+        for len in [0, 1, FILE_CHUNK_SIZE, 2 * FILE_CHUNK_SIZE + 7] {
+            let plaintext: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let (ciphertext, file_hash) = encrypt_file(&file_key, &plaintext, 10_000);
+
+            assert_eq!(file_hash, hex::encode(Sha256::digest(&ciphertext)));
+            assert_eq!(decrypt_file(&file_key, &ciphertext, &file_hash, 7_777).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_chunked_file_rejects_tampering_and_truncation() {
+        let service = EncryptionService::new();
+        let file_key = service.generate_file_key();
+        let plaintext = vec![42u8; 2 * FILE_CHUNK_SIZE + 100];
+        let (ciphertext, file_hash) = encrypt_file(&file_key, &plaintext, FILE_CHUNK_SIZE);
+        assert!(decrypt_file(&file_key, &ciphertext, &file_hash, 4096).is_ok());
+
+        let mut tampered = ciphertext.clone();
+        tampered[FILE_HEADER_LEN + 5] ^= 1;
+        let tampered_hash = hex::encode(Sha256::digest(&tampered));
+        assert!(decrypt_file(&file_key, &tampered, &tampered_hash, 4096).is_err());
+
+        // Dropping the final chunk leaves a full chunk that wasn't sealed as final
+        let truncated = &ciphertext[..FILE_HEADER_LEN + 2 * (FILE_CHUNK_SIZE + TAG_LEN)];
+        let truncated_hash = hex::encode(Sha256::digest(truncated));
+        assert!(decrypt_file(&file_key, truncated, &truncated_hash, 4096).is_err());
+
+        assert!(matches!(
+            decrypt_file(&file_key, &ciphertext, &"0".repeat(64), 4096),
+            Err(EncryptionError::HashVerificationFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_file_key_wrapping() {
+        let service = EncryptionService::new();
+        let session_key = service.generate_session_key(1).unwrap();
+        let other_session_key = service.generate_session_key(1).unwrap();
+        let file_key = service.generate_file_key();
+
+        let wrapped = service.wrap_file_key(&file_key, &session_key).unwrap();
+        assert_eq!(general_purpose::STANDARD.decode(&wrapped).unwrap().len(), WRAPPED_FILE_KEY_LEN);
+        assert_eq!(service.unwrap_file_key(&wrapped, &session_key).unwrap(), file_key);
+        assert!(service.unwrap_file_key(&wrapped, &other_session_key).is_err());
+    }
+
+    #[test]
+    fn test_safety_numbers_match_on_both_sides_and_follow_key_changes() {
+        let service = EncryptionService::new();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let alice_key = service.generate_key_pair(1).unwrap().public_key;
+        let bob_key = service.generate_key_pair(1).unwrap().public_key;
+
+        let from_alice = service.safety_number(alice, &alice_key, bob, &bob_key).unwrap();
+        let from_bob = service.safety_number(bob, &bob_key, alice, &alice_key).unwrap();
+        assert_eq!(from_alice, from_bob);
+        assert_eq!(from_alice.numeric.len(), 60 + 11);
+        assert!(from_alice.numeric.split(' ').all(|group| group.len() == 5 && group.bytes().all(|b| b.is_ascii_digit())));
+        assert!(from_alice.qr_payload.starts_with("ochat-safety:1:"));
+
+        let rotated_key = service.generate_key_pair(2).unwrap().public_key;
+        let after_rotation = service.safety_number(alice, &alice_key, bob, &rotated_key).unwrap();
+        assert_ne!(after_rotation.numeric, from_alice.numeric);
+        assert_ne!(after_rotation.qr_payload, from_alice.qr_payload);
+    }
+
+    #[test]
+    fn test_message_signatures_bind_sender_content_and_context() {
+        let service = EncryptionService::new();
+        let sender = service.generate_key_pair(1).unwrap();
+        let impostor = service.generate_key_pair(1).unwrap();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let context = MessageContext::direct(alice, bob, Uuid::new_v4());
+
+        let signature = service.sign_message(&sender.private_key, &context, "hello").unwrap();
+        assert!(service.verify_message_signature(&sender.public_key, &context, "hello", &signature).is_ok());
+
+        assert!(service.verify_message_signature(&sender.public_key, &context, "hellO", &signature).is_err());
+        assert!(service.verify_message_signature(&impostor.public_key, &context, "hello", &signature).is_err());
+        let moved = MessageContext { message_id: Uuid::new_v4(), ..context };
+        assert!(service.verify_message_signature(&sender.public_key, &moved, "hello", &signature).is_err());
+        let forged = service.sign_message(&impostor.private_key, &context, "hello").unwrap();
+        assert!(service.verify_message_signature(&sender.public_key, &context, "hello", &forged).is_err());
+    }
+
+    #[test]
+    fn test_signatures_verify_from_the_content_hash() {
+        let service = EncryptionService::new();
+        let sender = service.generate_key_pair(1).unwrap();
+        let context = test_context();
+        let signature = service.sign_message(&sender.private_key, &context, "hello").unwrap();
+
+        let content_hash = service.generate_content_hash("hello");
+        assert!(service.verify_content_signature(&sender.public_key, &context, &content_hash, &signature).is_ok());
+        let other_hash = service.generate_content_hash("hellO");
+        assert!(service.verify_content_signature(&sender.public_key, &context, &other_hash, &signature).is_err());
+        assert!(service.verify_content_signature(&sender.public_key, &context, "not a hash", &signature).is_err());
+    }
+
+    #[test]
+    fn test_client_encryption_metadata_is_validated() {
+        let service = EncryptionService::new();
+        let session_key = service.generate_session_key(2).unwrap();
+        let encrypted = service.encrypt_message("hello", &session_key, &test_context()).unwrap();
+        let metadata = MessageEncryption {
+            content_hash: encrypted.content_hash.clone(),
+            nonce: encrypted.nonce.clone(),
+            encryption_version: encrypted.encryption_version as i32,
+            session_key_id: encrypted.session_key_id,
+            session_key_generation: encrypted.session_key_generation as i32,
+            signature: None,
+        };
+        assert!(validate_message_encryption(&encrypted.encrypted_content, &metadata).is_ok());
+
+        // New messages never use the shared-nonce format, or one we don't know yet
+        let legacy = MessageEncryption { encryption_version: LEGACY_SHARED_NONCE_VERSION as i32, ..metadata.clone() };
+        assert!(validate_message_encryption(&encrypted.encrypted_content, &legacy).is_err());
+        let future = MessageEncryption { encryption_version: ENCRYPTION_VERSION as i32 + 1, ..metadata.clone() };
+        assert!(validate_message_encryption(&encrypted.encrypted_content, &future).is_err());
+
+        let short_nonce = MessageEncryption { nonce: general_purpose::STANDARD.encode([0u8; 8]), ..metadata.clone() };
+        assert!(validate_message_encryption(&encrypted.encrypted_content, &short_nonce).is_err());
+        let bad_hash = MessageEncryption { content_hash: "abc".to_string(), ..metadata.clone() };
+        assert!(validate_message_encryption(&encrypted.encrypted_content, &bad_hash).is_err());
+        assert!(validate_message_encryption("hello", &metadata).is_err());
+    }
+
     #[test]
     fn test_conversation_id_consistency() {
         let user1 = Uuid::new_v4();
@@ -0,0 +1,349 @@
+// 🔁 DOUBLE RATCHET
+// Per-message keys for 1:1 conversations, after Signal's Double Ratchet. Every
+// message is encrypted under its own key from a symmetric chain (forward
+// secrecy: used keys are deleted), and each reply turn mixes a fresh X25519
+// exchange into the root key (post-compromise security: a leaked state stops
+// being useful once both sides have ratcheted).
+//
+//   root:    (RK, CK) = HKDF(salt = RK, DH(our ratchet key, their ratchet key))
+//   chain:   MK = HMAC(CK, 0x01), CK' = HMAC(CK, 0x02)
+//   message: AES-256-GCM, key and nonce = HKDF(MK), AAD = X3DH AD || header
+//
+// Sessions start from an `x3dh::X3dhOutput`; the initiator ratchets against the
+// responder's signed prekey, which the responder keeps as its first ratchet key.
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{Engine as _, engine::general_purpose};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+use super::x3dh::{decode_public_key, diffie_hellman, X3dhOutput};
+use super::EncryptionError;
+
+/// Most message keys one chain may skip ahead for out-of-order messages
+pub const MAX_SKIP: u32 = 1000;
+
+const ROOT_INFO: &[u8] = b"ochat-ratchet-root";
+const MESSAGE_INFO: &[u8] = b"ochat-ratchet-message";
+
+type ChainKey = Zeroizing<[u8; 32]>;
+
+/// Sent in the clear with every ratchet message (and authenticated as AAD)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RatchetHeader {
+    pub ratchet_key: String,              // Sender's current X25519 ratchet key, base64
+    pub previous_chain_length: u32,       // Messages in the sender's previous sending chain
+    pub message_number: u32,              // Position in the current sending chain
+}
+
+impl RatchetHeader {
+    /// ratchet key || previous chain length (u32 BE) || message number (u32 BE)
+    fn encode(&self, ratchet_key: &PublicKey) -> [u8; 40] {
+        let mut encoded = [0u8; 40];
+        encoded[..32].copy_from_slice(ratchet_key.as_bytes());
+        encoded[32..36].copy_from_slice(&self.previous_chain_length.to_be_bytes());
+        encoded[36..].copy_from_slice(&self.message_number.to_be_bytes());
+        encoded
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RatchetMessage {
+    pub header: RatchetHeader,
+    pub ciphertext: String,               // Base64 AES-256-GCM ciphertext || tag
+}
+
+/// One side of a Double Ratchet conversation
+#[derive(Clone)]
+pub struct RatchetSession {
+    ratchet_key: StaticSecret,
+    remote_ratchet_key: Option<PublicKey>,
+    root_key: ChainKey,
+    sending_chain: Option<ChainKey>,
+    receiving_chain: Option<ChainKey>,
+    sent: u32,
+    received: u32,
+    previous_chain_length: u32,
+    skipped: HashMap<([u8; 32], u32), ChainKey>,
+    associated_data: Vec<u8>,
+}
+
+impl RatchetSession {
+    /// Start as the initiator, ratcheting against the responder's signed prekey
+    pub fn initiate(x3dh: X3dhOutput, responder_signed_prekey: &PublicKey) -> Result<Self, EncryptionError> {
+        let ratchet_key = StaticSecret::random_from_rng(OsRng);
+        let (root_key, sending_chain) =
+            kdf_root(&x3dh.shared_secret, &*diffie_hellman(&ratchet_key, responder_signed_prekey)?)?;
+
+        Ok(Self {
+            ratchet_key,
+            remote_ratchet_key: Some(*responder_signed_prekey),
+            root_key,
+            sending_chain: Some(sending_chain),
+            receiving_chain: None,
+            sent: 0,
+            received: 0,
+            previous_chain_length: 0,
+            skipped: HashMap::new(),
+            associated_data: x3dh.associated_data,
+        })
+    }
+
+    /// Start as the responder; it can only send once the initiator's first message is in
+    pub fn respond(x3dh: X3dhOutput, signed_prekey: StaticSecret) -> Self {
+        Self {
+            ratchet_key: signed_prekey,
+            remote_ratchet_key: None,
+            root_key: x3dh.shared_secret,
+            sending_chain: None,
+            receiving_chain: None,
+            sent: 0,
+            received: 0,
+            previous_chain_length: 0,
+            skipped: HashMap::new(),
+            associated_data: x3dh.associated_data,
+        }
+    }
+
+    /// Encrypt the next message under a fresh message key
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<RatchetMessage, EncryptionError> {
+        let chain = self.sending_chain.as_ref().ok_or_else(|| {
+            EncryptionError::EncryptionFailed("No sending chain until the first message is received".to_string())
+        })?;
+        let (next_chain, message_key) = kdf_chain(chain);
+
+        let ratchet_key = PublicKey::from(&self.ratchet_key);
+        let header = RatchetHeader {
+            ratchet_key: general_purpose::STANDARD.encode(ratchet_key.as_bytes()),
+            previous_chain_length: self.previous_chain_length,
+            message_number: self.sent,
+        };
+        let ciphertext = seal(&message_key, &self.message_aad(&header, &ratchet_key), plaintext)?;
+
+        self.sending_chain = Some(next_chain);
+        self.sent += 1;
+        Ok(RatchetMessage { header, ciphertext: general_purpose::STANDARD.encode(ciphertext) })
+    }
+
+    /// Decrypt a message, which may arrive out of order
+    /// The session is only changed if the message authenticates, so forged or
+    /// replayed messages can't knock it out of step.
+    pub fn decrypt(&mut self, message: &RatchetMessage) -> Result<Vec<u8>, EncryptionError> {
+        let mut next = self.clone();
+        let plaintext = next.decrypt_in_place(message)?;
+        *self = next;
+        Ok(plaintext)
+    }
+
+    fn decrypt_in_place(&mut self, message: &RatchetMessage) -> Result<Vec<u8>, EncryptionError> {
+        let header = &message.header;
+        let their_ratchet_key = decode_public_key(&header.ratchet_key)?;
+        let ciphertext = general_purpose::STANDARD
+            .decode(&message.ciphertext)
+            .map_err(|e| EncryptionError::DecryptionFailed(format!("Invalid base64: {}", e)))?;
+        let aad = self.message_aad(header, &their_ratchet_key);
+
+        // A key skipped earlier is used once, then forgotten
+        if let Some(message_key) = self.skipped.remove(&(*their_ratchet_key.as_bytes(), header.message_number)) {
+            return open(&message_key, &aad, &ciphertext);
+        }
+
+        // 🔁 A new ratchet key from the other side starts a new turn
+        if self.remote_ratchet_key != Some(their_ratchet_key) {
+            self.skip_message_keys(header.previous_chain_length)?;
+            self.dh_ratchet(their_ratchet_key)?;
+        }
+
+        self.skip_message_keys(header.message_number)?;
+        let chain = self.receiving_chain.as_ref()
+            .ok_or_else(|| EncryptionError::DecryptionFailed("No receiving chain".to_string()))?;
+        let (next_chain, message_key) = kdf_chain(chain);
+        self.receiving_chain = Some(next_chain);
+        self.received += 1;
+
+        open(&message_key, &aad, &ciphertext)
+    }
+
+    /// Store the keys of messages before `until` in the receiving chain that haven't arrived
+    fn skip_message_keys(&mut self, until: u32) -> Result<(), EncryptionError> {
+        let (Some(remote), Some(mut chain)) = (self.remote_ratchet_key, self.receiving_chain.clone()) else {
+            return Ok(());
+        };
+        if until > self.received.saturating_add(MAX_SKIP) {
+            return Err(EncryptionError::DecryptionFailed("Too many skipped messages".to_string()));
+        }
+
+        while self.received < until {
+            let (next_chain, message_key) = kdf_chain(&chain);
+            self.skipped.insert((*remote.as_bytes(), self.received), message_key);
+            chain = next_chain;
+            self.received += 1;
+        }
+        self.receiving_chain = Some(chain);
+        Ok(())
+    }
+
+    fn dh_ratchet(&mut self, their_ratchet_key: PublicKey) -> Result<(), EncryptionError> {
+        self.previous_chain_length = self.sent;
+        self.sent = 0;
+        self.received = 0;
+        self.remote_ratchet_key = Some(their_ratchet_key);
+
+        let (root_key, receiving_chain) =
+            kdf_root(&self.root_key, &*diffie_hellman(&self.ratchet_key, &their_ratchet_key)?)?;
+        self.ratchet_key = StaticSecret::random_from_rng(OsRng);
+        let (root_key, sending_chain) =
+            kdf_root(&root_key, &*diffie_hellman(&self.ratchet_key, &their_ratchet_key)?)?;
+
+        self.root_key = root_key;
+        self.receiving_chain = Some(receiving_chain);
+        self.sending_chain = Some(sending_chain);
+        Ok(())
+    }
+
+    fn message_aad(&self, header: &RatchetHeader, ratchet_key: &PublicKey) -> Vec<u8> {
+        let mut aad = self.associated_data.clone();
+        aad.extend_from_slice(&header.encode(ratchet_key));
+        aad
+    }
+}
+
+// 🔧 KEY DERIVATION
+
+fn kdf_root(root_key: &[u8; 32], dh_output: &[u8; 32]) -> Result<(ChainKey, ChainKey), EncryptionError> {
+    let mut output = Zeroizing::new([0u8; 64]);
+    Hkdf::<Sha256>::new(Some(root_key), dh_output)
+        .expand(ROOT_INFO, output.as_mut())
+        .map_err(|e| EncryptionError::KeyGenerationFailed(e.to_string()))?;
+
+    let mut root = Zeroizing::new([0u8; 32]);
+    let mut chain = Zeroizing::new([0u8; 32]);
+    root.copy_from_slice(&output[..32]);
+    chain.copy_from_slice(&output[32..]);
+    Ok((root, chain))
+}
+
+/// Returns the next chain key and this step's message key
+fn kdf_chain(chain_key: &[u8; 32]) -> (ChainKey, ChainKey) {
+    let step = |constant: u8| {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(chain_key).expect("HMAC accepts any key length");
+        mac.update(&[constant]);
+        Zeroizing::new(<[u8; 32]>::from(mac.finalize().into_bytes()))
+    };
+    let message_key = step(0x01);
+    (step(0x02), message_key)
+}
+
+/// Each message key is used once, so its key and nonce can both come from it
+fn message_cipher(message_key: &[u8; 32]) -> Result<(Aes256Gcm, [u8; 12]), EncryptionError> {
+    let mut output = Zeroizing::new([0u8; 44]);
+    Hkdf::<Sha256>::new(None, message_key)
+        .expand(MESSAGE_INFO, output.as_mut())
+        .map_err(|e| EncryptionError::KeyGenerationFailed(e.to_string()))?;
+
+    let mut nonce = [0u8; 12];
+    nonce.copy_from_slice(&output[32..]);
+    Ok((Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&output[..32])), nonce))
+}
+
+fn seal(message_key: &[u8; 32], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    let (cipher, nonce) = message_cipher(message_key)?;
+    cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+        .map_err(|e| EncryptionError::EncryptionFailed(e.to_string()))
+}
+
+fn open(message_key: &[u8; 32], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    let (cipher, nonce) = message_cipher(message_key)?;
+    cipher
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: ciphertext, aad })
+        .map_err(|e| EncryptionError::DecryptionFailed(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::x3dh::{self, IdentityKeyPair, PrekeyBundle, PrekeyKeyring};
+
+    /// Alice starts a session from Bob's bundle; Bob picks it up from her first message
+    fn start_sessions() -> (RatchetSession, RatchetSession) {
+        let alice = IdentityKeyPair::generate();
+        let mut bob = PrekeyKeyring::new(IdentityKeyPair::generate());
+        let upload = bob.upload(1);
+        let bundle = PrekeyBundle {
+            user_id: uuid::Uuid::new_v4(),
+            identity_key: upload.identity_key,
+            signing_key: upload.signing_key,
+            signed_prekey: upload.signed_prekey,
+            one_time_prekey: upload.one_time_prekeys.into_iter().next(),
+        };
+
+        let (alice_x3dh, header) = x3dh::initiate(&alice, &bundle).unwrap();
+        let bob_signed_prekey = decode_public_key(&bundle.signed_prekey.public_key).unwrap();
+        let alice_session = RatchetSession::initiate(alice_x3dh, &bob_signed_prekey).unwrap();
+        let (bob_x3dh, signed_prekey) = bob.respond(&header).unwrap();
+        (alice_session, RatchetSession::respond(bob_x3dh, signed_prekey))
+    }
+
+    #[test]
+    fn test_messages_flow_both_ways_with_fresh_keys() {
+        let (mut alice, mut bob) = start_sessions();
+        assert!(bob.encrypt(b"too early").is_err());
+
+        for round in 0..3 {
+            let first = alice.encrypt(format!("alice {}", round).as_bytes()).unwrap();
+            let second = alice.encrypt(b"same text").unwrap();
+            let third = alice.encrypt(b"same text").unwrap();
+            assert_ne!(second.ciphertext, third.ciphertext);
+            assert_eq!(bob.decrypt(&first).unwrap(), format!("alice {}", round).as_bytes());
+            assert_eq!(bob.decrypt(&second).unwrap(), b"same text");
+            assert_eq!(bob.decrypt(&third).unwrap(), b"same text");
+
+            let reply = bob.encrypt(b"bob").unwrap();
+            assert_eq!(alice.decrypt(&reply).unwrap(), b"bob");
+        }
+    }
+
+    #[test]
+    fn test_out_of_order_and_replayed_messages() {
+        let (mut alice, mut bob) = start_sessions();
+        let messages: Vec<_> = (0..4).map(|i| alice.encrypt(&[i]).unwrap()).collect();
+
+        assert_eq!(bob.decrypt(&messages[3]).unwrap(), [3]);
+        assert_eq!(bob.decrypt(&messages[0]).unwrap(), [0]);
+
+        // Keys are deleted once used: a replay no longer decrypts
+        assert!(bob.decrypt(&messages[0]).is_err());
+        assert!(bob.decrypt(&messages[3]).is_err());
+
+        // Messages from a previous turn still arrive after the other side ratchets
+        let reply = bob.encrypt(b"ack").unwrap();
+        alice.decrypt(&reply).unwrap();
+        let next_turn = alice.encrypt(b"next turn").unwrap();
+        assert_eq!(bob.decrypt(&next_turn).unwrap(), b"next turn");
+        assert_eq!(bob.decrypt(&messages[2]).unwrap(), [2]);
+        assert_eq!(bob.decrypt(&messages[1]).unwrap(), [1]);
+    }
+
+    #[test]
+    fn test_tampered_messages_leave_the_session_intact() {
+        let (mut alice, mut bob) = start_sessions();
+        let message = alice.encrypt(b"hello").unwrap();
+
+        let mut tampered = message.clone();
+        tampered.header.message_number = 1;
+        assert!(bob.decrypt(&tampered).is_err());
+        let mut too_far = message.clone();
+        too_far.header.message_number = MAX_SKIP + 1;
+        assert!(bob.decrypt(&too_far).is_err());
+
+        assert_eq!(bob.decrypt(&message).unwrap(), b"hello");
+    }
+}
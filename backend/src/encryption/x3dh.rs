@@ -0,0 +1,113 @@
+// 🤝 X3DH KEY AGREEMENT
+// Asynchronous session setup for 1:1 conversations, after Signal's X3DH. Each
+// user publishes an identity key, a signed prekey and a batch of one-time
+// prekeys; an initiator claims a bundle of them and derives a shared secret the
+// responder can derive too once the first message arrives, while offline.
+//
+//   DH1 = DH(IK_A, SPK_B)   DH2 = DH(EK_A, IK_B)   DH3 = DH(EK_A, SPK_B)
+//   DH4 = DH(EK_A, OPK_B)   (only if a one-time prekey was left)
+//   SK  = HKDF-SHA-256(0xFF * 32 || DH1 || DH2 || DH3 [|| DH4])
+//
+// Identity keys are an Ed25519 key that signs prekeys plus an X25519 key for
+// the agreement itself. SK then seeds a `ratchet::RatchetSession`.
+
+use base64::{Engine as _, engine::general_purpose};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use uuid::Uuid;
+use x25519_dalek::PublicKey;
+
+use super::EncryptionError;
+
+const SIGNED_PREKEY_LABEL: &[u8] = b"ochat-signed-prekey";
+
+// 🔑 WIRE TYPES
+// Keys travel as base64 of their 32 raw bytes, signatures as base64 of 64
+
+/// A signed prekey as uploaded and handed out in bundles
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SignedPrekey {
+    pub key_id: u32,
+    pub public_key: String,               // X25519
+    pub signature: String,                // Ed25519, see `signed_prekey_message`
+}
+
+/// A one-time prekey; the server hands each one out at most once
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct OneTimePrekey {
+    pub key_id: u32,
+    pub public_key: String,               // X25519
+}
+
+/// What a client publishes: its identity, current signed prekey and new one-time prekeys
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PrekeyUpload {
+    pub identity_key: String,             // X25519 identity key
+    pub signing_key: String,              // Ed25519 key that signed `signed_prekey`
+    pub signed_prekey: SignedPrekey,
+    #[serde(default)]
+    pub one_time_prekeys: Vec<OneTimePrekey>,
+}
+
+/// What an initiator claims for a user; `one_time_prekey` is None once they've run out
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PrekeyBundle {
+    pub user_id: Uuid,
+    pub identity_key: String,
+    pub signing_key: String,
+    pub signed_prekey: SignedPrekey,
+    pub one_time_prekey: Option<OneTimePrekey>,
+}
+
+/// label || identity key || key id (u32 BE) || prekey
+fn signed_prekey_message(identity_key: &PublicKey, key_id: u32, public_key: &PublicKey) -> Vec<u8> {
+    let mut message = Vec::with_capacity(SIGNED_PREKEY_LABEL.len() + 32 + 4 + 32);
+    message.extend_from_slice(SIGNED_PREKEY_LABEL);
+    message.extend_from_slice(identity_key.as_bytes());
+    message.extend_from_slice(&key_id.to_be_bytes());
+    message.extend_from_slice(public_key.as_bytes());
+    message
+}
+
+/// Check that a signed prekey was signed by the identity it's published with
+pub fn verify_signed_prekey(
+    identity_key: &str,
+    signing_key: &str,
+    signed_prekey: &SignedPrekey,
+) -> Result<(), EncryptionError> {
+    let identity_key = decode_public_key(identity_key)?;
+    let verifying_key = VerifyingKey::from_bytes(&decode_key_bytes(signing_key)?)
+        .map_err(|e| EncryptionError::InvalidKeyFormat(format!("Invalid signing key: {}", e)))?;
+    let prekey = decode_public_key(&signed_prekey.public_key)?;
+    let signature = general_purpose::STANDARD
+        .decode(&signed_prekey.signature)
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or_else(|| EncryptionError::InvalidKeyFormat("Invalid prekey signature".to_string()))?;
+
+    let message = signed_prekey_message(&identity_key, signed_prekey.key_id, &prekey);
+    verifying_key
+        .verify(&message, &signature)
+        .map_err(|_| EncryptionError::HashVerificationFailed("Signed prekey signature does not verify".to_string()))
+}
+
+/// Decode a base64 X25519 public key
+pub fn decode_public_key(key: &str) -> Result<PublicKey, EncryptionError> {
+    decode_key_bytes(key).map(PublicKey::from)
+}
+
+fn decode_key_bytes(key: &str) -> Result<[u8; 32], EncryptionError> {
+    general_purpose::STANDARD
+        .decode(key)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes.as_slice()).ok())
+        .ok_or_else(|| EncryptionError::InvalidKeyFormat("Expected a base64 32-byte key".to_string()))
+}
+
+// 🧪 CLIENT SIDE
+// Identity keys, the key agreement itself and the prekey keyring only ever run
+// on clients. They're built for tests, where two simulated clients handshake
+// through the real prekey endpoints.
+#[cfg(test)]
+mod client;
+#[cfg(test)]
+pub use client::*;
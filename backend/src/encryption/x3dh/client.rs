@@ -0,0 +1,260 @@
+// 🤝 X3DH, CLIENT SIDE
+// Everything that touches private keys: identities, the initiator and responder
+// halves of the agreement, and the secrets behind a published bundle.
+
+use aes_gcm::aead::OsRng;
+use base64::{Engine as _, engine::general_purpose};
+use ed25519_dalek::{Signer, SigningKey};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::collections::HashMap;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+use super::{decode_key_bytes, decode_public_key, signed_prekey_message, verify_signed_prekey};
+use super::{OneTimePrekey, PrekeyBundle, PrekeyUpload, SignedPrekey};
+use crate::encryption::EncryptionError;
+
+const X3DH_INFO: &[u8] = b"ochat-x3dh";
+
+/// Sent alongside the initiator's first message so the responder can derive the same secret
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct InitialMessageHeader {
+    pub identity_key: String,             // Initiator's X25519 identity key
+    pub signing_key: String,              // Initiator's Ed25519 identity key
+    pub ephemeral_key: String,
+    pub signed_prekey_id: u32,
+    pub one_time_prekey_id: Option<u32>,
+}
+
+/// The agreed secret, plus both identities as associated data for every ratchet message
+pub struct X3dhOutput {
+    pub shared_secret: Zeroizing<[u8; 32]>,
+    pub associated_data: Vec<u8>,         // IK_A || signing_A || IK_B || signing_B
+}
+
+// 🪪 IDENTITY KEYS
+
+/// A user's long-term identity key pair
+#[derive(Clone)]
+pub struct IdentityKeyPair {
+    signing_key: SigningKey,
+    dh_key: StaticSecret,
+}
+
+impl IdentityKeyPair {
+    pub fn generate() -> Self {
+        Self {
+            signing_key: SigningKey::generate(&mut OsRng),
+            dh_key: StaticSecret::random_from_rng(OsRng),
+        }
+    }
+
+    /// The X25519 identity key, base64
+    pub fn identity_key(&self) -> String {
+        encode_key(PublicKey::from(&self.dh_key).as_bytes())
+    }
+
+    /// The Ed25519 identity key, base64
+    pub fn signing_key(&self) -> String {
+        encode_key(self.signing_key.verifying_key().as_bytes())
+    }
+
+    /// Sign a prekey, binding it to this identity
+    pub fn sign_prekey(&self, key_id: u32, public_key: &PublicKey) -> SignedPrekey {
+        let identity_key = PublicKey::from(&self.dh_key);
+        let message = signed_prekey_message(&identity_key, key_id, public_key);
+        SignedPrekey {
+            key_id,
+            public_key: encode_key(public_key.as_bytes()),
+            signature: general_purpose::STANDARD.encode(self.signing_key.sign(&message).to_bytes()),
+        }
+    }
+}
+
+fn encode_key(key: &[u8; 32]) -> String {
+    general_purpose::STANDARD.encode(key)
+}
+
+// 🚀 INITIATOR
+
+/// Derive a shared secret from a claimed bundle
+/// Returns it with the header to send alongside the first message. Fails unless
+/// the bundle's signed prekey was signed by its identity.
+pub fn initiate(
+    identity: &IdentityKeyPair,
+    bundle: &PrekeyBundle,
+) -> Result<(X3dhOutput, InitialMessageHeader), EncryptionError> {
+    verify_signed_prekey(&bundle.identity_key, &bundle.signing_key, &bundle.signed_prekey)?;
+    let their_identity = decode_public_key(&bundle.identity_key)?;
+    let their_signed_prekey = decode_public_key(&bundle.signed_prekey.public_key)?;
+    let their_one_time_prekey = bundle.one_time_prekey.as_ref()
+        .map(|prekey| decode_public_key(&prekey.public_key))
+        .transpose()?;
+
+    let ephemeral = StaticSecret::random_from_rng(OsRng);
+    let mut dh_outputs = vec![
+        diffie_hellman(&identity.dh_key, &their_signed_prekey)?,
+        diffie_hellman(&ephemeral, &their_identity)?,
+        diffie_hellman(&ephemeral, &their_signed_prekey)?,
+    ];
+    if let Some(one_time_prekey) = &their_one_time_prekey {
+        dh_outputs.push(diffie_hellman(&ephemeral, one_time_prekey)?);
+    }
+
+    let output = X3dhOutput {
+        shared_secret: derive_shared_secret(&dh_outputs)?,
+        associated_data: associated_data(
+            &identity.identity_key(),
+            &identity.signing_key(),
+            &bundle.identity_key,
+            &bundle.signing_key,
+        )?,
+    };
+    let header = InitialMessageHeader {
+        identity_key: identity.identity_key(),
+        signing_key: identity.signing_key(),
+        ephemeral_key: encode_key(PublicKey::from(&ephemeral).as_bytes()),
+        signed_prekey_id: bundle.signed_prekey.key_id,
+        one_time_prekey_id: bundle.one_time_prekey.as_ref().map(|prekey| prekey.key_id),
+    };
+    Ok((output, header))
+}
+
+// 📥 RESPONDER
+
+/// A client's own prekey secrets
+/// Old signed prekeys are kept for initial messages still in flight; one-time
+/// prekeys are forgotten the moment they're used, so each works exactly once.
+pub struct PrekeyKeyring {
+    identity: IdentityKeyPair,
+    signed_prekeys: HashMap<u32, StaticSecret>,
+    current_signed_prekey: SignedPrekey,
+    one_time_prekeys: HashMap<u32, StaticSecret>,
+    next_key_id: u32,
+}
+
+impl PrekeyKeyring {
+    pub fn new(identity: IdentityKeyPair) -> Self {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let current_signed_prekey = identity.sign_prekey(1, &PublicKey::from(&secret));
+        Self {
+            identity,
+            signed_prekeys: HashMap::from([(1, secret)]),
+            current_signed_prekey,
+            one_time_prekeys: HashMap::new(),
+            next_key_id: 2,
+        }
+    }
+
+    pub fn identity(&self) -> &IdentityKeyPair {
+        &self.identity
+    }
+
+    /// Replace the published signed prekey
+    pub fn rotate_signed_prekey(&mut self) -> SignedPrekey {
+        let key_id = self.take_key_id();
+        let secret = StaticSecret::random_from_rng(OsRng);
+        self.current_signed_prekey = self.identity.sign_prekey(key_id, &PublicKey::from(&secret));
+        self.signed_prekeys.insert(key_id, secret);
+        self.current_signed_prekey.clone()
+    }
+
+    /// Everything to publish, with `one_time_count` fresh one-time prekeys
+    pub fn upload(&mut self, one_time_count: usize) -> PrekeyUpload {
+        let one_time_prekeys = (0..one_time_count)
+            .map(|_| {
+                let key_id = self.take_key_id();
+                let secret = StaticSecret::random_from_rng(OsRng);
+                let prekey = OneTimePrekey { key_id, public_key: encode_key(PublicKey::from(&secret).as_bytes()) };
+                self.one_time_prekeys.insert(key_id, secret);
+                prekey
+            })
+            .collect();
+
+        PrekeyUpload {
+            identity_key: self.identity.identity_key(),
+            signing_key: self.identity.signing_key(),
+            signed_prekey: self.current_signed_prekey.clone(),
+            one_time_prekeys,
+        }
+    }
+
+    /// Derive the initiator's secret from their first message header
+    /// Also returns the signed prekey secret, which starts the responder's ratchet.
+    pub fn respond(&mut self, header: &InitialMessageHeader) -> Result<(X3dhOutput, StaticSecret), EncryptionError> {
+        let signed_prekey = self.signed_prekeys.get(&header.signed_prekey_id).cloned()
+            .ok_or_else(|| EncryptionError::InvalidKeyFormat(format!("Unknown signed prekey {}", header.signed_prekey_id)))?;
+        let one_time_prekey = header.one_time_prekey_id
+            .map(|key_id| {
+                self.one_time_prekeys.remove(&key_id)
+                    .ok_or_else(|| EncryptionError::InvalidKeyFormat(format!("One-time prekey {} is unknown or used", key_id)))
+            })
+            .transpose()?;
+        let their_identity = decode_public_key(&header.identity_key)?;
+        let their_ephemeral = decode_public_key(&header.ephemeral_key)?;
+
+        let mut dh_outputs = vec![
+            diffie_hellman(&signed_prekey, &their_identity)?,
+            diffie_hellman(&self.identity.dh_key, &their_ephemeral)?,
+            diffie_hellman(&signed_prekey, &their_ephemeral)?,
+        ];
+        if let Some(one_time_prekey) = &one_time_prekey {
+            dh_outputs.push(diffie_hellman(one_time_prekey, &their_ephemeral)?);
+        }
+
+        let output = X3dhOutput {
+            shared_secret: derive_shared_secret(&dh_outputs)?,
+            associated_data: associated_data(
+                &header.identity_key,
+                &header.signing_key,
+                &self.identity.identity_key(),
+                &self.identity.signing_key(),
+            )?,
+        };
+        Ok((output, signed_prekey))
+    }
+
+    fn take_key_id(&mut self) -> u32 {
+        let key_id = self.next_key_id;
+        self.next_key_id += 1;
+        key_id
+    }
+}
+
+// 🔧 KEY DERIVATION
+
+/// X25519, refusing low-order points that would force a known output
+pub(crate) fn diffie_hellman(secret: &StaticSecret, public: &PublicKey) -> Result<Zeroizing<[u8; 32]>, EncryptionError> {
+    let shared = secret.diffie_hellman(public);
+    if !shared.was_contributory() {
+        return Err(EncryptionError::InvalidKeyFormat("Non-contributory X25519 public key".to_string()));
+    }
+    Ok(Zeroizing::new(shared.to_bytes()))
+}
+
+fn derive_shared_secret(dh_outputs: &[Zeroizing<[u8; 32]>]) -> Result<Zeroizing<[u8; 32]>, EncryptionError> {
+    let mut input = Zeroizing::new(vec![0xFF; 32]);
+    for output in dh_outputs {
+        input.extend_from_slice(output.as_ref());
+    }
+
+    let mut secret = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(&[0u8; 32]), &input)
+        .expand(X3DH_INFO, secret.as_mut())
+        .map_err(|e| EncryptionError::KeyGenerationFailed(e.to_string()))?;
+    Ok(secret)
+}
+
+fn associated_data(
+    initiator_identity: &str,
+    initiator_signing: &str,
+    responder_identity: &str,
+    responder_signing: &str,
+) -> Result<Vec<u8>, EncryptionError> {
+    let mut data = Vec::with_capacity(4 * 32);
+    for key in [initiator_identity, initiator_signing, responder_identity, responder_signing] {
+        data.extend_from_slice(&decode_key_bytes(key)?);
+    }
+    Ok(data)
+}
@@ -36,6 +36,9 @@ pub enum AppError {
     #[error("Token expired")]
     TokenExpired,
     
+    #[error("Forbidden: {message}")]
+    Forbidden { message: String },
+    
     // 📡 WebSocket errors
     #[error("WebSocket error: {message}")]
     WebSocket { message: String },
@@ -68,6 +71,12 @@ pub enum AppError {
     #[error("Not found: {resource}")]
     NotFound { resource: String },
     
+    #[error("Payload too large (limit is {limit} bytes)")]
+    PayloadTooLarge { limit: u64 },
+    
+    #[error("Conflict: {message}")]
+    Conflict { message: String },
+    
     // 🔐 Encryption errors
     #[error("Encryption error: {message}")]
     Encryption { message: String },
@@ -122,6 +131,14 @@ impl actix_web::ResponseError for AppError {
                 })
             }
             
+            // 🚫 Authenticated but not allowed -> 403 Forbidden
+            AppError::Forbidden { .. } => {
+                HttpResponse::Forbidden().json(ErrorResponse {
+                    error: "forbidden".to_string(),
+                    message: self.to_string(),
+                })
+            }
+            
             // 🔍 Not found errors -> 404 Not Found
             AppError::NotFound { .. } => {
                 HttpResponse::NotFound().json(ErrorResponse {
@@ -130,6 +147,22 @@ impl actix_web::ResponseError for AppError {
                 })
             }
             
+            // 📦 Upload over the size limit -> 413 Payload Too Large
+            AppError::PayloadTooLarge { .. } => {
+                HttpResponse::PayloadTooLarge().json(ErrorResponse {
+                    error: "payload_too_large".to_string(),
+                    message: self.to_string(),
+                })
+            }
+            
+            // ⏳ Not available in its current state -> 409 Conflict
+            AppError::Conflict { .. } => {
+                HttpResponse::Conflict().json(ErrorResponse {
+                    error: "conflict".to_string(),
+                    message: self.to_string(),
+                })
+            }
+            
             // 📝 Bad request errors -> 400 Bad Request
             AppError::BadRequest { .. } | 
             AppError::InvalidMessage { .. } => {
@@ -158,8 +191,14 @@ impl actix_web::ResponseError for AppError {
             AppError::InvalidToken { .. } | 
             AppError::TokenExpired => StatusCode::UNAUTHORIZED,
             
+            AppError::Forbidden { .. } => StatusCode::FORBIDDEN,
+            
             AppError::NotFound { .. } => StatusCode::NOT_FOUND,
             
+            AppError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            
             AppError::BadRequest { .. } | 
             AppError::InvalidMessage { .. } => StatusCode::BAD_REQUEST,
             
@@ -188,6 +227,13 @@ impl AppError {
         }
     }
     
+    // 🚫 Forbidden helper
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        AppError::Forbidden {
+            message: message.into(),
+        }
+    }
+    
     // 📡 WebSocket helper
     pub fn websocket_error(message: impl Into<String>) -> Self {
         AppError::WebSocket {
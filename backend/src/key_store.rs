@@ -0,0 +1,113 @@
+// 🔑 KEY STORE MODULE
+// Where users' key pairs live. The key endpoints only talk to the `KeyStore`
+// trait: Supabase in production, an in-memory store in tests so rotations and
+// their notifications can run offline.
+
+use async_trait::async_trait;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::database::EncryptionKey;
+use crate::errors::AppResult;
+use crate::supabase_api::SupabaseClient;
+
+/// Storage for users' key pairs, private keys always sealed by the client
+#[async_trait]
+pub trait KeyStore: Send + Sync {
+    /// All of a user's key pairs, oldest first
+    async fn key_pairs(&self, user_id: Uuid, access_token: &str) -> AppResult<Vec<EncryptionKey>>;
+
+    /// Add a user's next key pair, expiring the version it replaces (if any)
+    async fn add_key_pair(&self, key: &EncryptionKey, replaces: Option<i32>, access_token: &str) -> AppResult<()>;
+
+    /// Replace the sealed private keys of existing key pairs, all or none
+    async fn reseal_key_pairs(&self, user_id: Uuid, keys: &[EncryptionKey], access_token: &str) -> AppResult<()>;
+
+    /// Everyone who shares a conversation with the user
+    async fn contacts(&self, user_id: Uuid, access_token: &str) -> AppResult<HashSet<Uuid>>;
+}
+
+// 🗄️ SUPABASE STORE
+// The (user_id, key_version) unique constraint turns a concurrent rotation to the
+// same version into an error rather than a second key.
+#[async_trait]
+impl KeyStore for SupabaseClient {
+    async fn key_pairs(&self, user_id: Uuid, access_token: &str) -> AppResult<Vec<EncryptionKey>> {
+        self.get_encryption_keys(user_id, access_token).await
+    }
+
+    async fn add_key_pair(&self, key: &EncryptionKey, replaces: Option<i32>, access_token: &str) -> AppResult<()> {
+        self.store_encryption_key(key, replaces, access_token).await
+    }
+
+    async fn reseal_key_pairs(&self, user_id: Uuid, keys: &[EncryptionKey], access_token: &str) -> AppResult<()> {
+        self.reseal_private_keys(user_id, keys, access_token).await
+    }
+
+    async fn contacts(&self, user_id: Uuid, access_token: &str) -> AppResult<HashSet<Uuid>> {
+        Ok(self.get_user_conversations(user_id, access_token).await?
+            .into_iter()
+            .flat_map(|summary| summary.participants)
+            .filter(|participant| *participant != user_id)
+            .collect())
+    }
+}
+
+// 🧪 IN-MEMORY STORE
+// Mirrors the database: versions are unique per user, and contacts are added by hand.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryKeyStore {
+    keys: std::sync::Mutex<Vec<EncryptionKey>>,
+    contacts: std::sync::Mutex<std::collections::HashMap<Uuid, HashSet<Uuid>>>,
+}
+
+#[cfg(test)]
+impl MemoryKeyStore {
+    /// Put two users in a conversation together
+    pub fn add_contact(&self, user_id: Uuid, other_user_id: Uuid) {
+        let mut contacts = self.contacts.lock().unwrap();
+        contacts.entry(user_id).or_default().insert(other_user_id);
+        contacts.entry(other_user_id).or_default().insert(user_id);
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl KeyStore for MemoryKeyStore {
+    async fn key_pairs(&self, user_id: Uuid, _access_token: &str) -> AppResult<Vec<EncryptionKey>> {
+        let mut keys: Vec<EncryptionKey> = self.keys.lock().unwrap().iter()
+            .filter(|key| key.user_id == user_id)
+            .cloned()
+            .collect();
+        keys.sort_by_key(|key| key.key_version);
+        Ok(keys)
+    }
+
+    async fn add_key_pair(&self, key: &EncryptionKey, replaces: Option<i32>, _access_token: &str) -> AppResult<()> {
+        let mut keys = self.keys.lock().unwrap();
+        if keys.iter().any(|existing| existing.user_id == key.user_id && existing.key_version == key.key_version) {
+            return Err(crate::errors::AppError::Conflict { message: format!("Key version {} already exists", key.key_version) });
+        }
+        if let Some(replaced) = keys.iter_mut().find(|existing| existing.user_id == key.user_id && Some(existing.key_version) == replaces) {
+            replaced.expires_at = Some(chrono::Utc::now());
+        }
+        keys.push(key.clone());
+        Ok(())
+    }
+
+    async fn reseal_key_pairs(&self, user_id: Uuid, resealed: &[EncryptionKey], _access_token: &str) -> AppResult<()> {
+        let mut keys = self.keys.lock().unwrap();
+        for key in resealed {
+            if let Some(existing) = keys.iter_mut().find(|existing| existing.user_id == user_id && existing.key_version == key.key_version) {
+                existing.encrypted_private_key = key.encrypted_private_key.clone();
+                existing.kdf_params = key.kdf_params.clone();
+            }
+        }
+        Ok(())
+    }
+
+    async fn contacts(&self, user_id: Uuid, _access_token: &str) -> AppResult<HashSet<Uuid>> {
+        Ok(self.contacts.lock().unwrap().get(&user_id).cloned().unwrap_or_default())
+    }
+}
@@ -0,0 +1,88 @@
+/*
+🔑 KEY VERIFICATION MODULE
+==========================
+
+Safety numbers let two users check that the server handed them each other's
+real public keys (see `EncryptionService::safety_number`).
+
+FLOW:
+1. Either user asks for the pair's safety number: GET /api/v1/users/{userId}/safety-number
+2. They compare the digits in person or scan each other's QR payload;
+   both sides always get the same values
+3. When someone rotates their key pair, their conversation partners get an
+   `identity_key_changed` event, since any number they verified is now stale
+*/
+
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::auth::auth::{AccessToken, Claims};
+use crate::encryption::EncryptionService;
+use crate::errors::{AppError, AppResult};
+use crate::key_store::KeyStore;
+use crate::supabase_api::SupabaseClient;
+use crate::websocket::{OutgoingMessage, SessionManager};
+
+// 🔢 SAFETY NUMBER RESPONSE
+#[derive(Debug, Serialize)]
+pub struct SafetyNumberResponse {
+    pub user_id: Uuid,               // The contact
+    pub key_version: i32,            // Caller's key the number was computed with
+    pub contact_key_version: i32,    // Contact's key the number was computed with
+    pub safety_number: String,
+    pub qr_payload: String,
+}
+
+// 🔢 SAFETY NUMBER ENDPOINT
+// GET /api/v1/users/{userId}/safety-number
+pub async fn get_safety_number(
+    path: web::Path<Uuid>,
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    supabase_client: web::Data<SupabaseClient>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+    let contact_id = path.into_inner();
+    if contact_id == user_id {
+        return Err(AppError::bad_request("A safety number needs two different users"));
+    }
+
+    // 🔐 Each side's current key, from the public view both users read
+    let own_key = supabase_client.get_public_key(user_id, token.as_str()).await?
+        .ok_or_else(|| AppError::NotFound { resource: format!("encryption key for user {}", user_id) })?;
+    let contact_key = supabase_client.get_public_key(contact_id, token.as_str()).await?
+        .ok_or_else(|| AppError::NotFound { resource: format!("encryption key for user {}", contact_id) })?;
+
+    let service = EncryptionService::new();
+    let safety_number = service.safety_number(
+        user_id,
+        &service.import_public_key_pem(&own_key.public_key)?,
+        contact_id,
+        &service.import_public_key_pem(&contact_key.public_key)?,
+    )?;
+
+    Ok(HttpResponse::Ok().json(SafetyNumberResponse {
+        user_id: contact_id,
+        key_version: own_key.key_version,
+        contact_key_version: contact_key.key_version,
+        safety_number: safety_number.numeric,
+        qr_payload: safety_number.qr_payload,
+    }))
+}
+
+// 📢 Tell everyone who shares a conversation with `user_id` that their key changed
+pub async fn notify_identity_key_changed(
+    key_store: &dyn KeyStore,
+    session_manager: &SessionManager,
+    user_id: Uuid,
+    key_version: i32,
+    access_token: &str,
+) -> AppResult<()> {
+    let partners = key_store.contacts(user_id, access_token).await?;
+
+    session_manager.send_to_users(&partners, None, OutgoingMessage::IdentityKeyChanged { user_id, key_version });
+    log::info!("🔑 Notified {} contact(s) that user {} rotated to key version {}", partners.len(), user_id, key_version);
+    Ok(())
+}
@@ -0,0 +1,372 @@
+/*
+🔑 KEYS MODULE
+==============
+
+Users' long-term key pairs (`encryption_keys`). Clients generate them and seal
+the private half under their passphrase (`EncryptionService::seal_private_key`)
+before uploading, so the server only ever holds public keys and sealed blobs.
+
+FLOW:
+1. A client publishes its first key pair, or rotates to a new one:
+   POST /api/v1/keys/rotate
+   The sealed key is bound to its version, so the client names the version it
+   sealed for: 1, then one more than the current key. The replaced key pair is
+   expired but kept, so older messages stay readable.
+2. After a rotation, everyone sharing a conversation with the user gets an
+   `identity_key_changed` event (see `key_verification`)
+3. A new device fetches the sealed keys and unseals them locally:
+   GET /api/v1/keys/private
+4. A passphrase change re-seals every key on the client and uploads them in one go:
+   PUT /api/v1/keys/private
+   The key pairs themselves don't change, so nothing needs re-wrapping.
+*/
+
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::auth::{AccessToken, Claims};
+use crate::database::EncryptionKey;
+use crate::encryption::{decode_sealed_private_key, EncryptionService, KdfParams, KeyWrapAlgorithm};
+use crate::errors::{AppError, AppResult};
+use crate::key_store::KeyStore;
+use crate::key_verification::notify_identity_key_changed;
+use crate::websocket::SessionManager;
+
+// 📤 KEY PAIR UPLOAD
+#[derive(Debug, Deserialize)]
+pub struct KeyPairUpload {
+    pub key_version: i32,              // The version the private key was sealed for
+    pub public_key: String,            // PEM
+    pub encrypted_private_key: String, // Sealed by the client, never the plain key
+    pub kdf_params: KdfParams,         // How the client derived the sealing key
+    pub algorithm: String,             // Must be the current `KeyWrapAlgorithm`
+}
+
+// 🔁 KEY ROTATION RESPONSE
+#[derive(Debug, Serialize)]
+pub struct KeyRotationResponse {
+    pub key_version: i32,
+}
+
+// 🔒 ONE RE-SEALED PRIVATE KEY
+#[derive(Debug, Deserialize)]
+pub struct ResealedKey {
+    pub key_version: i32,
+    pub encrypted_private_key: String, // Sealed under the new passphrase
+    pub kdf_params: KdfParams,         // Fresh parameters, salt included
+}
+
+// 🔍 Reject key pairs no client could use; the sealed half can only be checked for shape
+fn validate_key_pair(upload: &KeyPairUpload) -> AppResult<()> {
+    let algorithm: KeyWrapAlgorithm = upload.algorithm.parse()
+        .map_err(|e| AppError::bad_request(format!("Invalid key algorithm: {}", e)))?;
+    if algorithm != KeyWrapAlgorithm::CURRENT {
+        return Err(AppError::bad_request(format!("New key pairs must use {}", KeyWrapAlgorithm::CURRENT.as_str())));
+    }
+    EncryptionService::new().import_public_key_pem(&upload.public_key)
+        .map_err(|e| AppError::bad_request(format!("Invalid public key: {}", e)))?;
+    upload.kdf_params.validate()
+        .map_err(|e| AppError::bad_request(format!("Invalid key derivation parameters: {}", e)))?;
+    decode_sealed_private_key(&upload.encrypted_private_key)
+        .map_err(|e| AppError::bad_request(format!("Invalid sealed private key: {}", e)))?;
+    Ok(())
+}
+
+async fn rotate_key_pair(
+    store: &dyn KeyStore,
+    session_manager: &SessionManager,
+    user_id: Uuid,
+    upload: KeyPairUpload,
+    access_token: &str,
+) -> AppResult<KeyRotationResponse> {
+    validate_key_pair(&upload)?;
+
+    let current = store.key_pairs(user_id, access_token).await?
+        .last()
+        .map(|key| key.key_version);
+    let expected = current.map_or(1, |key_version| key_version + 1);
+    if upload.key_version != expected {
+        return Err(AppError::Conflict { message: format!("Expected key version {}", expected) });
+    }
+
+    let key = EncryptionKey {
+        id: Uuid::new_v4(),
+        user_id,
+        encrypted_private_key: upload.encrypted_private_key,
+        kdf_params: upload.kdf_params,
+        public_key: upload.public_key,
+        key_version: upload.key_version,
+        algorithm: upload.algorithm,
+        created_at: Utc::now(),
+        expires_at: None,
+    };
+    store.add_key_pair(&key, current, access_token).await?;
+
+    // A first key pair replaces nothing, so there is no verified key to go stale.
+    // The rotation is already stored, so a failed notice must not fail the request:
+    // a retry would only get a Conflict.
+    if current.is_some() {
+        if let Err(e) = notify_identity_key_changed(store, session_manager, user_id, key.key_version, access_token).await {
+            log::warn!("Key rotation for user {} stored, but notifying contacts failed: {}", user_id, e);
+        }
+    }
+    Ok(KeyRotationResponse { key_version: key.key_version })
+}
+
+async fn reseal_private_keys(
+    store: &dyn KeyStore,
+    user_id: Uuid,
+    resealed: Vec<ResealedKey>,
+    access_token: &str,
+) -> AppResult<Vec<EncryptionKey>> {
+    let mut keys = store.key_pairs(user_id, access_token).await?;
+    if keys.is_empty() {
+        return Err(AppError::NotFound { resource: format!("encryption keys for user {}", user_id) });
+    }
+    // Every key moves to the new passphrase, or the user would need both to unlock
+    if resealed.len() != keys.len() {
+        return Err(AppError::bad_request(format!("Expected {} re-sealed keys", keys.len())));
+    }
+
+    for key in &mut keys {
+        let update = resealed.iter().find(|update| update.key_version == key.key_version)
+            .ok_or_else(|| AppError::bad_request(format!("Key version {} was not re-sealed", key.key_version)))?;
+        update.kdf_params.validate()
+            .map_err(|e| AppError::bad_request(format!("Invalid key derivation parameters: {}", e)))?;
+        if update.kdf_params.salt == key.kdf_params.salt {
+            return Err(AppError::bad_request("Re-sealed keys need a fresh salt"));
+        }
+        decode_sealed_private_key(&update.encrypted_private_key)
+            .map_err(|e| AppError::bad_request(format!("Invalid sealed private key: {}", e)))?;
+
+        key.encrypted_private_key = update.encrypted_private_key.clone();
+        key.kdf_params = update.kdf_params.clone();
+    }
+
+    store.reseal_key_pairs(user_id, &keys, access_token).await?;
+    Ok(keys)
+}
+
+// 🔁 ROTATE KEY PAIR ENDPOINT
+// POST /api/v1/keys/rotate
+pub async fn rotate_keys(
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    key_store: web::Data<dyn KeyStore>,
+    session_manager: web::Data<SessionManager>,
+    upload: web::Json<KeyPairUpload>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+
+    let rotated = rotate_key_pair(key_store.get_ref(), &session_manager, user_id, upload.into_inner(), token.as_str()).await?;
+
+    log::info!("🔑 User {} published key version {}", user_id, rotated.key_version);
+    Ok(HttpResponse::Ok().json(rotated))
+}
+
+// 🔒 GET SEALED KEYS ENDPOINT
+// GET /api/v1/keys/private
+// Every version, oldest first, so history from before a rotation still decrypts
+pub async fn get_private_keys(
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    key_store: web::Data<dyn KeyStore>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+
+    let keys = key_store.key_pairs(user_id, token.as_str()).await?;
+    Ok(HttpResponse::Ok().json(keys))
+}
+
+// 🔒 RE-SEAL KEYS ENDPOINT
+// PUT /api/v1/keys/private
+pub async fn put_private_keys(
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    key_store: web::Data<dyn KeyStore>,
+    resealed: web::Json<Vec<ResealedKey>>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+
+    let keys = reseal_private_keys(key_store.get_ref(), user_id, resealed.into_inner(), token.as_str()).await?;
+
+    log::info!("🔒 User {} re-sealed {} private key(s)", user_id, keys.len());
+    Ok(HttpResponse::Ok().json(keys))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix::{Actor, Handler};
+    use base64::{engine::general_purpose, Engine as _};
+    use std::collections::HashSet;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use crate::key_store::MemoryKeyStore;
+    use crate::websocket::{OutgoingMessage, SendToClient};
+
+    const TOKEN: &str = "test-token";
+
+    /// A connected device that forwards every frame it's sent
+    struct RecordingSession(mpsc::UnboundedSender<OutgoingMessage>);
+
+    impl Actor for RecordingSession {
+        type Context = actix::Context<Self>;
+    }
+
+    impl Handler<SendToClient> for RecordingSession {
+        type Result = ();
+
+        fn handle(&mut self, msg: SendToClient, _ctx: &mut Self::Context) {
+            let _ = self.0.send(msg.message);
+        }
+    }
+
+    fn connect(session_manager: &mut SessionManager, user_id: Uuid) -> mpsc::UnboundedReceiver<OutgoingMessage> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let addr = RecordingSession(sender).start();
+        session_manager.add_session(user_id, Uuid::new_v4(), addr.recipient());
+        receiver
+    }
+
+    /// What a client uploads; the server can't open the sealed key, so any well-formed blob will do
+    fn upload(key_version: i32) -> KeyPairUpload {
+        let service = EncryptionService::new();
+        let key_pair = service.generate_key_pair(key_version as u32).unwrap();
+        KeyPairUpload {
+            key_version,
+            public_key: service.export_public_key_pem(&key_pair.public_key).unwrap(),
+            encrypted_private_key: general_purpose::STANDARD.encode([7u8; 64]),
+            kdf_params: KdfParams::generate(),
+            algorithm: KeyWrapAlgorithm::CURRENT.as_str().to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotation_adds_versions_and_expires_the_old_key() {
+        let store = MemoryKeyStore::default();
+        let session_manager = SessionManager::new();
+        let user_id = Uuid::new_v4();
+
+        // Versions must follow on from the current key
+        assert!(rotate_key_pair(&store, &session_manager, user_id, upload(2), TOKEN).await.is_err());
+        let first = rotate_key_pair(&store, &session_manager, user_id, upload(1), TOKEN).await.unwrap();
+        assert_eq!(first.key_version, 1);
+        assert!(rotate_key_pair(&store, &session_manager, user_id, upload(1), TOKEN).await.is_err());
+
+        let second = rotate_key_pair(&store, &session_manager, user_id, upload(2), TOKEN).await.unwrap();
+        assert_eq!(second.key_version, 2);
+
+        let keys = store.key_pairs(user_id, TOKEN).await.unwrap();
+        assert_eq!(keys.iter().map(|key| key.key_version).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(keys[0].expires_at.is_some());
+        assert!(keys[1].expires_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_passphrase_change_reseals_every_key() {
+        let store = MemoryKeyStore::default();
+        let session_manager = SessionManager::new();
+        let user_id = Uuid::new_v4();
+        let reseal = |key_version| ResealedKey {
+            key_version,
+            encrypted_private_key: general_purpose::STANDARD.encode([9u8; 64]),
+            kdf_params: KdfParams::generate(),
+        };
+
+        assert!(reseal_private_keys(&store, user_id, vec![reseal(1)], TOKEN).await.is_err());
+        rotate_key_pair(&store, &session_manager, user_id, upload(1), TOKEN).await.unwrap();
+        rotate_key_pair(&store, &session_manager, user_id, upload(2), TOKEN).await.unwrap();
+        let before = store.key_pairs(user_id, TOKEN).await.unwrap();
+
+        // Leaving a key behind, reusing its salt, or a malformed blob changes nothing
+        assert!(reseal_private_keys(&store, user_id, vec![reseal(2)], TOKEN).await.is_err());
+        let stale_salt = ResealedKey { kdf_params: before[0].kdf_params.clone(), ..reseal(1) };
+        assert!(reseal_private_keys(&store, user_id, vec![stale_salt, reseal(2)], TOKEN).await.is_err());
+        let malformed = ResealedKey { encrypted_private_key: "AAAA".to_string(), ..reseal(2) };
+        assert!(reseal_private_keys(&store, user_id, vec![reseal(1), malformed], TOKEN).await.is_err());
+        assert_eq!(store.key_pairs(user_id, TOKEN).await.unwrap()[0].encrypted_private_key, before[0].encrypted_private_key);
+
+        reseal_private_keys(&store, user_id, vec![reseal(2), reseal(1)], TOKEN).await.unwrap();
+        let after = store.key_pairs(user_id, TOKEN).await.unwrap();
+        for (old, new) in before.iter().zip(&after) {
+            assert_eq!(old.public_key, new.public_key);
+            assert_ne!(old.encrypted_private_key, new.encrypted_private_key);
+            assert_ne!(old.kdf_params, new.kdf_params);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_rotation_notifies_connected_partners() {
+        let store = MemoryKeyStore::default();
+        let mut session_manager = SessionManager::new();
+        let (alice, bob, carol) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        store.add_contact(alice, bob);
+        let mut bob_frames = connect(&mut session_manager, bob);
+        let mut carol_frames = connect(&mut session_manager, carol);
+
+        rotate_key_pair(&store, &session_manager, alice, upload(1), TOKEN).await.unwrap();
+        rotate_key_pair(&store, &session_manager, alice, upload(2), TOKEN).await.unwrap();
+
+        // Only the rotation is announced, not the first key, and only to contacts
+        let frame = tokio::time::timeout(Duration::from_secs(5), bob_frames.recv()).await.unwrap().unwrap();
+        assert!(matches!(frame, OutgoingMessage::IdentityKeyChanged { user_id, key_version: 2 } if user_id == alice));
+        assert!(tokio::time::timeout(Duration::from_millis(100), carol_frames.recv()).await.is_err());
+    }
+
+    /// A store whose contact lookup is down, so rotation notices can't go out
+    struct NoContacts(MemoryKeyStore);
+
+    #[async_trait::async_trait]
+    impl KeyStore for NoContacts {
+        async fn key_pairs(&self, user_id: Uuid, access_token: &str) -> AppResult<Vec<EncryptionKey>> {
+            self.0.key_pairs(user_id, access_token).await
+        }
+
+        async fn add_key_pair(&self, key: &EncryptionKey, replaces: Option<i32>, access_token: &str) -> AppResult<()> {
+            self.0.add_key_pair(key, replaces, access_token).await
+        }
+
+        async fn reseal_key_pairs(&self, user_id: Uuid, keys: &[EncryptionKey], access_token: &str) -> AppResult<()> {
+            self.0.reseal_key_pairs(user_id, keys, access_token).await
+        }
+
+        async fn contacts(&self, _user_id: Uuid, _access_token: &str) -> AppResult<HashSet<Uuid>> {
+            Err(AppError::Internal { message: "contacts unavailable".to_string() })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotation_succeeds_when_notifying_contacts_fails() {
+        let store = NoContacts(MemoryKeyStore::default());
+        let session_manager = SessionManager::new();
+        let user_id = Uuid::new_v4();
+
+        rotate_key_pair(&store, &session_manager, user_id, upload(1), TOKEN).await.unwrap();
+        let rotated = rotate_key_pair(&store, &session_manager, user_id, upload(2), TOKEN).await.unwrap();
+        assert_eq!(rotated.key_version, 2);
+        assert_eq!(store.key_pairs(user_id, TOKEN).await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_key_pairs_are_validated() {
+        assert!(validate_key_pair(&upload(1)).is_ok());
+
+        let legacy = KeyPairUpload { algorithm: KeyWrapAlgorithm::RsaPkcs1v15.as_str().to_string(), ..upload(1) };
+        assert!(validate_key_pair(&legacy).is_err());
+
+        let malformed = KeyPairUpload { public_key: "not a key".to_string(), ..upload(1) };
+        assert!(validate_key_pair(&malformed).is_err());
+
+        let cheap = KeyPairUpload { kdf_params: KdfParams { iterations: 1, ..KdfParams::generate() }, ..upload(1) };
+        assert!(validate_key_pair(&cheap).is_err());
+
+        let unsealed = KeyPairUpload { encrypted_private_key: "AAAA".to_string(), ..upload(1) };
+        assert!(validate_key_pair(&unsealed).is_err());
+    }
+}
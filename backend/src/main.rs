@@ -22,10 +22,9 @@ ARCHITECTURE OVERVIEW:
 
 // 🔧 EXTERNAL DEPENDENCIES
 // These are the external crates (libraries) we're using
-use actix_web::{web, App, HttpServer, middleware::Logger};
-use actix_web_httpauth::middleware::HttpAuthentication;
+use actix_web::{web, App, HttpServer, middleware::{from_fn, Logger}};
 use actix_cors::Cors;
-use std::env;
+use std::sync::Arc;
 
 // 📦 INTERNAL MODULES  
 // These declare our internal modules - each corresponds to a .rs file
@@ -39,6 +38,14 @@ mod errors;         // Custom error types
 mod supabase_api;   // Supabase HTTP API wrapper (ZERO TRUST)
 mod encryption;     // End-to-end encryption for messages
 mod encrypted_messaging; // Encrypted messaging workflow 
+mod blob_store;     // Pluggable storage for attachment bytes
+mod attachments;    // Attachment upload/download endpoints
+mod media;          // Image thumbnails, blurhash and metadata stripping
+mod prekey_store;   // Pluggable storage for published X3DH prekeys
+mod prekeys;        // Prekey upload and bundle claim endpoints
+mod key_verification; // Safety numbers and key change notices
+mod key_store;      // Pluggable storage for users' sealed key pairs
+mod keys;           // Key pair rotation and sealed private key endpoints
 
 // 🎯 MAIN FUNCTION
 // In Rust, async main requires the #[tokio::main] attribute
@@ -79,6 +86,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // This will keep track of all connected users
     let session_manager = websocket::SessionManager::new();
     
+    // 📎 SETUP ATTACHMENT STORAGE
+    // Handlers only see the `BlobStore` trait; the local filesystem is the first backend
+    let blob_store: Arc<dyn blob_store::BlobStore> =
+        Arc::new(blob_store::LocalBlobStore::new(&config.attachment_storage_dir)?);
+    
+    // 🖼️ RECOVER INTERRUPTED MEDIA JOBS
+    // Thumbnail jobs run in-process, so none survive a restart
+    attachments::fail_interrupted_media_jobs(&supabase_client).await;
+    
+    // 🤝 SETUP PREKEY STORAGE
+    // Published X3DH prekeys live in Supabase, behind the `PrekeyStore` trait
+    let prekey_store: Arc<dyn prekey_store::PrekeyStore> = Arc::new(supabase_client.clone());
+    
+    // 🔑 SETUP KEY PAIR STORAGE
+    // Users' sealed key pairs live in Supabase too, behind the `KeyStore` trait
+    let key_store: Arc<dyn key_store::KeyStore> = Arc::new(supabase_client.clone());
+    
     log::info!("🚀 Starting OChat backend server on {}:{}", 
                config.server_host, config.server_port);
     
@@ -152,46 +176,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             // These are shared across all request handlers
             .app_data(web::Data::new(supabase_client.clone()))   // Supabase API client (ZERO TRUST)
             .app_data(web::Data::new(session_manager.clone()))   // WebSocket sessions
+            .app_data(web::Data::from(blob_store.clone()))       // Attachment bytes
+            .app_data(web::Data::from(prekey_store.clone()))     // Published prekeys
+            .app_data(web::Data::from(key_store.clone()))        // Sealed key pairs
             .app_data(web::Data::new(config.clone()))            // Configuration
             .app_data(web::Data::new(jwt_validator.clone()))     // JWT validator
             // 🛤️ SETUP ROUTES
-            .service(
-                web::scope("/api/v1")
-                    // WebSocket endpoint: ws://localhost:8080/api/v1/ws
-                    .route("/ws", web::get().to(websocket::websocket_handler))
-                    // Health check endpoint: GET /api/v1/health
-                    .route("/health", web::get().to(health_check))
-                    // Test endpoints (no authentication required for testing)
-                    .route("/test", web::get().to(test_endpoint))
-                    .route("/test/send", web::post().to(test_send_message))
-                    .route("/test/conversations/{userId}", web::get().to(test_get_conversations))
-                    .route("/test/messages/{conversationId}", web::get().to(test_get_messages))
-                    
-                    // 🧪 TEMPORARY: Non-authenticated users endpoint for testing
-                    // 🔰 BEGINNER EXPLANATION: Why do we need this?
-                    // =====================================================
-                    // Authentication can be complex to set up initially.
-                    // This endpoint lets us test the basic functionality first,
-                    // then add authentication once everything else works.
-                    //
-                    // 🚨 IMPORTANT: Remove this in production!
-                    .route("/test/users", web::get().to(test_get_users))
-                    
-                    // Message-related endpoints (NO AUTH FOR NOW)
-                    .configure(messages::configure_routes)
-                    
-                    // 🚨 ALL ENDPOINTS WITHOUT AUTHENTICATION (TEMPORARY!)
-                    // Removing all JWT middleware so everything works immediately
-                    // User-related endpoints (NO AUTH REQUIRED)
-                    .configure(users::configure_routes)
-                    
-                    // Conversation endpoints (NO AUTH REQUIRED)
-                    .service(
-                        web::scope("/conversations")
-                            .route("/create", web::post().to(messages::create_conversation))
-                            .route("/{userId}", web::get().to(messages::get_conversations_by_user))
-                    )
-            )
+            .service(api_scope())
     })
     .bind("0.0.0.0:8080")?
     .run()
@@ -200,6 +191,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+// 🛤️ API ROUTES
+// Every route below requires a valid bearer token unless it is listed in
+// `auth::route_policy::PUBLIC_ROUTES` (health check and the WebSocket upgrade,
+// which verifies its own token from the query string).
+fn api_scope() -> actix_web::Scope<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse<impl actix_web::body::MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let scope = web::scope("/api/v1")
+        .wrap(from_fn(auth::route_policy::require_auth))
+        // WebSocket endpoint: ws://localhost:8080/api/v1/ws
+        .route("/ws", web::get().to(websocket::websocket_handler))
+        // Health check endpoint: GET /api/v1/health
+        .route("/health", web::get().to(health_check))
+        // Message-related endpoints
+        .configure(messages::configure_routes)
+        // Attachment downloads
+        .configure(attachments::configure_routes)
+        // User-related endpoints
+        .configure(users::configure_routes)
+        // Prekey distribution for 1:1 sessions
+        .configure(prekeys::configure_routes)
+        // Conversation endpoints
+        .service(
+            web::scope("/conversations")
+                .route("/create", web::post().to(messages::create_conversation))
+                .route("/{userId}", web::get().to(messages::get_conversations_by_user))
+        );
+    
+    // 🧪 Dummy endpoints only exist in builds with `--features test-routes`
+    #[cfg(feature = "test-routes")]
+    let scope = scope.configure(configure_test_routes);
+    
+    scope
+}
+
 // 🏥 HEALTH CHECK ENDPOINT
 // This is a simple endpoint to check if the server is running
 // RUST CONCEPT: Result<T, E> is Rust's way of handling errors
@@ -213,7 +245,18 @@ async fn health_check() -> Result<actix_web::HttpResponse, actix_web::Error> {
 }
 
 // 🧪 TEST ENDPOINTS (No authentication required)
-// These endpoints are for testing the Flutter app connection
+// These endpoints are for testing the Flutter app connection.
+// They are compiled only with `--features test-routes` and never ship by default.
+#[cfg(feature = "test-routes")]
+fn configure_test_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/test", web::get().to(test_endpoint))
+        .route("/test/send", web::post().to(test_send_message))
+        .route("/test/conversations/{userId}", web::get().to(test_get_conversations))
+        .route("/test/messages/{conversationId}", web::get().to(test_get_messages))
+        .route("/test/users", web::get().to(test_get_users));
+}
+
+#[cfg(feature = "test-routes")]
 
 async fn test_endpoint() -> Result<actix_web::HttpResponse, actix_web::Error> {
     Ok(actix_web::HttpResponse::Ok().json(serde_json::json!({
@@ -222,6 +265,7 @@ async fn test_endpoint() -> Result<actix_web::HttpResponse, actix_web::Error> {
     })))
 }
 
+#[cfg(feature = "test-routes")]
 async fn test_send_message(request: actix_web::web::Json<serde_json::Value>) -> Result<actix_web::HttpResponse, actix_web::Error> {
     Ok(actix_web::HttpResponse::Ok().json(serde_json::json!({
         "message": {
@@ -236,6 +280,7 @@ async fn test_send_message(request: actix_web::web::Json<serde_json::Value>) ->
     })))
 }
 
+#[cfg(feature = "test-routes")]
 async fn test_get_conversations(path: actix_web::web::Path<String>) -> Result<actix_web::HttpResponse, actix_web::Error> {
     let user_id = path.into_inner();
     Ok(actix_web::HttpResponse::Ok().json(serde_json::json!({
@@ -254,6 +299,7 @@ async fn test_get_conversations(path: actix_web::web::Path<String>) -> Result<ac
     })))
 }
 
+#[cfg(feature = "test-routes")]
 async fn test_get_messages(path: actix_web::web::Path<String>) -> Result<actix_web::HttpResponse, actix_web::Error> {
     let conversation_id = path.into_inner();
     Ok(actix_web::HttpResponse::Ok().json(serde_json::json!({
@@ -283,6 +329,7 @@ async fn test_get_messages(path: actix_web::web::Path<String>) -> Result<actix_w
 /// 
 /// TESTING URLS:
 /// - GET https://your-ngrok-url.ngrok-free.app/api/v1/test/users
+#[cfg(feature = "test-routes")]
 async fn test_get_users() -> Result<actix_web::HttpResponse, actix_web::Error> {
     Ok(actix_web::HttpResponse::Ok().json(serde_json::json!({
         "users": [
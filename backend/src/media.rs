@@ -0,0 +1,444 @@
+// 🖼️ MEDIA MODULE
+// Image processing for attachments: metadata stripping, thumbnails and blurhash
+// placeholders. Everything here is synchronous and CPU-bound, so callers run it
+// on the blocking thread pool (see attachments::spawn_media_job).
+
+use image::codecs::jpeg::JpegEncoder;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits, RgbImage};
+use std::io::Cursor;
+
+use crate::errors::{AppError, AppResult};
+
+// 📐 Thumbnail sizes as (name, longest edge in pixels), smallest first
+// Images already within a size don't get that thumbnail; clients use the original
+pub const THUMBNAIL_SIZES: &[(&str, u32)] = &[("small", 160), ("medium", 640)];
+
+const THUMBNAIL_QUALITY: u8 = 80;
+const MAX_IMAGE_DIMENSION: u32 = 16_384;
+const MAX_DECODE_BYTES: u64 = 512 * 1024 * 1024;
+const BLURHASH_SAMPLE_EDGE: u32 = 32;
+
+// 🖼️ PROCESSED IMAGE
+// Everything the thumbnail job stores for one upload
+pub struct ProcessedImage {
+    pub stripped: Vec<u8>,           // The original without EXIF/GPS and other metadata
+    pub width: u32,                  // Display size, after EXIF orientation
+    pub height: u32,
+    pub blurhash: String,
+    pub thumbnails: Vec<EncodedThumbnail>,
+}
+
+pub struct EncodedThumbnail {
+    pub name: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub jpeg: Vec<u8>,
+}
+
+/// Decode an uploaded image, strip its metadata and render its previews
+/// The format comes from the bytes themselves, never from the declared MIME type.
+pub fn process_image(bytes: &[u8]) -> AppResult<ProcessedImage> {
+    let format = image::guess_format(bytes)
+        .map_err(|_| AppError::bad_request("Unrecognized image format"))?;
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_BYTES);
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder()
+        .map_err(|e| AppError::bad_request(format!("Failed to read image: {}", e)))?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder)
+        .map_err(|e| AppError::bad_request(format!("Failed to decode image: {}", e)))?;
+    image.apply_orientation(orientation);
+
+    let stripped = strip_metadata(bytes, format, orientation)?;
+    let thumbnails = THUMBNAIL_SIZES
+        .iter()
+        .filter(|(_, edge)| image.width().max(image.height()) > *edge)
+        .map(|(name, edge)| encode_thumbnail(name, &image, *edge))
+        .collect::<AppResult<Vec<_>>>()?;
+    let sample = flatten(&image.thumbnail(BLURHASH_SAMPLE_EDGE, BLURHASH_SAMPLE_EDGE));
+
+    Ok(ProcessedImage {
+        stripped,
+        width: image.width(),
+        height: image.height(),
+        blurhash: blurhash(&sample),
+        thumbnails,
+    })
+}
+
+fn encode_thumbnail(name: &'static str, image: &DynamicImage, edge: u32) -> AppResult<EncodedThumbnail> {
+    let thumbnail = flatten(&image.thumbnail(edge, edge));
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, THUMBNAIL_QUALITY)
+        .encode_image(&thumbnail)
+        .map_err(|e| AppError::internal(format!("Failed to encode thumbnail: {}", e)))?;
+
+    Ok(EncodedThumbnail { name, width: thumbnail.width(), height: thumbnail.height(), jpeg })
+}
+
+// JPEG has no alpha channel, so composite transparent images onto white
+fn flatten(image: &DynamicImage) -> RgbImage {
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let over_white = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        image::Rgb([over_white(r), over_white(g), over_white(b)])
+    })
+}
+
+// 🧼 METADATA STRIPPING
+// Removes EXIF (including GPS), XMP, IPTC and comments without re-encoding the
+// pixels. Orientation is the one EXIF field worth keeping, so it's written back
+// as a minimal EXIF block. GIF has no EXIF block and is kept as uploaded.
+fn strip_metadata(bytes: &[u8], format: ImageFormat, orientation: Orientation) -> AppResult<Vec<u8>> {
+    let exif = (orientation != Orientation::NoTransforms).then(|| orientation_exif(orientation));
+    match format {
+        ImageFormat::Jpeg => strip_jpeg(bytes, exif.as_deref()),
+        ImageFormat::Png => strip_png(bytes, exif.as_deref()),
+        ImageFormat::WebP => strip_webp(bytes, exif.as_deref()),
+        ImageFormat::Gif => Ok(bytes.to_vec()),
+        other => Err(AppError::bad_request(format!("Unsupported image format {:?}", other))),
+    }
+}
+
+fn malformed(format: &str) -> AppError {
+    AppError::bad_request(format!("Malformed {} file", format))
+}
+
+// A big-endian TIFF block with a single IFD0 entry: Orientation (0x0112, SHORT)
+fn orientation_exif(orientation: Orientation) -> Vec<u8> {
+    let mut tiff = Vec::with_capacity(26);
+    tiff.extend_from_slice(b"MM\0\x2a");
+    tiff.extend_from_slice(&8u32.to_be_bytes());           // IFD0 offset
+    tiff.extend_from_slice(&1u16.to_be_bytes());           // Entry count
+    tiff.extend_from_slice(&0x0112u16.to_be_bytes());      // Orientation tag
+    tiff.extend_from_slice(&3u16.to_be_bytes());           // SHORT
+    tiff.extend_from_slice(&1u32.to_be_bytes());           // One value
+    tiff.extend_from_slice(&(orientation.to_exif() as u16).to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]);                       // Value padding
+    tiff.extend_from_slice(&0u32.to_be_bytes());           // No next IFD
+    tiff
+}
+
+// JPEG: keep JFIF (APP0), ICC profiles (APP2) and Adobe color info (APP14);
+// drop every other APPn segment, comments, and anything after the image ends
+fn strip_jpeg(bytes: &[u8], exif: Option<&[u8]>) -> AppResult<Vec<u8>> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return Err(malformed("JPEG"));
+    }
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&[0xFF, 0xD8]);
+    let mut exif = exif;
+    let mut pos = 2;
+
+    loop {
+        if bytes.get(pos) != Some(&0xFF) {
+            return Err(malformed("JPEG"));
+        }
+        while bytes.get(pos) == Some(&0xFF) {
+            pos += 1;
+        }
+        let marker = *bytes.get(pos).ok_or_else(|| malformed("JPEG"))?;
+        pos += 1;
+
+        match marker {
+            0xD9 => {
+                out.extend_from_slice(&[0xFF, 0xD9]);
+                return Ok(out);
+            }
+            0x01 | 0xD0..=0xD7 => {
+                out.extend_from_slice(&[0xFF, marker]);
+                continue;
+            }
+            _ => {}
+        }
+
+        let length = bytes.get(pos..pos + 2)
+            .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+            .filter(|len| *len >= 2)
+            .ok_or_else(|| malformed("JPEG"))?;
+        let segment = bytes.get(pos - 2..pos + length).ok_or_else(|| malformed("JPEG"))?;
+        pos += length;
+
+        // Orientation goes right after JFIF, before any other segment
+        if marker != 0xE0 {
+            if let Some(tiff) = exif.take() {
+                out.extend_from_slice(&[0xFF, 0xE1]);
+                out.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+                out.extend_from_slice(b"Exif\0\0");
+                out.extend_from_slice(tiff);
+            }
+        }
+
+        let keep = match marker {
+            0xE0 | 0xEE => true,
+            0xE2 => segment[4..].starts_with(b"ICC_PROFILE\0"),
+            0xE1 | 0xE3..=0xED | 0xEF | 0xFE => false,
+            _ => true,
+        };
+        if keep {
+            out.extend_from_slice(segment);
+        }
+
+        // Entropy-coded data follows a scan header; copy it up to the next real marker
+        if marker == 0xDA {
+            // 0xFF00 is an escaped data byte and 0xFFD0-D7 are restart markers
+            let at_marker = |i: usize| bytes[i] == 0xFF && !matches!(bytes[i + 1], 0x00 | 0xD0..=0xD7 | 0xFF);
+            let start = pos;
+            while pos + 1 < bytes.len() && !at_marker(pos) {
+                pos += 1;
+            }
+            if pos + 1 >= bytes.len() {
+                return Err(malformed("JPEG"));
+            }
+            out.extend_from_slice(&bytes[start..pos]);
+        }
+    }
+}
+
+// PNG: drop eXIf and text/time chunks; an orientation eXIf goes before the image data
+fn strip_png(bytes: &[u8], exif: Option<&[u8]>) -> AppResult<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !bytes.starts_with(SIGNATURE) {
+        return Err(malformed("PNG"));
+    }
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(SIGNATURE);
+    let mut exif = exif;
+    let mut pos = SIGNATURE.len();
+
+    while pos < bytes.len() {
+        let length = bytes.get(pos..pos + 4)
+            .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+            .ok_or_else(|| malformed("PNG"))?;
+        let chunk = bytes.get(pos..pos + 12 + length).ok_or_else(|| malformed("PNG"))?;
+        let kind = &chunk[4..8];
+        pos += chunk.len();
+
+        if kind == b"IDAT" {
+            if let Some(tiff) = exif.take() {
+                write_png_chunk(&mut out, b"eXIf", tiff);
+            }
+        }
+        if !matches!(kind, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME") {
+            out.extend_from_slice(chunk);
+        }
+        if kind == b"IEND" {
+            return Ok(out);
+        }
+    }
+    Err(malformed("PNG"))
+}
+
+fn write_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+// WebP: drop EXIF and XMP chunks and their VP8X flags; only extended (VP8X)
+// files can carry EXIF, so only they can need the orientation written back
+fn strip_webp(bytes: &[u8], exif: Option<&[u8]>) -> AppResult<Vec<u8>> {
+    const XMP_FLAG: u8 = 0x04;
+    const EXIF_FLAG: u8 = 0x08;
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WEBP" {
+        return Err(malformed("WebP"));
+    }
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&bytes[0..12]);
+    let mut extended = false;
+    let mut pos = 12;
+
+    while pos < bytes.len() {
+        let header = bytes.get(pos..pos + 8).ok_or_else(|| malformed("WebP"))?;
+        let kind = &header[0..4];
+        let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let padded = length + (length & 1);
+        let end = (pos + 8 + padded).min(bytes.len());
+        let chunk = bytes.get(pos..pos + 8 + length).ok_or_else(|| malformed("WebP"))?;
+        let chunk_start = out.len();
+
+        if kind != b"EXIF" && kind != b"XMP " {
+            out.extend_from_slice(&bytes[pos..end]);
+        }
+        if kind == b"VP8X" && chunk.len() > 8 {
+            extended = true;
+            out[chunk_start + 8] &= !(XMP_FLAG | EXIF_FLAG);
+            if exif.is_some() {
+                out[chunk_start + 8] |= EXIF_FLAG;
+            }
+        }
+        pos = end;
+    }
+
+    // EXIF belongs after the image data
+    if let Some(tiff) = exif.filter(|_| extended) {
+        out.extend_from_slice(b"EXIF");
+        out.extend_from_slice(&(tiff.len() as u32).to_le_bytes());
+        out.extend_from_slice(tiff);
+        if tiff.len() % 2 == 1 {
+            out.push(0);
+        }
+    }
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Ok(out)
+}
+
+// 🌫️ BLURHASH
+// Encodes a tiny DCT of the image as a short string (https://blurha.sh) that
+// clients decode into a blurry placeholder. Uses 4 components along the long
+// edge and 3 along the short one.
+fn blurhash(image: &RgbImage) -> String {
+    let (x_components, y_components) = if image.width() >= image.height() { (4, 3) } else { (3, 4) };
+    let (width, height) = (image.width() as f64, image.height() as f64);
+
+    let mut factors = Vec::with_capacity(x_components * y_components);
+    for j in 0..y_components {
+        for i in 0..x_components {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0f64; 3];
+            for (x, y, pixel) in image.enumerate_pixels() {
+                let basis = (std::f64::consts::PI * i as f64 * x as f64 / width).cos()
+                    * (std::f64::consts::PI * j as f64 * y as f64 / height).cos();
+                for (channel, value) in factor.iter_mut().zip(pixel.0) {
+                    *channel += basis * srgb_to_linear(value);
+                }
+            }
+            let scale = normalisation / (width * height);
+            factors.push(factor.map(|channel| channel * scale));
+        }
+    }
+
+    let mut hash = String::with_capacity(4 + 2 * factors.len());
+    encode_base83(((x_components - 1) + (y_components - 1) * 9) as u32, 1, &mut hash);
+
+    let (dc, ac) = factors.split_first().expect("at least one component");
+    let maximum_value = match ac.iter().flatten().map(|v| v.abs()).reduce(f64::max) {
+        Some(actual_maximum) => {
+            let quantised = (actual_maximum * 166.0 - 0.5).floor().clamp(0.0, 82.0) as u32;
+            encode_base83(quantised, 1, &mut hash);
+            (quantised + 1) as f64 / 166.0
+        }
+        None => {
+            encode_base83(0, 1, &mut hash);
+            1.0
+        }
+    };
+
+    let [r, g, b] = dc.map(linear_to_srgb);
+    encode_base83((r << 16) | (g << 8) | b, 4, &mut hash);
+    for factor in ac {
+        let [r, g, b] = factor.map(|value| {
+            let scaled = value / maximum_value;
+            (scaled.signum() * scaled.abs().sqrt() * 9.0 + 9.5).floor().clamp(0.0, 18.0) as u32
+        });
+        encode_base83(r * 19 * 19 + g * 19 + b, 2, &mut hash);
+    }
+    hash
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    let v = value as f64 / 255.0;
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(value: f64) -> u32 {
+    let v = value.clamp(0.0, 1.0);
+    if v <= 0.003_130_8 {
+        (v * 12.92 * 255.0 + 0.5) as u32
+    } else {
+        ((1.055 * v.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u32
+    }
+}
+
+fn encode_base83(value: u32, length: u32, out: &mut String) {
+    const ALPHABET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+    for i in (0..length).rev() {
+        let digit = (value / 83u32.pow(i)) % 83;
+        out.push(ALPHABET[digit as usize] as char);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(image: &DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), format).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn blurhash_of_a_flat_image_has_no_detail() {
+        let black = RgbImage::new(8, 6);
+        assert_eq!(blurhash(&black), "L00000fQfQfQfQfQfQfQfQfQfQfQ");
+    }
+
+    #[test]
+    fn jpeg_metadata_is_dropped_but_orientation_survives() {
+        let jpeg = encode(&DynamicImage::new_rgb8(40, 20), ImageFormat::Jpeg);
+
+        // Splice in an EXIF block claiming a 90° rotation, plus a comment
+        let mut exif_segment = b"Exif\0\0".to_vec();
+        let mut tiff = orientation_exif(Orientation::Rotate90);
+        tiff.extend_from_slice(b"GPS 51.5N 0.1W");
+        exif_segment.extend_from_slice(&tiff);
+        let mut tagged = jpeg[..2].to_vec();
+        tagged.extend_from_slice(&[0xFF, 0xE1]);
+        tagged.extend_from_slice(&((exif_segment.len() + 2) as u16).to_be_bytes());
+        tagged.extend_from_slice(&exif_segment);
+        tagged.extend_from_slice(&[0xFF, 0xFE, 0x00, 0x07]);
+        tagged.extend_from_slice(b"hello");
+        tagged.extend_from_slice(&jpeg[2..]);
+        tagged.extend_from_slice(b"trailing data");
+
+        let processed = process_image(&tagged).unwrap();
+        let stripped = &processed.stripped;
+        assert!(!stripped.windows(3).any(|w| w == b"GPS"));
+        assert!(!stripped.windows(5).any(|w| w == b"hello"));
+        assert!(stripped.ends_with(&[0xFF, 0xD9]));
+        assert_eq!((processed.width, processed.height), (20, 40));
+
+        let mut decoder = ImageReader::new(Cursor::new(stripped)).with_guessed_format().unwrap()
+            .into_decoder().unwrap();
+        assert_eq!(decoder.orientation().unwrap(), Orientation::Rotate90);
+    }
+
+    #[test]
+    fn png_text_chunks_are_dropped() {
+        let png = encode(&DynamicImage::new_rgba8(4, 4), ImageFormat::Png);
+        let mut tagged = png[..33].to_vec(); // Signature + IHDR
+        write_png_chunk(&mut tagged, b"tEXt", b"Comment\0secret");
+        tagged.extend_from_slice(&png[33..]);
+
+        let stripped = strip_png(&tagged, None).unwrap();
+        assert_eq!(stripped, png);
+        assert!(image::load_from_memory(&stripped).is_ok());
+    }
+
+    #[test]
+    fn thumbnails_fit_their_size_and_keep_the_aspect_ratio() {
+        let png = encode(&DynamicImage::new_rgb8(1000, 500), ImageFormat::Png);
+        let processed = process_image(&png).unwrap();
+
+        let sizes: Vec<_> = processed.thumbnails.iter().map(|t| (t.name, t.width, t.height)).collect();
+        assert_eq!(sizes, vec![("small", 160, 80), ("medium", 640, 320)]);
+        assert!(image::load_from_memory_with_format(&processed.thumbnails[0].jpeg, ImageFormat::Jpeg).is_ok());
+
+        let tiny = encode(&DynamicImage::new_rgb8(100, 50), ImageFormat::Png);
+        assert!(process_image(&tiny).unwrap().thumbnails.is_empty());
+    }
+}
@@ -20,10 +20,14 @@ use actix_web::{web, HttpResponse};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
-use crate::database::Message;
+use std::collections::HashMap;
+use crate::database::{Message, MessageEncryption, MessageReaction, MessageStatus, NewConversation};
 use crate::errors::{AppError, AppResult};
-use crate::auth::auth::Claims;
+use crate::auth::auth::{AccessToken, Claims};
 use crate::supabase_api::SupabaseClient;
+use crate::config::Config;
+use crate::blob_store::BlobStore;
+use crate::websocket::{delete_chat_message, edit_chat_message, send_chat_message, update_chat_reaction, ChatDelete, ChatEdit, ChatReaction, ChatSend, DeleteScope, OutgoingMessage, SessionManager};
 // use sqlx::PgPool; // COMMENTED OUT - Using Supabase API instead
 
 // 📋 REQUEST/RESPONSE TYPES
@@ -42,13 +46,20 @@ pub struct GetConversationQuery {
 #[derive(Debug, Serialize)]
 pub struct MessageResponse {
     pub id: Uuid,
+    pub conversation_id: Uuid,
     pub sender_id: Uuid,
-    pub receiver_id: Uuid,
+    pub receiver_id: Option<Uuid>,  // Only set for 1:1 conversations
+    pub reply_to_id: Option<Uuid>,  // Message this one replies to
+    pub edited_at: Option<DateTime<Utc>>, // Last edit, if the message was edited
+    pub deleted_at: Option<DateTime<Utc>>, // Set on tombstones deleted for everyone
     pub encrypted_content: String,  // Encrypted message content
     pub content_hash: String,       // Hash for integrity verification
     pub encryption_version: i32,    // Encryption version
+    pub encryption: Option<MessageEncryption>, // What readers need to decrypt; None if stored as sent
     pub message_type: String,
     pub is_read: bool,
+    pub status: MessageStatus,      // sent → delivered → read
+    pub client_msg_id: Option<Uuid>, // Sender-generated ID, if one was supplied
     pub file_url: Option<String>,   // File attachment URL
     pub file_size: Option<i64>,     // File size in bytes
     pub mime_type: Option<String>,  // MIME type
@@ -57,6 +68,66 @@ pub struct MessageResponse {
     
     // Additional computed fields
     pub is_sender: bool,        // True if current user sent this message
+    pub reactions: Vec<ReactionSummary>, // Grouped by emoji
+}
+
+// 😀 REACTION SUMMARY
+// Everyone who reacted to a message with one emoji
+#[derive(Debug, Serialize)]
+pub struct ReactionSummary {
+    pub emoji: String,
+    pub count: usize,
+    pub user_ids: Vec<Uuid>,
+    pub reacted: bool,          // True if current user is among them
+}
+
+// 🧮 Group one message's reactions by emoji, in the order each emoji first appeared
+// (reactions arrive oldest first)
+fn summarize_reactions(reactions: Vec<MessageReaction>, current_user_id: Uuid) -> Vec<ReactionSummary> {
+    let mut summaries: Vec<ReactionSummary> = Vec::new();
+    for reaction in reactions {
+        let index = match summaries.iter().position(|summary| summary.emoji == reaction.emoji) {
+            Some(index) => index,
+            None => {
+                summaries.push(ReactionSummary {
+                    emoji: reaction.emoji,
+                    count: 0,
+                    user_ids: Vec::new(),
+                    reacted: false,
+                });
+                summaries.len() - 1
+            }
+        };
+        let summary = &mut summaries[index];
+        summary.count += 1;
+        summary.reacted |= reaction.user_id == current_user_id;
+        summary.user_ids.push(reaction.user_id);
+    }
+    summaries
+}
+
+// 😀 Convert messages to responses with their reaction summaries attached
+async fn message_responses(
+    supabase_client: &SupabaseClient,
+    messages: Vec<Message>,
+    current_user_id: Uuid,
+    access_token: &str,
+) -> AppResult<Vec<MessageResponse>> {
+    let message_ids: Vec<Uuid> = messages.iter().map(|message| message.id).collect();
+    let mut reactions: HashMap<Uuid, Vec<MessageReaction>> = HashMap::new();
+    for reaction in supabase_client.get_reactions(&message_ids, access_token).await? {
+        reactions.entry(reaction.message_id).or_default().push(reaction);
+    }
+    
+    Ok(messages
+        .into_iter()
+        .map(|message| {
+            let message_reactions = reactions.remove(&message.id).unwrap_or_default();
+            let mut response = MessageResponse::from_db_message(message, current_user_id);
+            response.reactions = summarize_reactions(message_reactions, current_user_id);
+            response
+        })
+        .collect())
 }
 
 impl MessageResponse {
@@ -64,19 +135,27 @@ impl MessageResponse {
     fn from_db_message(message: Message, current_user_id: Uuid) -> Self {
         Self {
             id: message.id,
+            conversation_id: message.conversation_id,
             sender_id: message.sender_id,
             receiver_id: message.receiver_id,
+            reply_to_id: message.reply_to_id,
+            edited_at: message.edited_at,
+            deleted_at: message.deleted_at,
             encrypted_content: message.encrypted_content.clone(),
             content_hash: message.content_hash.clone(),
             encryption_version: message.encryption_version,
+            encryption: message.encryption(),
             message_type: format!("{:?}", message.message_type).to_lowercase(),
             is_read: message.is_read,
+            status: message.status,
+            client_msg_id: message.client_msg_id,
             file_url: message.file_url.clone(),
             file_size: message.file_size,
             mime_type: message.mime_type.clone(),
             created_at: message.created_at,
             updated_at: message.updated_at,
             is_sender: message.sender_id == current_user_id,
+            reactions: Vec::new(),
         }
     }
 }
@@ -115,9 +194,6 @@ pub struct ConversationParticipant {
 #[derive(Debug, Deserialize)]
 pub struct SearchMessagesQuery {
     pub query: String,              // Search term
-    pub with_user: Option<Uuid>,    // Limit search to conversation with specific user
-    pub limit: Option<i64>,         // Maximum results (default: 20)
-    pub offset: Option<i64>,        // Pagination offset (default: 0)
 }
 
 // 🔍 SEARCH RESULTS RESPONSE
@@ -153,6 +229,7 @@ struct ConversationRow {
 // GET /api/v1/messages/conversation?with_user=<uuid>&limit=50&before=<timestamp>
 pub async fn get_conversation(
     claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
     query: web::Query<GetConversationQuery>,
     supabase_client: web::Data<SupabaseClient>,
 ) -> AppResult<HttpResponse> {
@@ -168,7 +245,7 @@ pub async fn get_conversation(
     
     // 🔐 ZERO TRUST: Get messages via Supabase API
     // This respects Row-Level Security (RLS) policies
-    let access_token = "TODO: Extract from claims or request"; // TODO: Implement token extraction
+    let access_token = token.as_str();
     let messages = supabase_client.get_conversation(
         current_user_id,
         other_user_id,
@@ -177,10 +254,7 @@ pub async fn get_conversation(
     ).await?;
     
     // Convert to response format
-    let message_responses: Vec<MessageResponse> = messages
-        .into_iter()
-        .map(|msg| MessageResponse::from_db_message(msg, current_user_id))
-        .collect();
+    let message_responses = message_responses(&supabase_client, messages, current_user_id, access_token).await?;
     
     // 🚫 DIRECT DATABASE COUNT QUERIES (COMMENTED OUT)
     // These operations are now handled by Supabase RLS policies
@@ -205,6 +279,7 @@ pub async fn get_conversation(
 // GET /api/v1/messages/stats
 pub async fn get_message_stats(
     claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
     supabase_client: web::Data<SupabaseClient>,
 ) -> AppResult<HttpResponse> {
     let user_id = Uuid::parse_str(&claims.sub)
@@ -218,7 +293,7 @@ pub async fn get_message_stats(
     // let active_conversations = sqlx::query_scalar::<_, i64>(...).await?;
     
     // 🔐 ZERO TRUST: Get statistics via Supabase API
-    let access_token = "TODO: Extract from claims or request"; // TODO: Implement token extraction
+    let access_token = token.as_str();
     
     // For now, we'll use simplified statistics
     // In a full implementation, you'd add specific API endpoints for these statistics
@@ -253,7 +328,6 @@ pub async fn get_conversations(
     // 🔐 ZERO TRUST: Get conversations via Supabase API
     // For now, we'll return an empty list as this requires a more complex implementation
     // In a full implementation, you'd create a dedicated Supabase API endpoint for this
-    let access_token = "TODO: Extract from claims or request"; // TODO: Implement token extraction
     
     // TODO: Implement conversation list via Supabase API
     // This would require a custom Supabase function or a different API approach
@@ -262,19 +336,16 @@ pub async fn get_conversations(
     Ok(HttpResponse::Ok().json(participants))
 }
 
-// 🔍 SEARCH MESSAGES (ZERO TRUST - Using Supabase API)
-// GET /api/v1/messages/search?query=hello&with_user=<uuid>&limit=20&offset=0
+// 🔍 SEARCH MESSAGES (NOT IMPLEMENTED SERVER-SIDE)
+// GET /api/v1/messages/search?query=hello
+// 🔐 Message content is end-to-end encrypted and the server keeps no searchable
+// index of it, so there is nothing here to match a query against. Clients search
+// their own decrypted history; this endpoint only validates the query and
+// always reports no matches.
 pub async fn search_messages(
-    claims: web::ReqData<Claims>,
     query: web::Query<SearchMessagesQuery>,
-    supabase_client: web::Data<SupabaseClient>,
 ) -> AppResult<HttpResponse> {
-    let user_id = Uuid::parse_str(&claims.sub)
-    .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
-    
     let search_term = &query.query;
-    let limit = query.limit.unwrap_or(20).min(50); // Cap at 50 results
-    let offset = query.offset.unwrap_or(0);
     
     if search_term.trim().is_empty() {
         return Err(AppError::bad_request("Search query cannot be empty"));
@@ -284,35 +355,11 @@ pub async fn search_messages(
         return Err(AppError::bad_request("Search query must be at least 2 characters"));
     }
     
-    // 🚫 DIRECT DATABASE SEARCH (COMMENTED OUT)
-    // We now use Supabase HTTP API instead of direct database queries
-    // let (messages, total_count) = if let Some(with_user) = query.with_user { ... } else { ... };
-    
-    // 🔐 ZERO TRUST: Search messages via Supabase API
-    let access_token = "TODO: Extract from claims or request"; // TODO: Implement token extraction
-    let messages = supabase_client.search_messages(
-        user_id,
-        search_term,
-        query.with_user,
-        limit,
-        access_token
-    ).await?;
-    
-    let message_responses: Vec<MessageResponse> = messages
-        .into_iter()
-        .map(|msg| MessageResponse::from_db_message(msg, user_id))
-        .collect();
-    
-    // For now, we'll use a simplified count
-    let total_count = message_responses.len() as i64;
-    
-    let response = SearchResultsResponse {
-        has_more: (offset + limit) < total_count,
-        messages: message_responses,
-        total_matches: total_count,
-    };
-    
-    Ok(HttpResponse::Ok().json(response))
+    Ok(HttpResponse::Ok().json(SearchResultsResponse {
+        messages: Vec::new(),
+        total_matches: 0,
+        has_more: false,
+    }))
 }
 
 // ✅ MARK MESSAGES AS READ (ZERO TRUST - Using Supabase API)
@@ -324,8 +371,10 @@ pub struct MarkReadRequest {
 
 pub async fn mark_messages_read(
     claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
     request: web::Json<MarkReadRequest>,
     supabase_client: web::Data<SupabaseClient>,
+    session_manager: web::Data<SessionManager>,
 ) -> AppResult<HttpResponse> {
     let user_id = Uuid::parse_str(&claims.sub)
     .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
@@ -343,12 +392,20 @@ pub async fn mark_messages_read(
     // let updated_count = sqlx::query(...).execute(db_pool.get_ref()).await?.rows_affected();
     
     // 🔐 ZERO TRUST: Mark messages as read via Supabase API
-    let access_token = "TODO: Extract from claims or request"; // TODO: Implement token extraction
+    let access_token = token.as_str();
     let mut updated_count = 0;
     
     for message_id in &request.message_ids {
         match supabase_client.mark_message_read(*message_id, user_id, access_token).await {
-            Ok(_) => updated_count += 1,
+            Ok(Some(message)) => {
+                updated_count += 1;
+                // 📬 Same read receipt the WebSocket `mark_read` flow sends
+                session_manager.send_to_users(&[message.sender_id], None, OutgoingMessage::ReadReceipt {
+                    message_id: message.id,
+                    read_by: user_id,
+                });
+            }
+            Ok(None) => {}
             Err(e) => log::warn!("Failed to mark message {} as read: {}", message_id, e),
         }
     }
@@ -367,12 +424,17 @@ pub struct SendMessageRequest {
     pub sender_id: String,
     pub text: String,
     pub reply_to_id: Option<String>,
+    pub client_msg_id: Option<Uuid>,   // Sender-generated ID; retries return the original message
+    pub id: Option<Uuid>,              // Message ID the text was encrypted for
+    pub encryption: Option<MessageEncryption>, // How `text` was encrypted; None sends it as is
 }
 
 pub async fn send_message(
     claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
     request: web::Json<SendMessageRequest>,
     supabase_client: web::Data<SupabaseClient>,
+    session_manager: web::Data<SessionManager>,
 ) -> AppResult<HttpResponse> {
     let user_id = Uuid::parse_str(&claims.sub)
         .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
@@ -395,30 +457,27 @@ pub async fn send_message(
     };
     
     // 🔐 ZERO TRUST: Send message via Supabase API
-    let access_token = "TODO: Extract from claims or request"; // TODO: Implement token extraction
-    
-    // For now, we'll create a simple message response
-    // In a real implementation, this would encrypt the message and store it
-    let message_response = MessageResponse {
-        id: Uuid::new_v4(),
+    // Same pipeline as the WebSocket `message` frame, so connected
+    // participants (and the sender's own devices) get it live
+    let request = request.into_inner();
+    let send = ChatSend {
         sender_id: user_id,
-        receiver_id: conversation_id, // This should be the other user in the conversation
-        encrypted_content: request.text.clone(), // In real app, this would be encrypted
-        content_hash: "TODO: Generate hash".to_string(),
-        encryption_version: 1,
-        message_type: "text".to_string(),
-        is_read: false,
-        file_url: None,
-        file_size: None,
-        mime_type: None,
-        created_at: chrono::Utc::now(),
-        updated_at: chrono::Utc::now(),
-        is_sender: true,
+        to: None,
+        conversation_id: Some(conversation_id),
+        client_msg_id: request.client_msg_id,
+        reply_to_id,
+        id: request.id,
+        content: request.text,
+        encryption: request.encryption,
     };
+    let inserted = send_chat_message(&supabase_client, &session_manager, send, None, token.as_str()).await?;
+    
+    // 🔁 A retried send returns the message stored the first time
+    let status = if inserted.is_new() { "sent" } else { "duplicate" };
     
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": message_response,
-        "status": "sent"
+        "message": MessageResponse::from_db_message(inserted.into_message(), user_id),
+        "status": status
     })))
 }
 
@@ -432,8 +491,12 @@ pub struct CreateConversationRequest {
     pub created_by: String,
 }
 
+// 👥 Upper bound on group size
+const MAX_GROUP_PARTICIPANTS: usize = 256;
+
 pub async fn create_conversation(
     claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
     request: web::Json<CreateConversationRequest>,
     supabase_client: web::Data<SupabaseClient>,
 ) -> AppResult<HttpResponse> {
@@ -451,23 +514,49 @@ pub async fn create_conversation(
         .map(|id| Uuid::parse_str(id))
         .collect();
     
-    let participant_ids = participant_ids
+    let mut participant_ids = participant_ids
         .map_err(|_| AppError::bad_request("Invalid participant ID"))?;
     
-    // For now, we'll create a simple conversation response
-    // In a real implementation, this would create the conversation in the database
-    let conversation_response = serde_json::json!({
-        "id": Uuid::new_v4(),
-        "name": request.name,
-        "is_group": request.is_group,
-        "participants": participant_ids,
-        "created_by": user_id,
-        "created_at": chrono::Utc::now(),
-        "updated_at": chrono::Utc::now(),
-    });
+    // The creator is always a participant; drop duplicates but keep order
+    participant_ids.insert(0, user_id);
+    let mut seen = std::collections::HashSet::new();
+    participant_ids.retain(|id| seen.insert(*id));
+    
+    // 🔐 ZERO TRUST: Create conversation via Supabase API
+    let access_token = token.as_str();
+    let conversation = if request.is_group {
+        if participant_ids.len() < 2 {
+            return Err(AppError::bad_request("A group needs at least one other participant"));
+        }
+        if participant_ids.len() > MAX_GROUP_PARTICIPANTS {
+            return Err(AppError::bad_request(format!(
+                "Too many participants (max {})", MAX_GROUP_PARTICIPANTS
+            )));
+        }
+        
+        let new_conversation = NewConversation {
+            name: request.name.clone(),
+            participant_ids: participant_ids.clone(),
+        };
+        supabase_client.create_conversation(&new_conversation, user_id, access_token).await?
+    } else {
+        // 1:1 conversations are unique per pair, so reuse an existing one
+        if participant_ids.len() != 2 {
+            return Err(AppError::bad_request("A direct conversation needs exactly one other participant"));
+        }
+        supabase_client.get_or_create_direct_conversation(user_id, participant_ids[1], access_token).await?
+    };
     
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "conversation": conversation_response,
+        "conversation": {
+            "id": conversation.id,
+            "name": conversation.name,
+            "is_group": conversation.is_group,
+            "participants": participant_ids,
+            "created_by": conversation.created_by,
+            "created_at": conversation.created_at,
+            "updated_at": conversation.updated_at,
+        },
         "status": "created"
     })))
 }
@@ -477,6 +566,7 @@ pub async fn create_conversation(
 pub async fn get_conversations_by_user(
     path: web::Path<String>,
     claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
     supabase_client: web::Data<SupabaseClient>,
 ) -> AppResult<HttpResponse> {
     let user_id = Uuid::parse_str(&claims.sub)
@@ -490,36 +580,28 @@ pub async fn get_conversations_by_user(
         return Err(AppError::auth_failed("Can only access own conversations"));
     }
     
-    // For now, we'll return a simple response
-    // In a real implementation, this would fetch conversations from the database
-    let conversations = vec![
-        serde_json::json!({
-            "id": Uuid::new_v4(),
-            "name": "Test Conversation",
-            "is_group": false,
-            "participants": vec![user_id],
-            "last_message": {
-                "id": Uuid::new_v4(),
-                "text": "Hello!",
-                "sender_id": user_id,
-                "timestamp": chrono::Utc::now(),
-            },
-            "unread_count": 0,
-            "created_at": chrono::Utc::now(),
-            "updated_at": chrono::Utc::now(),
-        })
-    ];
+    // 🔐 ZERO TRUST: RLS only returns conversations the caller belongs to
+    let conversations = supabase_client.get_user_conversations(user_id, token.as_str()).await?;
     
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "conversations": conversations
     })))
 }
 
+// 📜 CONVERSATION HISTORY QUERY
+#[derive(Debug, Deserialize)]
+pub struct ConversationMessagesQuery {
+    pub limit: Option<i64>,             // Maximum number of messages (default: 50)
+    pub before: Option<DateTime<Utc>>,  // Get messages before this timestamp (for pagination)
+}
+
 // ✅ GET MESSAGES BY CONVERSATION ID ENDPOINT
-// GET /api/v1/messages/{conversationId}
+// GET /api/v1/messages/{conversationId}?limit=50&before=<timestamp>
 pub async fn get_messages_by_conversation(
     path: web::Path<String>,
+    query: web::Query<ConversationMessagesQuery>,
     claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
     supabase_client: web::Data<SupabaseClient>,
 ) -> AppResult<HttpResponse> {
     let user_id = Uuid::parse_str(&claims.sub)
@@ -529,32 +611,225 @@ pub async fn get_messages_by_conversation(
     let conversation_id = Uuid::parse_str(&path.into_inner())
         .map_err(|_| AppError::bad_request("Invalid conversation ID"))?;
     
-    // For now, we'll return a simple response
-    // In a real implementation, this would fetch messages from the database
-    let messages = vec![
-        MessageResponse {
-            id: Uuid::new_v4(),
-            sender_id: user_id,
-            receiver_id: conversation_id,
-            encrypted_content: "Hello! This is a test message.".to_string(),
-            content_hash: "TODO: Generate hash".to_string(),
-            encryption_version: 1,
-            message_type: "text".to_string(),
-            is_read: true,
-            file_url: None,
-            file_size: None,
-            mime_type: None,
-            created_at: chrono::Utc::now(),
-            updated_at: chrono::Utc::now(),
-            is_sender: true,
-        }
-    ];
+    let limit = query.limit.unwrap_or(50).clamp(1, 100); // Cap at 100 messages
+    let access_token = token.as_str();
+    
+    // 🔐 ZERO TRUST: Non-participants get a 404 rather than an empty page
+    let participants = supabase_client.get_conversation_participants(conversation_id, access_token).await?;
+    if !participants.contains(&user_id) {
+        return Err(AppError::NotFound { resource: format!("conversation {}", conversation_id) });
+    }
+    
+    let messages = supabase_client
+        .get_conversation_messages(conversation_id, limit, query.before, access_token)
+        .await?;
+    let messages = message_responses(&supabase_client, messages, user_id, access_token).await?;
     
     Ok(HttpResponse::Ok().json(serde_json::json!({
+        "has_more": messages.len() as i64 == limit,
         "messages": messages
     })))
 }
 
+// ✏️ EDIT MESSAGE ENDPOINT
+// PATCH /api/v1/messages/{messageId}
+#[derive(Debug, Deserialize)]
+pub struct EditMessageRequest {
+    pub content: String,
+    pub encryption: Option<MessageEncryption>,
+}
+
+pub async fn edit_message(
+    path: web::Path<Uuid>,
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    request: web::Json<EditMessageRequest>,
+    supabase_client: web::Data<SupabaseClient>,
+    session_manager: web::Data<SessionManager>,
+    config: web::Data<Config>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+    
+    // 🔐 ZERO TRUST: Same checks and fan-out as the WebSocket `edit` frame
+    let request = request.into_inner();
+    let edit = ChatEdit {
+        editor_id: user_id,
+        message_id: path.into_inner(),
+        content: request.content,
+        encryption: request.encryption,
+    };
+    let message = edit_chat_message(
+        &supabase_client,
+        &session_manager,
+        edit,
+        config.message_edit_window_seconds,
+        None,
+        token.as_str(),
+    ).await?;
+    let message = message_responses(&supabase_client, vec![message], user_id, token.as_str()).await?.remove(0);
+    
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": message,
+        "status": "edited"
+    })))
+}
+
+// 😀 ADD REACTION ENDPOINT
+// POST /api/v1/messages/{messageId}/reactions
+#[derive(Debug, Deserialize)]
+pub struct ReactionRequest {
+    pub emoji: String,
+}
+
+pub async fn add_reaction(
+    path: web::Path<Uuid>,
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    request: web::Json<ReactionRequest>,
+    supabase_client: web::Data<SupabaseClient>,
+    session_manager: web::Data<SessionManager>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+    
+    // 🔐 ZERO TRUST: Same checks and fan-out as the WebSocket `react` frame
+    let reaction = ChatReaction {
+        user_id,
+        message_id: path.into_inner(),
+        emoji: request.into_inner().emoji,
+        added: true,
+    };
+    let event = update_chat_reaction(&supabase_client, &session_manager, reaction, None, token.as_str()).await?;
+    
+    Ok(HttpResponse::Ok().json(event))
+}
+
+// 😶 REMOVE REACTION ENDPOINT
+// DELETE /api/v1/messages/{messageId}/reactions/{emoji}
+pub async fn remove_reaction(
+    path: web::Path<(Uuid, String)>,
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    supabase_client: web::Data<SupabaseClient>,
+    session_manager: web::Data<SessionManager>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+    
+    let (message_id, emoji) = path.into_inner();
+    let reaction = ChatReaction {
+        user_id,
+        message_id,
+        emoji,
+        added: false,
+    };
+    let event = update_chat_reaction(&supabase_client, &session_manager, reaction, None, token.as_str()).await?;
+    
+    Ok(HttpResponse::Ok().json(event))
+}
+
+// 🗑️ DELETE MESSAGE ENDPOINT
+// DELETE /api/v1/messages/{messageId}?scope=me|everyone
+#[derive(Debug, Deserialize)]
+pub struct DeleteMessageQuery {
+    #[serde(default)]
+    pub scope: DeleteScope,     // `me` (default) or `everyone` (sender only)
+}
+
+pub async fn delete_message(
+    path: web::Path<Uuid>,
+    query: web::Query<DeleteMessageQuery>,
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    supabase_client: web::Data<SupabaseClient>,
+    session_manager: web::Data<SessionManager>,
+    blob_store: web::Data<dyn BlobStore>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+    
+    // 🔐 ZERO TRUST: Same checks and fan-out as the WebSocket `delete` frame
+    let delete = ChatDelete {
+        user_id,
+        message_id: path.into_inner(),
+        scope: query.scope,
+    };
+    let event = delete_chat_message(&supabase_client, &session_manager, blob_store.get_ref(), delete, None, token.as_str()).await?;
+    
+    Ok(HttpResponse::Ok().json(event))
+}
+
+// 📜 GET EDIT HISTORY ENDPOINT
+// GET /api/v1/messages/{messageId}/edits
+// Prior revisions, oldest first; the current content is on the message itself
+pub async fn get_message_edits(
+    path: web::Path<Uuid>,
+    token: web::ReqData<AccessToken>,
+    supabase_client: web::Data<SupabaseClient>,
+) -> AppResult<HttpResponse> {
+    let message_id = path.into_inner();
+    let access_token = token.as_str();
+    
+    // 🔐 ZERO TRUST: RLS hides messages (and their history) outside the caller's conversations
+    if supabase_client.get_message(message_id, access_token).await?.is_none() {
+        return Err(AppError::NotFound { resource: format!("message {}", message_id) });
+    }
+    let edits = supabase_client.get_message_edits(message_id, access_token).await?;
+    
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "edits": edits
+    })))
+}
+
+// 🧵 THREAD QUERY
+#[derive(Debug, Deserialize)]
+pub struct ThreadQuery {
+    pub limit: Option<i64>,             // Maximum number of replies (default: 50)
+    pub after: Option<DateTime<Utc>>,   // Get replies after this timestamp (for pagination)
+}
+
+// 🧵 GET THREAD ENDPOINT
+// GET /api/v1/messages/{messageId}/thread?limit=50&after=<timestamp>
+// Returns the root message plus its direct replies, oldest first
+pub async fn get_thread(
+    path: web::Path<Uuid>,
+    query: web::Query<ThreadQuery>,
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    supabase_client: web::Data<SupabaseClient>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+    
+    let root_id = path.into_inner();
+    let limit = query.limit.unwrap_or(50).clamp(1, 100); // Cap at 100 replies
+    let access_token = token.as_str();
+    
+    // 🔐 ZERO TRUST: RLS hides messages outside the caller's conversations
+    let root = supabase_client.get_message(root_id, access_token).await?
+        .ok_or_else(|| AppError::NotFound { resource: format!("message {}", root_id) })?;
+    
+    let replies = supabase_client
+        .get_thread_replies(root_id, limit, query.after, access_token)
+        .await?;
+    
+    // One reaction lookup for the root and its replies
+    let mut replies = message_responses(
+        &supabase_client,
+        std::iter::once(root).chain(replies).collect(),
+        user_id,
+        access_token,
+    ).await?;
+    let root = replies.remove(0);
+    
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "root": root,
+        "has_more": replies.len() as i64 == limit,
+        "replies": replies
+    })))
+}
+
 // 🛤️ CONFIGURE ROUTES
 // This function sets up all the message-related routes
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
@@ -567,5 +842,44 @@ pub fn configure_routes(cfg: &mut web::ServiceConfig) {
             .route("/mark-read", web::post().to(mark_messages_read))
             .route("/send", web::post().to(send_message)) // ✅ ADDED: Send message endpoint
             .route("/{conversationId}", web::get().to(get_messages_by_conversation)) // ✅ ADDED: Get messages by conversation ID
+            .route("/{messageId}", web::patch().to(edit_message)) // ✏️ Sender-only edit
+            .route("/{messageId}", web::delete().to(delete_message)) // 🗑️ Delete for me / for everyone
+            .route("/{messageId}/edits", web::get().to(get_message_edits)) // ✏️ Edit history
+            .route("/{messageId}/reactions", web::post().to(add_reaction)) // 😀 React
+            .route("/{messageId}/reactions/{emoji}", web::delete().to(remove_reaction)) // 😶 Unreact
+            .route("/{messageId}/attachments", web::post().to(crate::attachments::upload_attachment)) // 📎 Upload (sender only)
+            .route("/{messageId}/attachments", web::get().to(crate::attachments::get_message_attachments)) // 📎 List
+            .route("/{messageId}/thread", web::get().to(get_thread)) // 🧵 Root message plus replies
     );
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reaction(user: u128, emoji: &str) -> MessageReaction {
+        MessageReaction {
+            message_id: Uuid::from_u128(1),
+            user_id: Uuid::from_u128(user),
+            emoji: emoji.to_string(),
+            conversation_id: Uuid::from_u128(2),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn reactions_are_grouped_by_emoji_in_first_seen_order() {
+        let me = Uuid::from_u128(10);
+        let summaries = summarize_reactions(
+            vec![reaction(11, "👍"), reaction(10, "❤️"), reaction(10, "👍"), reaction(12, "❤️")],
+            me,
+        );
+
+        let emoji: Vec<&str> = summaries.iter().map(|s| s.emoji.as_str()).collect();
+        assert_eq!(emoji, vec!["👍", "❤️"]);
+        assert_eq!(summaries[0].count, 2);
+        assert_eq!(summaries[0].user_ids, vec![Uuid::from_u128(11), me]);
+        assert!(summaries[0].reacted);
+        assert!(summaries[1].reacted);
+        assert!(summarize_reactions(vec![reaction(11, "🎉")], me).iter().all(|s| !s.reacted));
+    }
+}
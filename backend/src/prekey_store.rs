@@ -0,0 +1,131 @@
+// 🤝 PREKEY STORE MODULE
+// Where published X3DH prekeys live. The prekey endpoints only talk to the
+// `PrekeyStore` trait: Supabase in production, an in-memory store in tests so
+// whole X3DH handshakes can run offline.
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::encryption::x3dh::{PrekeyBundle, PrekeyUpload};
+use crate::errors::AppResult;
+use crate::supabase_api::SupabaseClient;
+
+/// A bundle handed out by `claim_bundle`
+#[derive(Debug, Clone)]
+pub struct ClaimedBundle {
+    pub bundle: PrekeyBundle,
+    pub remaining: usize,      // The owner's unclaimed one-time prekeys after this claim
+}
+
+/// Storage for users' published prekeys
+#[async_trait]
+pub trait PrekeyStore: Send + Sync {
+    /// Publish a user's identity and signed prekey, and add their one-time prekeys
+    /// A new identity key discards the one-time prekeys published under the old one.
+    async fn publish(&self, user_id: Uuid, upload: &PrekeyUpload, access_token: &str) -> AppResult<()>;
+
+    /// Hand out a user's bundle, using up one one-time prekey while any are left
+    async fn claim_bundle(&self, user_id: Uuid, access_token: &str) -> AppResult<Option<ClaimedBundle>>;
+
+    /// How many of a user's one-time prekeys are still unclaimed
+    async fn count_one_time_prekeys(&self, user_id: Uuid, access_token: &str) -> AppResult<usize>;
+}
+
+// 🗄️ SUPABASE STORE
+// Claims go through the `claim_prekey_bundle` database function, so concurrent
+// initiators never receive the same one-time prekey.
+#[async_trait]
+impl PrekeyStore for SupabaseClient {
+    async fn publish(&self, user_id: Uuid, upload: &PrekeyUpload, access_token: &str) -> AppResult<()> {
+        self.upsert_prekey_identity(user_id, upload, access_token).await?;
+        if !upload.one_time_prekeys.is_empty() {
+            self.store_one_time_prekeys(user_id, &upload.one_time_prekeys, access_token).await?;
+        }
+        Ok(())
+    }
+
+    async fn claim_bundle(&self, user_id: Uuid, access_token: &str) -> AppResult<Option<ClaimedBundle>> {
+        let claimed = self.claim_prekey_bundle(user_id, access_token).await?;
+        Ok(claimed.map(|claimed| ClaimedBundle {
+            bundle: claimed.bundle,
+            remaining: claimed.remaining.max(0) as usize,
+        }))
+    }
+
+    async fn count_one_time_prekeys(&self, user_id: Uuid, access_token: &str) -> AppResult<usize> {
+        let count = SupabaseClient::count_one_time_prekeys(self, user_id, access_token).await?;
+        Ok(count.max(0) as usize)
+    }
+}
+
+// 🧪 IN-MEMORY STORE
+// Mirrors the database: one-time prekeys are claimed lowest key ID first, claimed
+// key IDs stay taken, and every one-time prekey goes when the identity key changes.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryPrekeyStore {
+    users: std::sync::Mutex<std::collections::HashMap<Uuid, PublishedPrekeys>>,
+}
+
+#[cfg(test)]
+struct PublishedPrekeys {
+    upload: PrekeyUpload,         // One-time prekeys here are the unclaimed ones
+    claimed_key_ids: Vec<u32>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl PrekeyStore for MemoryPrekeyStore {
+    async fn publish(&self, user_id: Uuid, upload: &PrekeyUpload, _access_token: &str) -> AppResult<()> {
+        let mut users = self.users.lock().unwrap();
+        let kept = users.get(&user_id)
+            .filter(|existing| existing.upload.identity_key == upload.identity_key);
+        if let Some(existing) = kept {
+            let taken = existing.upload.one_time_prekeys.iter().map(|prekey| prekey.key_id)
+                .chain(existing.claimed_key_ids.iter().copied())
+                .collect::<Vec<_>>();
+            if let Some(prekey) = upload.one_time_prekeys.iter().find(|prekey| taken.contains(&prekey.key_id)) {
+                return Err(crate::errors::AppError::Conflict { message: format!("Prekey {} already uploaded", prekey.key_id) });
+            }
+        }
+
+        let (mut one_time_prekeys, claimed_key_ids) = match users.remove(&user_id) {
+            Some(existing) if existing.upload.identity_key == upload.identity_key => {
+                (existing.upload.one_time_prekeys, existing.claimed_key_ids)
+            }
+            _ => (Vec::new(), Vec::new()),
+        };
+        one_time_prekeys.extend(upload.one_time_prekeys.iter().cloned());
+        one_time_prekeys.sort_by_key(|prekey| prekey.key_id);
+
+        users.insert(user_id, PublishedPrekeys {
+            upload: PrekeyUpload { one_time_prekeys, ..upload.clone() },
+            claimed_key_ids,
+        });
+        Ok(())
+    }
+
+    async fn claim_bundle(&self, user_id: Uuid, _access_token: &str) -> AppResult<Option<ClaimedBundle>> {
+        let mut users = self.users.lock().unwrap();
+        let Some(PublishedPrekeys { upload: published, claimed_key_ids }) = users.get_mut(&user_id) else { return Ok(None) };
+        let one_time_prekey = (!published.one_time_prekeys.is_empty())
+            .then(|| published.one_time_prekeys.remove(0));
+        claimed_key_ids.extend(one_time_prekey.as_ref().map(|prekey| prekey.key_id));
+
+        Ok(Some(ClaimedBundle {
+            bundle: PrekeyBundle {
+                user_id,
+                identity_key: published.identity_key.clone(),
+                signing_key: published.signing_key.clone(),
+                signed_prekey: published.signed_prekey.clone(),
+                one_time_prekey,
+            },
+            remaining: published.one_time_prekeys.len(),
+        }))
+    }
+
+    async fn count_one_time_prekeys(&self, user_id: Uuid, _access_token: &str) -> AppResult<usize> {
+        let users = self.users.lock().unwrap();
+        Ok(users.get(&user_id).map_or(0, |published| published.upload.one_time_prekeys.len()))
+    }
+}
@@ -0,0 +1,293 @@
+/*
+🤝 PREKEYS MODULE
+=================
+
+Prekey distribution for X3DH session setup in 1:1 conversations
+(see `encryption::x3dh` and `encryption::ratchet`).
+
+FLOW:
+1. Each client publishes its identity, a signed prekey and a batch of one-time
+   prekeys: PUT /api/v1/keys/prekeys
+2. An initiator claims the other user's bundle: POST /api/v1/keys/{userId}/bundle
+   Every claim uses up one one-time prekey; once they run out, bundles carry
+   the signed prekey only (X3DH still works, with weaker replay protection)
+3. The owner gets a `prekeys_low` event when a claim leaves them below
+   `PREKEY_LOW_WATER_MARK`, and can check GET /api/v1/keys/prekeys/count
+
+The server only ever sees public keys. It checks that the signed prekey
+verifies against the uploaded identity, but initiators verify it themselves
+too (`x3dh::initiate`), so a tampered bundle can't go unnoticed.
+*/
+
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::auth::auth::{AccessToken, Claims};
+use crate::encryption::x3dh::{self, PrekeyUpload};
+use crate::errors::{AppError, AppResult};
+use crate::prekey_store::{ClaimedBundle, PrekeyStore};
+use crate::websocket::{OutgoingMessage, SessionManager};
+
+// Most one-time prekeys accepted in one upload
+const MAX_ONE_TIME_PREKEYS_PER_UPLOAD: usize = 100;
+
+/// Below this many unclaimed one-time prekeys, clients should upload more
+pub const PREKEY_LOW_WATER_MARK: usize = 10;
+
+// 📊 PREKEY COUNT RESPONSE
+#[derive(Debug, Serialize)]
+pub struct PrekeyCountResponse {
+    pub remaining: usize,
+    pub replenish: bool,  // True below the low-water mark
+}
+
+impl PrekeyCountResponse {
+    fn new(remaining: usize) -> Self {
+        Self { remaining, replenish: remaining < PREKEY_LOW_WATER_MARK }
+    }
+}
+
+// 🔍 Reject uploads that no initiator could use
+fn validate_upload(upload: &PrekeyUpload) -> AppResult<()> {
+    x3dh::verify_signed_prekey(&upload.identity_key, &upload.signing_key, &upload.signed_prekey)
+        .map_err(|e| AppError::bad_request(format!("Invalid signed prekey: {}", e)))?;
+
+    if upload.one_time_prekeys.len() > MAX_ONE_TIME_PREKEYS_PER_UPLOAD {
+        return Err(AppError::bad_request(format!(
+            "At most {} one-time prekeys per upload", MAX_ONE_TIME_PREKEYS_PER_UPLOAD
+        )));
+    }
+    let mut key_ids = HashSet::new();
+    for prekey in &upload.one_time_prekeys {
+        x3dh::decode_public_key(&prekey.public_key)
+            .map_err(|e| AppError::bad_request(format!("Invalid one-time prekey {}: {}", prekey.key_id, e)))?;
+        if !key_ids.insert(prekey.key_id) {
+            return Err(AppError::bad_request(format!("Duplicate one-time prekey ID {}", prekey.key_id)));
+        }
+    }
+    Ok(())
+}
+
+async fn publish_prekeys(
+    store: &dyn PrekeyStore,
+    user_id: Uuid,
+    upload: &PrekeyUpload,
+    access_token: &str,
+) -> AppResult<PrekeyCountResponse> {
+    validate_upload(upload)?;
+    store.publish(user_id, upload, access_token).await?;
+    let remaining = store.count_one_time_prekeys(user_id, access_token).await?;
+    Ok(PrekeyCountResponse::new(remaining))
+}
+
+async fn claim_prekeys(
+    store: &dyn PrekeyStore,
+    user_id: Uuid,
+    owner_id: Uuid,
+    access_token: &str,
+) -> AppResult<ClaimedBundle> {
+    if owner_id == user_id {
+        return Err(AppError::bad_request("Cannot claim your own prekey bundle"));
+    }
+    store.claim_bundle(owner_id, access_token).await?
+        .ok_or_else(|| AppError::NotFound { resource: format!("prekey bundle for user {}", owner_id) })
+}
+
+// 📤 UPLOAD PREKEYS ENDPOINT
+// PUT /api/v1/keys/prekeys
+pub async fn upload_prekeys(
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    prekey_store: web::Data<dyn PrekeyStore>,
+    upload: web::Json<PrekeyUpload>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+
+    let count = publish_prekeys(prekey_store.get_ref(), user_id, &upload, token.as_str()).await?;
+
+    log::info!("🤝 User {} published prekeys ({} one-time prekeys available)", user_id, count.remaining);
+    Ok(HttpResponse::Ok().json(count))
+}
+
+// 📊 PREKEY COUNT ENDPOINT
+// GET /api/v1/keys/prekeys/count
+pub async fn get_prekey_count(
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    prekey_store: web::Data<dyn PrekeyStore>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+
+    let remaining = prekey_store.count_one_time_prekeys(user_id, token.as_str()).await?;
+    Ok(HttpResponse::Ok().json(PrekeyCountResponse::new(remaining)))
+}
+
+// 🎟️ CLAIM PREKEY BUNDLE ENDPOINT
+// POST /api/v1/keys/{userId}/bundle
+// POST because every call uses up one of the owner's one-time prekeys
+pub async fn claim_prekey_bundle(
+    path: web::Path<Uuid>,
+    claims: web::ReqData<Claims>,
+    token: web::ReqData<AccessToken>,
+    prekey_store: web::Data<dyn PrekeyStore>,
+    session_manager: web::Data<SessionManager>,
+) -> AppResult<HttpResponse> {
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::auth_failed("Invalid user ID"))?;
+    let owner_id = path.into_inner();
+
+    let claimed = claim_prekeys(prekey_store.get_ref(), user_id, owner_id, token.as_str()).await?;
+
+    if claimed.remaining < PREKEY_LOW_WATER_MARK {
+        session_manager.send_to_users(&[owner_id], None, OutgoingMessage::PrekeysLow {
+            remaining: claimed.remaining,
+        });
+    }
+    if claimed.bundle.one_time_prekey.is_none() {
+        log::warn!("🎟️ User {} has no one-time prekeys left; handing out signed prekey only", owner_id);
+    }
+
+    Ok(HttpResponse::Ok().json(claimed.bundle))
+}
+
+// 🛤️ CONFIGURE PREKEY ROUTES
+// Key pair endpoints (`keys`) share the /keys scope
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/keys")
+            .route("/prekeys", web::put().to(upload_prekeys))
+            .route("/prekeys/count", web::get().to(get_prekey_count))
+            .route("/{userId}/bundle", web::post().to(claim_prekey_bundle))
+            .route("/rotate", web::post().to(crate::keys::rotate_keys))
+            .route("/private", web::get().to(crate::keys::get_private_keys))
+            .route("/private", web::put().to(crate::keys::put_private_keys))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::ratchet::RatchetSession;
+    use crate::encryption::x3dh::{IdentityKeyPair, PrekeyBundle, PrekeyKeyring};
+    use crate::prekey_store::MemoryPrekeyStore;
+
+    const TOKEN: &str = "test-token";
+
+    /// Alice starts a session from a claimed bundle; Bob answers her first message
+    fn handshake(alice: &IdentityKeyPair, bob: &mut PrekeyKeyring, bundle: &PrekeyBundle) {
+        let (alice_x3dh, header) = x3dh::initiate(alice, bundle).unwrap();
+        let signed_prekey = x3dh::decode_public_key(&bundle.signed_prekey.public_key).unwrap();
+        let mut alice_session = RatchetSession::initiate(alice_x3dh, &signed_prekey).unwrap();
+        let first = alice_session.encrypt(b"hi bob").unwrap();
+
+        let (bob_x3dh, bob_signed_prekey) = bob.respond(&header).unwrap();
+        let mut bob_session = RatchetSession::respond(bob_x3dh, bob_signed_prekey);
+        assert_eq!(bob_session.decrypt(&first).unwrap(), b"hi bob");
+        let reply = bob_session.encrypt(b"hi alice").unwrap();
+        assert_eq!(alice_session.decrypt(&reply).unwrap(), b"hi alice");
+
+        // A one-time prekey only ever works once
+        if header.one_time_prekey_id.is_some() {
+            assert!(bob.respond(&header).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_two_clients_handshake_until_prekeys_run_out() {
+        let store = MemoryPrekeyStore::default();
+        let (alice_id, bob_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let alice = IdentityKeyPair::generate();
+        let mut bob = PrekeyKeyring::new(IdentityKeyPair::generate());
+
+        let count = publish_prekeys(&store, bob_id, &bob.upload(2), TOKEN).await.unwrap();
+        assert_eq!(count.remaining, 2);
+        assert!(count.replenish);
+        assert!(claim_prekeys(&store, bob_id, bob_id, TOKEN).await.is_err());
+        assert!(claim_prekeys(&store, bob_id, alice_id, TOKEN).await.is_err());
+
+        let first = claim_prekeys(&store, alice_id, bob_id, TOKEN).await.unwrap();
+        let second = claim_prekeys(&store, alice_id, bob_id, TOKEN).await.unwrap();
+        let exhausted = claim_prekeys(&store, alice_id, bob_id, TOKEN).await.unwrap();
+        assert_eq!(first.remaining, 1);
+        assert_eq!(second.remaining, 0);
+        assert_ne!(first.bundle.one_time_prekey, second.bundle.one_time_prekey);
+        assert!(exhausted.bundle.one_time_prekey.is_none());
+
+        for claimed in [&first, &second, &exhausted] {
+            handshake(&alice, &mut bob, &claimed.bundle);
+        }
+
+        // Replenishing hands out one-time prekeys again
+        let count = publish_prekeys(&store, bob_id, &bob.upload(PREKEY_LOW_WATER_MARK), TOKEN).await.unwrap();
+        assert!(!count.replenish);
+        let replenished = claim_prekeys(&store, alice_id, bob_id, TOKEN).await.unwrap();
+        assert!(replenished.bundle.one_time_prekey.is_some());
+        handshake(&alice, &mut bob, &replenished.bundle);
+    }
+
+    #[tokio::test]
+    async fn test_signed_prekey_rotation_keeps_claimed_bundles_working() {
+        let store = MemoryPrekeyStore::default();
+        let (alice_id, bob_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let alice = IdentityKeyPair::generate();
+        let mut bob = PrekeyKeyring::new(IdentityKeyPair::generate());
+
+        publish_prekeys(&store, bob_id, &bob.upload(2), TOKEN).await.unwrap();
+        let before = claim_prekeys(&store, alice_id, bob_id, TOKEN).await.unwrap();
+
+        let rotated = bob.rotate_signed_prekey();
+        publish_prekeys(&store, bob_id, &bob.upload(0), TOKEN).await.unwrap();
+        let after = claim_prekeys(&store, alice_id, bob_id, TOKEN).await.unwrap();
+        assert_eq!(after.bundle.signed_prekey, rotated);
+        assert_eq!(after.bundle.identity_key, bob.identity().identity_key());
+
+        // A bundle claimed before the rotation still completes its handshake
+        handshake(&alice, &mut bob, &before.bundle);
+        handshake(&alice, &mut bob, &after.bundle);
+    }
+
+    #[tokio::test]
+    async fn test_new_identity_discards_old_one_time_prekeys() {
+        let store = MemoryPrekeyStore::default();
+        let (alice_id, bob_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut bob = PrekeyKeyring::new(IdentityKeyPair::generate());
+        publish_prekeys(&store, bob_id, &bob.upload(5), TOKEN).await.unwrap();
+        let claimed = claim_prekeys(&store, alice_id, bob_id, TOKEN).await.unwrap();
+
+        // Under the same identity a claimed key ID stays taken, and a rejected
+        // upload leaves what was published alone
+        let mut reused = bob.upload(1);
+        reused.one_time_prekeys[0].key_id = claimed.bundle.one_time_prekey.unwrap().key_id;
+        assert!(publish_prekeys(&store, bob_id, &reused, TOKEN).await.is_err());
+        assert_eq!(store.count_one_time_prekeys(bob_id, TOKEN).await.unwrap(), 4);
+
+        // A new identity starts over, free to reuse every key ID
+        let count = publish_prekeys(&store, bob_id, &PrekeyKeyring::new(IdentityKeyPair::generate()).upload(5), TOKEN).await.unwrap();
+        assert_eq!(count.remaining, 5);
+    }
+
+    #[test]
+    fn test_uploads_are_validated() {
+        let mut bob = PrekeyKeyring::new(IdentityKeyPair::generate());
+        assert!(validate_upload(&bob.upload(3)).is_ok());
+
+        // Signed by someone else
+        let mut forged = bob.upload(0);
+        forged.signed_prekey = PrekeyKeyring::new(IdentityKeyPair::generate()).upload(0).signed_prekey;
+        assert!(validate_upload(&forged).is_err());
+
+        let mut duplicate = bob.upload(2);
+        duplicate.one_time_prekeys[1].key_id = duplicate.one_time_prekeys[0].key_id;
+        assert!(validate_upload(&duplicate).is_err());
+
+        let mut malformed = bob.upload(1);
+        malformed.one_time_prekeys[0].public_key = "AAAA".to_string();
+        assert!(validate_upload(&malformed).is_err());
+
+        assert!(validate_upload(&bob.upload(MAX_ONE_TIME_PREKEYS_PER_UPLOAD + 1)).is_err());
+    }
+}
@@ -31,8 +31,11 @@ use chrono::{DateTime, Utc};
 use std::sync::{Arc, Mutex};
 use crate::errors::{AppError, AppResult};
 use crate::config::Config;
-use crate::database::{User, Message, MessageType, NewMessage};
+use crate::encryption::UNENCRYPTED_VERSION;
+use crate::encryption::x3dh::{OneTimePrekey, PrekeyBundle, PrekeyUpload};
+use crate::database::{User, Message, MediaStatus, MessageType, NewMessage, Conversation, ConversationMember, HiddenMessage, MessageAttachment, MessageRead, MessageContentUpdate, MessageReaction, MessageEdit, MessageStatus, NewConversation, ConversationSession, EncryptionKey, SessionKeyShare, UserPublicKey};
 use reqwest::Method;
+use sha2::{Digest, Sha256};
 
 
 // 🔐 SUPABASE API CLIENT
@@ -127,6 +130,64 @@ pub struct SelectRequest {
     pub offset: Option<i64>,
 }
 
+// 🧑‍🤝‍🧑 CONVERSATION LIST ENTRY
+// A conversation plus what a chat list needs to render it
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationSummary {
+    #[serde(flatten)]
+    pub conversation: Conversation,
+    pub participants: Vec<Uuid>,
+    pub last_message: Option<Message>,
+}
+
+// 📨 OUTCOME OF STORING A MESSAGE
+// Retried sends (same sender and `client_msg_id`) resolve to the original row
+#[derive(Debug, Clone)]
+pub enum MessageInsert {
+    Created(Message),    // A new row was written
+    Existing(Message),   // A duplicate of an earlier send; nothing was written
+}
+
+impl MessageInsert {
+    pub fn is_new(&self) -> bool {
+        matches!(self, MessageInsert::Created(_))
+    }
+    
+    pub fn into_message(self) -> Message {
+        match self {
+            MessageInsert::Created(message) | MessageInsert::Existing(message) => message,
+        }
+    }
+}
+
+// 🤝 OUTCOME OF CLAIMING A PREKEY BUNDLE
+// Shape returned by the `claim_prekey_bundle` function
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClaimedPrekeyBundle {
+    pub bundle: PrekeyBundle,
+    pub remaining: i64,  // The owner's unclaimed one-time prekeys after this claim
+}
+
+// 🙈 Leaves out messages the caller deleted for themselves. RLS on
+// `hidden_messages` only exposes the caller's own rows to the embed.
+const NOT_HIDDEN: &str = "select=*,hidden_messages(user_id)&hidden_messages=is.null";
+
+// Shape of `conversations` rows with embedded participants and latest message
+#[derive(Debug, Deserialize)]
+struct ConversationRow {
+    #[serde(flatten)]
+    conversation: Conversation,
+    #[serde(default)]
+    conversation_participants: Vec<ParticipantRef>,
+    #[serde(default)]
+    messages: Vec<Message>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ParticipantRef {
+    user_id: Uuid,
+}
+
 // 🔄 REALTIME SUBSCRIPTION TYPES
 #[derive(Debug, Serialize)]
 pub struct RealtimeSubscription {
@@ -287,49 +348,144 @@ impl SupabaseClient {
     // 💬 MESSAGE OPERATIONS
     
     /// Create a new encrypted message
-    pub async fn create_message(&self, message: &NewMessage, sender_id: Uuid, access_token: &str) -> AppResult<Message> {
-        // 🔐 ENCRYPTION: The message content should already be encrypted by the caller
-        // This method expects the message to contain encrypted content
-        let message_data = json!({
-            "sender_id": sender_id,
-            "receiver_id": message.receiver_id,
-            "encrypted_content": message.content, // This should be the encrypted content
-            "content_hash": "TODO: Generate hash", // TODO: Generate content hash
-            "encryption_version": 1,
-            "nonce": "TODO: Generate nonce", // TODO: Generate nonce
-            "session_key_id": "TODO: Get session key ID", // TODO: Get session key ID
-            "message_type": message.message_type.as_ref().map(|mt| format!("{:?}", mt).to_lowercase()).unwrap_or_else(|| "text".to_string()),
-            "is_read": false,
-            "file_url": message.file_url,
-            "file_size": message.file_size,
-            "mime_type": message.mime_type,
-            "created_at": Utc::now(),
-            "updated_at": Utc::now()
-        });
-        
-        let response = self.post("/rest/v1/messages", &message_data, false, Some(access_token)).await?;
+    /// Idempotent per (sender_id, client_msg_id): a retry returns the original message
+    pub async fn create_message(&self, message: &NewMessage, sender_id: Uuid, access_token: &str) -> AppResult<MessageInsert> {
+        let message_data = new_message_row(message, sender_id);
+        
+        // 🔁 With a client_msg_id, a conflicting insert is skipped and returns no rows
+        let response = match message.client_msg_id {
+            Some(_) => self.post_with_prefer(
+                "/rest/v1/messages?on_conflict=sender_id,client_msg_id",
+                &message_data,
+                access_token,
+                "resolution=ignore-duplicates,return=representation",
+            ).await?,
+            None => self.post("/rest/v1/messages", &message_data, false, Some(access_token)).await?,
+        };
         self.log_audit("create_message", Some(sender_id), "messages", true, Some(message_data.clone()), Some(response.clone()));
         
         let messages: Vec<Message> = serde_json::from_value(response)
             .map_err(|e| AppError::Internal { message: format!("Failed to parse message response: {}", e) })?;
         
-        messages.into_iter().next()
-            .ok_or_else(|| AppError::Internal { message: "No message returned from create".to_string() })
+        if let Some(created) = messages.into_iter().next() {
+            return Ok(MessageInsert::Created(created));
+        }
+        
+        // Nothing inserted: this is a retry, so hand back the original
+        let client_msg_id = message.client_msg_id
+            .ok_or_else(|| AppError::Internal { message: "No message returned from create".to_string() })?;
+        self.find_message_by_client_id(sender_id, client_msg_id, access_token).await?
+            .map(MessageInsert::Existing)
+            .ok_or_else(|| AppError::Internal { message: "Duplicate message could not be loaded".to_string() })
+    }
+    
+    /// Find a message by the sender's client-generated ID
+    pub async fn find_message_by_client_id(&self, sender_id: Uuid, client_msg_id: Uuid, access_token: &str) -> AppResult<Option<Message>> {
+        let url = format!("/rest/v1/messages?sender_id=eq.{}&client_msg_id=eq.{}", sender_id, client_msg_id);
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("find_message_by_client_id", Some(sender_id), "messages", true, None, Some(response.clone()));
+        
+        let messages: Vec<Message> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse message response: {}", e) })?;
+        
+        Ok(messages.into_iter().next())
     }
     
     /// Get conversation between two users
     pub async fn get_conversation(&self, user1_id: Uuid, user2_id: Uuid, limit: i64, access_token: &str) -> AppResult<Vec<Message>> {
-        let filter = format!(
-            "or(sender_id.eq.{},receiver_id.eq.{})",
-            user1_id, user1_id
+        let conversation_id = crate::encryption::create_conversation_id(user1_id, user2_id);
+        self.get_conversation_messages(conversation_id, limit, None, access_token).await
+    }
+    
+    /// Get messages of a conversation, newest first
+    pub async fn get_conversation_messages(&self, conversation_id: Uuid, limit: i64, before: Option<DateTime<Utc>>, access_token: &str) -> AppResult<Vec<Message>> {
+        let mut url = format!(
+            "/rest/v1/messages?conversation_id=eq.{}&{}&order=created_at.desc&limit={}",
+            conversation_id, NOT_HIDDEN, limit
         );
+        if let Some(before) = before {
+            url.push_str(&format!("&created_at=lt.{}", urlencoding::encode(&before.to_rfc3339())));
+        }
+        
+        let response = self.get(&url, access_token).await?;
+        self.log_audit("get_conversation_messages", None, "messages", true, None, Some(response.clone()));
+        
+        let messages: Vec<Message> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse messages response: {}", e) })?;
+        
+        Ok(messages)
+    }
+    
+    /// Get a single message by ID
+    pub async fn get_message(&self, message_id: Uuid, access_token: &str) -> AppResult<Option<Message>> {
+        let url = format!("/rest/v1/messages?id=eq.{}", message_id);
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_message", None, "messages", true, None, Some(response.clone()));
+        
+        let messages: Vec<Message> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse message response: {}", e) })?;
+        
+        Ok(messages.into_iter().next())
+    }
+    
+    /// Replace a message's content; the database keeps the old revision in `message_edits`
+    /// Returns None if the message doesn't exist or wasn't sent by `sender_id`
+    pub async fn update_message_content(&self, message_id: Uuid, sender_id: Uuid, update: &MessageContentUpdate, access_token: &str) -> AppResult<Option<Message>> {
+        let url = format!("/rest/v1/messages?id=eq.{}&sender_id=eq.{}", message_id, sender_id);
+        let mut update_data = json!({
+            "encrypted_content": update.encrypted_content,
+            "content_hash": update.content_hash,
+            "updated_at": Utc::now()
+        });
+        if let Some(nonce) = &update.nonce {
+            update_data["nonce"] = json!(nonce);
+        }
+        if let Some(session_key_id) = update.session_key_id {
+            update_data["session_key_id"] = json!(session_key_id);
+        }
+        if let Some(session_key_generation) = update.session_key_generation {
+            update_data["session_key_generation"] = json!(session_key_generation);
+        }
+        if let Some(encryption_version) = update.encryption_version {
+            update_data["encryption_version"] = json!(encryption_version);
+        }
+        // ✍️ A signature only covers the content it was made for, so it's always replaced
+        update_data["signature"] = json!(update.signature.as_ref().map(|s| &s.signature));
+        update_data["signature_key_version"] = json!(update.signature.as_ref().map(|s| s.key_version));
+        
+        let response = self.patch(&url, &update_data, access_token).await?;
+        self.log_audit("update_message_content", Some(sender_id), "messages", true, Some(update_data), Some(response.clone()));
+        
+        let messages: Vec<Message> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse message response: {}", e) })?;
+        
+        Ok(messages.into_iter().next())
+    }
+    
+    /// Get the prior revisions of a message, oldest first
+    pub async fn get_message_edits(&self, message_id: Uuid, access_token: &str) -> AppResult<Vec<MessageEdit>> {
+        let url = format!("/rest/v1/message_edits?message_id=eq.{}&order=created_at.asc", message_id);
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_message_edits", None, "message_edits", true, None, Some(response.clone()));
+        
+        let edits: Vec<MessageEdit> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse message edits response: {}", e) })?;
+        
+        Ok(edits)
+    }
+    
+    /// Get messages edited after a sync position, oldest edit first
+    pub async fn get_edits_since(&self, after: DateTime<Utc>, after_id: Option<Uuid>, limit: i64, access_token: &str) -> AppResult<Vec<Message>> {
         let url = format!(
-            "/rest/v1/messages?{}&sender_id.eq.{}&receiver_id.eq.{},sender_id.eq.{}&receiver_id.eq.{}&order=created_at.desc&limit={}",
-            filter, user1_id, user2_id, user2_id, user1_id, limit
+            "/rest/v1/messages?{}&order=edited_at.asc,id.asc&limit={}",
+            keyset_filter("edited_at", after, after_id), limit
         );
-        
         let response = self.get(&url, access_token).await?;
-        self.log_audit("get_conversation", Some(user1_id), "messages", true, None, Some(response.clone()));
+        
+        self.log_audit("get_edits_since", None, "messages", true, None, Some(response.clone()));
         
         let messages: Vec<Message> = serde_json::from_value(response)
             .map_err(|e| AppError::Internal { message: format!("Failed to parse messages response: {}", e) })?;
@@ -337,20 +493,436 @@ impl SupabaseClient {
         Ok(messages)
     }
     
-    /// Mark message as read
-    pub async fn mark_message_read(&self, message_id: Uuid, user_id: Uuid, access_token: &str) -> AppResult<()> {
-        let url = format!("/rest/v1/messages?id=eq.{}&receiver_id.eq.{}", message_id, user_id);
+    /// Delete a message for everyone, leaving a tombstone
+    /// The database wipes its content, edit history, attachments and reactions.
+    /// Returns None if it doesn't exist, isn't the sender's, or is already deleted.
+    pub async fn delete_message_for_everyone(&self, message_id: Uuid, sender_id: Uuid, access_token: &str) -> AppResult<Option<Message>> {
+        let url = format!("/rest/v1/messages?id=eq.{}&sender_id=eq.{}&deleted_at=is.null", message_id, sender_id);
         let update_data = json!({
-            "is_read": true,
-            "updated_at": Utc::now()
+            "deleted_at": Utc::now(),
+            "deleted_by": sender_id
         });
         
         let response = self.patch(&url, &update_data, access_token).await?;
-        self.log_audit("mark_message_read", Some(user_id), "messages", true, Some(update_data), Some(response));
+        self.log_audit("delete_message_for_everyone", Some(sender_id), "messages", true, Some(update_data), Some(response.clone()));
+        
+        let messages: Vec<Message> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse message response: {}", e) })?;
+        
+        Ok(messages.into_iter().next())
+    }
+    
+    /// Hide a message for one user only ("delete for me"); hiding twice is a no-op
+    pub async fn hide_message(&self, message: &Message, user_id: Uuid, access_token: &str) -> AppResult<()> {
+        let hidden_data = json!({
+            "message_id": message.id,
+            "user_id": user_id,
+            "conversation_id": message.conversation_id,
+            "hidden_at": Utc::now()
+        });
+        
+        let response = self.post_with_prefer(
+            "/rest/v1/hidden_messages?on_conflict=message_id,user_id",
+            &hidden_data,
+            access_token,
+            "resolution=ignore-duplicates,return=representation",
+        ).await?;
+        self.log_audit("hide_message", Some(user_id), "hidden_messages", true, Some(hidden_data), Some(response));
         
         Ok(())
     }
     
+    /// Add a reaction; returns false if the user already reacted with this emoji
+    pub async fn add_reaction(&self, message: &Message, user_id: Uuid, emoji: &str, access_token: &str) -> AppResult<bool> {
+        let reaction_data = json!({
+            "message_id": message.id,
+            "user_id": user_id,
+            "emoji": emoji,
+            "conversation_id": message.conversation_id,
+            "created_at": Utc::now()
+        });
+        
+        let response = self.post_with_prefer(
+            "/rest/v1/message_reactions?on_conflict=message_id,user_id,emoji",
+            &reaction_data,
+            access_token,
+            "resolution=ignore-duplicates,return=representation",
+        ).await?;
+        self.log_audit("add_reaction", Some(user_id), "message_reactions", true, Some(reaction_data), Some(response.clone()));
+        
+        // Ignored duplicates come back as an empty array
+        Ok(response.as_array().is_some_and(|rows| !rows.is_empty()))
+    }
+    
+    /// Remove a reaction; returns false if there was nothing to remove
+    pub async fn remove_reaction(&self, message_id: Uuid, user_id: Uuid, emoji: &str, access_token: &str) -> AppResult<bool> {
+        let url = format!(
+            "/rest/v1/message_reactions?message_id=eq.{}&user_id=eq.{}&emoji=eq.{}",
+            message_id, user_id, urlencoding::encode(emoji)
+        );
+        let response = self.delete(&url, access_token).await?;
+        self.log_audit("remove_reaction", Some(user_id), "message_reactions", true, None, Some(response.clone()));
+        
+        Ok(response.as_array().is_some_and(|rows| !rows.is_empty()))
+    }
+    
+    /// Get all reactions on the given messages, oldest first
+    pub async fn get_reactions(&self, message_ids: &[Uuid], access_token: &str) -> AppResult<Vec<MessageReaction>> {
+        if message_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ids: Vec<String> = message_ids.iter().map(Uuid::to_string).collect();
+        let url = format!("/rest/v1/message_reactions?message_id=in.({})&order=created_at.asc", ids.join(","));
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_reactions", None, "message_reactions", true, None, Some(response.clone()));
+        
+        let reactions: Vec<MessageReaction> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse reactions response: {}", e) })?;
+        
+        Ok(reactions)
+    }
+    
+    /// Record an uploaded attachment; RLS only lets the message's sender do this
+    pub async fn create_attachment(&self, attachment: &MessageAttachment, access_token: &str) -> AppResult<MessageAttachment> {
+        let attachment_data = serde_json::to_value(attachment)
+            .map_err(|e| AppError::Internal { message: format!("Failed to serialize attachment: {}", e) })?;
+        
+        let response = self.post("/rest/v1/message_attachments", &attachment_data, false, Some(access_token)).await?;
+        self.log_audit("create_attachment", None, "message_attachments", true, Some(attachment_data), Some(response.clone()));
+        
+        let attachments: Vec<MessageAttachment> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse attachment response: {}", e) })?;
+        
+        attachments.into_iter().next()
+            .ok_or_else(|| AppError::Internal { message: "No attachment returned from create".to_string() })
+    }
+    
+    /// Get a single attachment; RLS hides attachments outside the caller's conversations
+    pub async fn get_attachment(&self, attachment_id: Uuid, access_token: &str) -> AppResult<Option<MessageAttachment>> {
+        let url = format!("/rest/v1/message_attachments?id=eq.{}", attachment_id);
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_attachment", None, "message_attachments", true, None, Some(response.clone()));
+        
+        let attachments: Vec<MessageAttachment> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse attachment response: {}", e) })?;
+        
+        Ok(attachments.into_iter().next())
+    }
+    
+    /// Get a message's attachments in upload order
+    pub async fn get_message_attachments(&self, message_id: Uuid, access_token: &str) -> AppResult<Vec<MessageAttachment>> {
+        let url = format!("/rest/v1/message_attachments?message_id=eq.{}&order=created_at.asc", message_id);
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_message_attachments", None, "message_attachments", true, None, Some(response.clone()));
+        
+        let attachments: Vec<MessageAttachment> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse attachments response: {}", e) })?;
+        
+        Ok(attachments)
+    }
+    
+    /// Point a message's file fields at its first attachment
+    /// Returns None if the message already has a file, was deleted, or isn't the sender's
+    pub async fn set_message_file(&self, message_id: Uuid, sender_id: Uuid, attachment: &MessageAttachment, file_url: &str, access_token: &str) -> AppResult<Option<Message>> {
+        let url = format!(
+            "/rest/v1/messages?id=eq.{}&sender_id=eq.{}&deleted_at=is.null&file_url=is.null",
+            message_id, sender_id
+        );
+        let update_data = json!({
+            "file_url": file_url,
+            "file_size": attachment.file_size,
+            "mime_type": attachment.mime_type
+        });
+        
+        let response = self.patch(&url, &update_data, access_token).await?;
+        self.log_audit("set_message_file", Some(sender_id), "messages", true, Some(update_data), Some(response.clone()));
+        
+        let messages: Vec<Message> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse message response: {}", e) })?;
+        
+        Ok(messages.into_iter().next())
+    }
+    
+    /// Record the thumbnail job's results on an attachment
+    /// SECURITY: Uses service_role_key; clients have no UPDATE policy on attachments
+    /// Returns None if the attachment is gone (its message was deleted meanwhile)
+    pub async fn update_attachment_media(&self, attachment_id: Uuid, update_data: &Value) -> AppResult<Option<MessageAttachment>> {
+        let url = format!("/rest/v1/message_attachments?id=eq.{}", attachment_id);
+        let response = self.patch(&url, update_data, &self.service_role_key).await?;
+        
+        self.log_audit("update_attachment_media", None, "message_attachments", true, Some(update_data.clone()), Some(response.clone()));
+        
+        let attachments: Vec<MessageAttachment> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse attachment response: {}", e) })?;
+        
+        Ok(attachments.into_iter().next())
+    }
+    
+    /// Mark every attachment still `pending` from before `before` as `failed`
+    /// SECURITY: Uses service_role_key, called once at startup for jobs a restart cut short
+    pub async fn fail_pending_attachments(&self, before: DateTime<Utc>) -> AppResult<Vec<MessageAttachment>> {
+        let url = format!(
+            "/rest/v1/message_attachments?media_status=eq.pending&created_at=lt.{}",
+            urlencoding::encode(&before.to_rfc3339_opts(chrono::SecondsFormat::Micros, true))
+        );
+        let update_data = json!({ "media_status": MediaStatus::Failed, "processed_at": Utc::now() });
+        let response = self.patch(&url, &update_data, &self.service_role_key).await?;
+        
+        self.log_audit("fail_pending_attachments", None, "message_attachments", true, Some(update_data), Some(response.clone()));
+        
+        serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse attachment response: {}", e) })
+    }
+    
+    /// Keep a message's file size in step with its (re-stored) first attachment
+    /// Only applies while `file_url` is still the message's file, i.e. that attachment is its first.
+    /// SECURITY: Uses service_role_key, called by the thumbnail job only
+    pub async fn update_message_file_size(&self, message_id: Uuid, file_url: &str, file_size: i64) -> AppResult<()> {
+        let url = format!(
+            "/rest/v1/messages?id=eq.{}&file_url=eq.{}&deleted_at=is.null",
+            message_id, urlencoding::encode(file_url)
+        );
+        let update_data = json!({ "file_size": file_size });
+        let response = self.patch(&url, &update_data, &self.service_role_key).await?;
+        
+        self.log_audit("update_message_file_size", None, "messages", true, Some(update_data), Some(response));
+        
+        Ok(())
+    }
+    
+    /// Get messages deleted for everyone after a sync position, oldest deletion first
+    pub async fn get_deletions_since(&self, after: DateTime<Utc>, after_id: Option<Uuid>, limit: i64, access_token: &str) -> AppResult<Vec<Message>> {
+        let url = format!(
+            "/rest/v1/messages?{}&order=deleted_at.asc,id.asc&limit={}",
+            keyset_filter("deleted_at", after, after_id), limit
+        );
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_deletions_since", None, "messages", true, None, Some(response.clone()));
+        
+        let messages: Vec<Message> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse messages response: {}", e) })?;
+        
+        Ok(messages)
+    }
+    
+    /// Get messages the user hid for themselves after a sync position, oldest first
+    pub async fn get_hidden_since(&self, user_id: Uuid, after: DateTime<Utc>, after_id: Option<Uuid>, limit: i64, access_token: &str) -> AppResult<Vec<HiddenMessage>> {
+        // hidden_messages has no `id` column, so ties are broken on message_id
+        let url = format!(
+            "/rest/v1/hidden_messages?user_id=eq.{}&{}&order=hidden_at.asc,message_id.asc&limit={}",
+            user_id, keyset_filter_on("hidden_at", "message_id", after, after_id), limit
+        );
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_hidden_since", Some(user_id), "hidden_messages", true, None, Some(response.clone()));
+        
+        let hidden: Vec<HiddenMessage> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse hidden messages response: {}", e) })?;
+        
+        Ok(hidden)
+    }
+    
+    /// Get the replies to a message, oldest first
+    pub async fn get_thread_replies(&self, root_id: Uuid, limit: i64, after: Option<DateTime<Utc>>, access_token: &str) -> AppResult<Vec<Message>> {
+        let mut url = format!(
+            "/rest/v1/messages?reply_to_id=eq.{}&{}&order=created_at.asc,id.asc&limit={}",
+            root_id, NOT_HIDDEN, limit
+        );
+        if let Some(after) = after {
+            url.push_str(&format!("&{}", keyset_filter("created_at", after, None)));
+        }
+        
+        let response = self.get(&url, access_token).await?;
+        self.log_audit("get_thread_replies", None, "messages", true, None, Some(response.clone()));
+        
+        let messages: Vec<Message> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse messages response: {}", e) })?;
+        
+        Ok(messages)
+    }
+    
+    /// Get messages created after a sync position, oldest first
+    /// RLS limits the result to conversations the caller participates in
+    pub async fn get_messages_since(&self, after: DateTime<Utc>, after_id: Option<Uuid>, limit: i64, access_token: &str) -> AppResult<Vec<Message>> {
+        let url = format!(
+            "/rest/v1/messages?{}&{}&order=created_at.asc,id.asc&limit={}",
+            keyset_filter("created_at", after, after_id), NOT_HIDDEN, limit
+        );
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_messages_since", None, "messages", true, None, Some(response.clone()));
+        
+        let messages: Vec<Message> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse messages response: {}", e) })?;
+        
+        Ok(messages)
+    }
+    
+    /// Get reads of the user's sent messages recorded after a sync position, oldest first
+    /// One row per reader, so group messages yield a receipt from every member.
+    pub async fn get_read_receipts_since(&self, user_id: Uuid, after: DateTime<Utc>, after_ids: Option<(Uuid, Uuid)>, limit: i64, access_token: &str) -> AppResult<Vec<MessageRead>> {
+        // A message can be read by several group members at the same instant,
+        // so the reader is part of the position too
+        let url = format!(
+            "/rest/v1/message_reads?select=*,messages!inner(sender_id)&messages.sender_id=eq.{}&{}&order=read_at.asc,message_id.asc,user_id.asc&limit={}",
+            user_id, keyset_filter_on_pair("read_at", ("message_id", "user_id"), after, after_ids), limit
+        );
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_read_receipts_since", Some(user_id), "message_reads", true, None, Some(response.clone()));
+        
+        let reads: Vec<MessageRead> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse message reads response: {}", e) })?;
+        
+        Ok(reads)
+    }
+    
+    /// Get the user's sent messages that were delivered after a sync position, oldest delivery first
+    pub async fn get_delivery_receipts_since(&self, user_id: Uuid, after: DateTime<Utc>, after_id: Option<Uuid>, limit: i64, access_token: &str) -> AppResult<Vec<Message>> {
+        let url = format!(
+            "/rest/v1/messages?sender_id=eq.{}&{}&order=delivered_at.asc,id.asc&limit={}",
+            user_id, keyset_filter("delivered_at", after, after_id), limit
+        );
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_delivery_receipts_since", Some(user_id), "messages", true, None, Some(response.clone()));
+        
+        let messages: Vec<Message> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse messages response: {}", e) })?;
+        
+        Ok(messages)
+    }
+    
+    // 🧑‍🤝‍🧑 CONVERSATION OPERATIONS
+    
+    /// Create a group conversation together with its participants
+    /// Both go in through `create_group_conversation`, so a failure leaves nothing behind.
+    pub async fn create_conversation(&self, conversation: &NewConversation, created_by: Uuid, access_token: &str) -> AppResult<Conversation> {
+        let request_data = json!({
+            "p_name": conversation.name,
+            "p_participant_ids": conversation.participant_ids,
+        });
+        
+        let response = self.post("/rest/v1/rpc/create_group_conversation", &request_data, false, Some(access_token)).await?;
+        self.log_audit("create_conversation", Some(created_by), "conversations", true, Some(request_data), Some(response.clone()));
+        
+        let created: Conversation = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse conversation response: {}", e) })?;
+        
+        Ok(created)
+    }
+    
+    /// Get a conversation by ID (None if it doesn't exist or the caller isn't in it)
+    pub async fn get_conversation_by_id(&self, conversation_id: Uuid, access_token: &str) -> AppResult<Option<Conversation>> {
+        let url = format!("/rest/v1/conversations?id=eq.{}", conversation_id);
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_conversation_by_id", None, "conversations", true, None, Some(response.clone()));
+        
+        let conversations: Vec<Conversation> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse conversation response: {}", e) })?;
+        
+        Ok(conversations.into_iter().next())
+    }
+    
+    /// Get the 1:1 conversation between two users, creating it on first use
+    /// `create_direct_conversation` derives the ID itself and only ever adds the two of them.
+    pub async fn get_or_create_direct_conversation(&self, user_id: Uuid, other_user_id: Uuid, access_token: &str) -> AppResult<Conversation> {
+        let request_data = json!({ "p_other_user_id": other_user_id });
+        
+        let response = self.post("/rest/v1/rpc/create_direct_conversation", &request_data, false, Some(access_token)).await?;
+        self.log_audit("get_or_create_direct_conversation", Some(user_id), "conversations", true, Some(request_data), Some(response.clone()));
+        
+        let conversation: Conversation = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse conversation response: {}", e) })?;
+        
+        Ok(conversation)
+    }
+    
+    /// Get the user IDs participating in a conversation
+    pub async fn get_conversation_participants(&self, conversation_id: Uuid, access_token: &str) -> AppResult<Vec<Uuid>> {
+        let url = format!("/rest/v1/conversation_participants?conversation_id=eq.{}&order=joined_at.asc", conversation_id);
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_conversation_participants", None, "conversation_participants", true, None, Some(response.clone()));
+        
+        let participants: Vec<ConversationMember> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse participants response: {}", e) })?;
+        
+        Ok(participants.into_iter().map(|p| p.user_id).collect())
+    }
+    
+    /// Get every conversation a user participates in, most recently active first
+    pub async fn get_user_conversations(&self, user_id: Uuid, access_token: &str) -> AppResult<Vec<ConversationSummary>> {
+        // Step 1: Which conversations is the user in?
+        let url = format!("/rest/v1/conversation_participants?user_id=eq.{}&select=conversation_id", user_id);
+        let response = self.get(&url, access_token).await?;
+        
+        let conversation_ids: Vec<Uuid> = response.as_array()
+            .map(|rows| rows.iter()
+                .filter_map(|row| row.get("conversation_id")?.as_str()?.parse().ok())
+                .collect())
+            .unwrap_or_default();
+        
+        if conversation_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        // Step 2: Load them with all participants and the latest message embedded,
+        // skipping messages the user hid (`NOT_HIDDEN`, one level down)
+        let ids = conversation_ids.iter().map(Uuid::to_string).collect::<Vec<_>>().join(",");
+        let url = format!(
+            "/rest/v1/conversations?id=in.({})&select=*,conversation_participants(user_id),messages(*,hidden_messages(user_id))&messages.hidden_messages=is.null&messages.order=created_at.desc&messages.limit=1&order=updated_at.desc",
+            ids
+        );
+        let response = self.get(&url, access_token).await?;
+        self.log_audit("get_user_conversations", Some(user_id), "conversations", true, None, Some(response.clone()));
+        
+        let rows: Vec<ConversationRow> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse conversations response: {}", e) })?;
+        
+        Ok(rows.into_iter().map(|row| ConversationSummary {
+            conversation: row.conversation,
+            participants: row.conversation_participants.into_iter().map(|p| p.user_id).collect(),
+            last_message: row.messages.into_iter().next(),
+        }).collect())
+    }
+    
+    /// Mark message as read by one participant (a row of `message_reads`)
+    /// Returns the message if this call recorded a new read (None if they already
+    /// read it, sent it, or aren't in its conversation). Works for group messages too.
+    pub async fn mark_message_read(&self, message_id: Uuid, user_id: Uuid, access_token: &str) -> AppResult<Option<Message>> {
+        // 🔐 Recipients can't update messages directly; the function only touches the read columns
+        let request_data = json!({ "p_message_id": message_id });
+        
+        let response = self.post("/rest/v1/rpc/mark_message_read", &request_data, false, Some(access_token)).await?;
+        self.log_audit("mark_message_read", Some(user_id), "messages", true, Some(request_data), Some(response.clone()));
+        
+        let messages: Vec<Message> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse message response: {}", e) })?;
+        
+        Ok(messages.into_iter().next())
+    }
+    
+    /// Mark messages as delivered to a recipient device, in one call
+    /// Returns the messages this call changed (not those already delivered or read).
+    pub async fn mark_messages_delivered(&self, message_ids: &[Uuid], user_id: Uuid, access_token: &str) -> AppResult<Vec<Message>> {
+        // 🔐 The function only moves `sent` messages forward, and never the sender's own
+        let request_data = json!({ "p_message_ids": message_ids });
+        
+        let response = self.post("/rest/v1/rpc/mark_messages_delivered", &request_data, false, Some(access_token)).await?;
+        self.log_audit("mark_messages_delivered", Some(user_id), "messages", true, Some(request_data), Some(response.clone()));
+        
+        let messages: Vec<Message> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse message response: {}", e) })?;
+        
+        Ok(messages)
+    }
+    
     /// Get unread message count for user
     pub async fn get_unread_count(&self, user_id: Uuid, access_token: &str) -> AppResult<i64> {
         let url = format!("/rest/v1/messages?receiver_id.eq.{}&is_read.eq.false&select=count", user_id);
@@ -368,48 +940,52 @@ impl SupabaseClient {
         Ok(count)
     }
     
-    /// Search messages (encrypted content cannot be searched directly)
-    /// Note: This will need to be implemented differently for encrypted messages
-    pub async fn search_messages(&self, user_id: Uuid, query: &str, with_user: Option<Uuid>, limit: i64, access_token: &str) -> AppResult<Vec<Message>> {
-        // 🔐 ENCRYPTION NOTE: Cannot search encrypted content directly
-        // This is a limitation of end-to-end encryption
-        // In a real implementation, you might use:
-        // 1. Client-side search after decryption
-        // 2. Encrypted search indexes
-        // 3. Metadata-based search only
-        
-        let mut filter = format!("or(sender_id.eq.{},receiver_id.eq.{})", user_id, user_id);
-        
-        if let Some(other_user) = with_user {
-            filter = format!("and({},or(sender_id.eq.{},receiver_id.eq.{}))", filter, other_user, other_user);
-        }
-        
-        // For now, return empty results since we can't search encrypted content
-        log::warn!("Search not implemented for encrypted messages");
-        Ok(Vec::new())
-    }
-
     // 🔐 ENCRYPTION KEY OPERATIONS
 
-    /// Store encryption key for a user
-    pub async fn store_encryption_key(&self, user_id: Uuid, key_data: &Value, access_token: &str) -> AppResult<()> {
-        let response = self.post("/rest/v1/encryption_keys", key_data, false, Some(access_token)).await?;
-        self.log_audit("store_encryption_key", Some(user_id), "encryption_keys", true, Some(key_data.clone()), Some(response));
+    /// Store a user's next key pair, expiring the version it replaces (if any)
+    /// One function call, so a rotation never leaves two live keys or none.
+    pub async fn store_encryption_key(&self, key: &EncryptionKey, replaces: Option<i32>, access_token: &str) -> AppResult<()> {
+        let request_data = json!({
+            "p_encrypted_private_key": key.encrypted_private_key,
+            "p_kdf_params": key.kdf_params,
+            "p_public_key": key.public_key,
+            "p_key_version": key.key_version,
+            "p_algorithm": key.algorithm,
+            "p_replaces": replaces,
+        });
+        
+        let response = self.post("/rest/v1/rpc/rotate_encryption_key", &request_data, false, Some(access_token)).await?;
+        self.log_audit("store_encryption_key", Some(key.user_id), "encryption_keys", true, Some(request_data), Some(response));
         Ok(())
     }
 
-    /// Get encryption key for a user
-    pub async fn get_encryption_key(&self, user_id: Uuid, access_token: &str) -> AppResult<Option<Value>> {
-        let url = format!("/rest/v1/encryption_keys?user_id=eq.{}&order=key_version.desc&limit=1", user_id);
+    /// Get all of a user's key pairs, oldest first; RLS only returns the caller's own
+    pub async fn get_encryption_keys(&self, user_id: Uuid, access_token: &str) -> AppResult<Vec<EncryptionKey>> {
+        let url = format!("/rest/v1/encryption_keys?user_id=eq.{}&order=key_version.asc", user_id);
         let response = self.get(&url, access_token).await?;
         
-        self.log_audit("get_encryption_key", Some(user_id), "encryption_keys", true, None, Some(response.clone()));
+        self.log_audit("get_encryption_keys", Some(user_id), "encryption_keys", true, None, Some(response.clone()));
         
-        if let Some(keys) = response.as_array() {
-            Ok(keys.first().cloned())
-        } else {
-            Ok(None)
-        }
+        let keys: Vec<EncryptionKey> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse encryption keys response: {}", e) })?;
+        
+        Ok(keys)
+    }
+
+    /// Write back re-sealed private keys, e.g. after a passphrase change
+    /// One upsert, so either every key moves to the new passphrase or none does.
+    pub async fn reseal_private_keys(&self, user_id: Uuid, keys: &[EncryptionKey], access_token: &str) -> AppResult<()> {
+        let keys_data = serde_json::to_value(keys)
+            .map_err(|e| AppError::Internal { message: format!("Failed to serialize encryption keys: {}", e) })?;
+        
+        let response = self.post_with_prefer(
+            "/rest/v1/encryption_keys?on_conflict=user_id,key_version",
+            &keys_data,
+            access_token,
+            "resolution=merge-duplicates,return=representation",
+        ).await?;
+        self.log_audit("reseal_private_keys", Some(user_id), "encryption_keys", true, Some(keys_data), Some(response));
+        Ok(())
     }
 
     /// Store conversation session key
@@ -419,21 +995,155 @@ impl SupabaseClient {
         Ok(())
     }
 
-    /// Get conversation session key
-    pub async fn get_conversation_session(&self, user1_id: Uuid, user2_id: Uuid, access_token: &str) -> AppResult<Option<Value>> {
-        let url = format!(
-            "/rest/v1/conversation_sessions?or(and(user1_id.eq.{},user2_id.eq.{}),and(user1_id.eq.{},user2_id.eq.{}))&is_active.eq.true&order=last_used.desc&limit=1",
-            user1_id, user2_id, user2_id, user1_id
-        );
+    /// Get a conversation's newest session key generation, active or not
+    pub async fn get_latest_conversation_session(&self, conversation_id: Uuid, access_token: &str) -> AppResult<Option<ConversationSession>> {
+        let url = format!("/rest/v1/conversation_sessions?conversation_id=eq.{}&order=generation.desc&limit=1", conversation_id);
+        let response = self.get(&url, access_token).await?;
         
+        self.log_audit("get_latest_conversation_session", None, "conversation_sessions", true, None, Some(response.clone()));
+        
+        let sessions: Vec<ConversationSession> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse conversation session response: {}", e) })?;
+        
+        Ok(sessions.into_iter().next())
+    }
+    
+    /// Rotate a session out; its key stays available for decrypting older messages
+    pub async fn deactivate_conversation_session(&self, session_id: Uuid, access_token: &str) -> AppResult<()> {
+        let url = format!("/rest/v1/conversation_sessions?id=eq.{}", session_id);
+        let update_data = json!({ "is_active": false });
+        
+        let response = self.patch(&url, &update_data, access_token).await?;
+        self.log_audit("deactivate_conversation_session", None, "conversation_sessions", true, Some(update_data), Some(response));
+        Ok(())
+    }
+    
+    /// Get a session by ID (a message's `session_key_id`); RLS limits it to participants
+    pub async fn get_conversation_session_by_id(&self, session_id: Uuid, access_token: &str) -> AppResult<Option<ConversationSession>> {
+        let url = format!("/rest/v1/conversation_sessions?id=eq.{}", session_id);
         let response = self.get(&url, access_token).await?;
-        self.log_audit("get_conversation_session", Some(user1_id), "conversation_sessions", true, None, Some(response.clone()));
         
-        if let Some(sessions) = response.as_array() {
-            Ok(sessions.first().cloned())
-        } else {
-            Ok(None)
-        }
+        self.log_audit("get_conversation_session_by_id", None, "conversation_sessions", true, None, Some(response.clone()));
+        
+        let sessions: Vec<ConversationSession> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse conversation session response: {}", e) })?;
+        
+        Ok(sessions.into_iter().next())
+    }
+    
+    /// Store a session key wrapped for each participant
+    pub async fn store_session_key_shares(&self, shares: &[SessionKeyShare], access_token: &str) -> AppResult<()> {
+        let shares_data = serde_json::to_value(shares)
+            .map_err(|e| AppError::Internal { message: format!("Failed to serialize session key shares: {}", e) })?;
+        
+        let response = self.post("/rest/v1/session_key_shares", &shares_data, false, Some(access_token)).await?;
+        self.log_audit("store_session_key_shares", None, "session_key_shares", true, Some(shares_data), Some(response));
+        Ok(())
+    }
+    
+    /// Get a user's wrapped copy of a session key; RLS only shows users their own
+    pub async fn get_session_key_share(&self, session_id: Uuid, user_id: Uuid, access_token: &str) -> AppResult<Option<SessionKeyShare>> {
+        let url = format!("/rest/v1/session_key_shares?session_id=eq.{}&user_id=eq.{}", session_id, user_id);
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_session_key_share", Some(user_id), "session_key_shares", true, None, Some(response.clone()));
+        
+        let shares: Vec<SessionKeyShare> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse session key share response: {}", e) })?;
+        
+        Ok(shares.into_iter().next())
+    }
+    
+    /// Get a user's latest public key, readable by any authenticated user
+    pub async fn get_public_key(&self, user_id: Uuid, access_token: &str) -> AppResult<Option<UserPublicKey>> {
+        let url = format!("/rest/v1/user_public_keys?user_id=eq.{}&order=key_version.desc&limit=1", user_id);
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_public_key", Some(user_id), "user_public_keys", true, None, Some(response.clone()));
+        
+        let keys: Vec<UserPublicKey> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse public key response: {}", e) })?;
+        
+        Ok(keys.into_iter().next())
+    }
+    
+    /// Get one specific version of a user's public key, current or rotated out
+    pub async fn get_public_key_version(&self, user_id: Uuid, key_version: i32, access_token: &str) -> AppResult<Option<UserPublicKey>> {
+        let url = format!("/rest/v1/user_public_keys?user_id=eq.{}&key_version=eq.{}", user_id, key_version);
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("get_public_key_version", Some(user_id), "user_public_keys", true, None, Some(response.clone()));
+        
+        let keys: Vec<UserPublicKey> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse public key response: {}", e) })?;
+        
+        Ok(keys.into_iter().next())
+    }
+    
+    // 🤝 PREKEY OPERATIONS
+    
+    /// Publish (or replace) a user's identity and signed prekey
+    /// A changed identity key drops their unclaimed one-time prekeys (see the trigger).
+    pub async fn upsert_prekey_identity(&self, user_id: Uuid, upload: &PrekeyUpload, access_token: &str) -> AppResult<()> {
+        let identity_data = json!({
+            "user_id": user_id,
+            "identity_key": upload.identity_key,
+            "signing_key": upload.signing_key,
+            "signed_prekey_id": upload.signed_prekey.key_id,
+            "signed_prekey": upload.signed_prekey.public_key,
+            "signed_prekey_signature": upload.signed_prekey.signature,
+            "updated_at": Utc::now(),
+        });
+        
+        let response = self.post_with_prefer(
+            "/rest/v1/prekey_identities?on_conflict=user_id",
+            &identity_data,
+            access_token,
+            "resolution=merge-duplicates,return=representation",
+        ).await?;
+        self.log_audit("upsert_prekey_identity", Some(user_id), "prekey_identities", true, Some(identity_data), Some(response));
+        Ok(())
+    }
+    
+    /// Add one-time prekeys; reusing a key ID the user already uploaded fails
+    pub async fn store_one_time_prekeys(&self, user_id: Uuid, prekeys: &[OneTimePrekey], access_token: &str) -> AppResult<()> {
+        let prekeys_data: Vec<Value> = prekeys.iter()
+            .map(|prekey| json!({
+                "user_id": user_id,
+                "key_id": prekey.key_id,
+                "public_key": prekey.public_key,
+            }))
+            .collect();
+        let prekeys_data = Value::Array(prekeys_data);
+        
+        let response = self.post("/rest/v1/one_time_prekeys", &prekeys_data, false, Some(access_token)).await?;
+        self.log_audit("store_one_time_prekeys", Some(user_id), "one_time_prekeys", true, Some(prekeys_data), Some(response));
+        Ok(())
+    }
+    
+    /// Claim a user's prekey bundle, using up one of their one-time prekeys if any are left
+    /// None if they haven't published prekeys.
+    pub async fn claim_prekey_bundle(&self, user_id: Uuid, access_token: &str) -> AppResult<Option<ClaimedPrekeyBundle>> {
+        let request_data = json!({ "p_user_id": user_id });
+        let response = self.post("/rest/v1/rpc/claim_prekey_bundle", &request_data, false, Some(access_token)).await?;
+        
+        self.log_audit("claim_prekey_bundle", Some(user_id), "one_time_prekeys", true, Some(request_data), None);
+        
+        let claimed: Option<ClaimedPrekeyBundle> = serde_json::from_value(response)
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse prekey bundle response: {}", e) })?;
+        
+        Ok(claimed)
+    }
+    
+    /// Count a user's unclaimed one-time prekeys; RLS only lets users count their own
+    pub async fn count_one_time_prekeys(&self, user_id: Uuid, access_token: &str) -> AppResult<i64> {
+        let url = format!("/rest/v1/one_time_prekeys?user_id=eq.{}&claimed_at=is.null&select=key_id", user_id);
+        let response = self.get(&url, access_token).await?;
+        
+        self.log_audit("count_one_time_prekeys", Some(user_id), "one_time_prekeys", true, None, None);
+        
+        let count = response.as_array().map(|rows| rows.len()).unwrap_or(0);
+        Ok(count as i64)
     }
     
     // 🔄 REALTIME OPERATIONS
@@ -502,6 +1212,37 @@ impl SupabaseClient {
         } else {
             return Err(AppError::Authentication { message: "Access token required for this operation".to_string() });
         }
+        // PostgREST only returns the inserted rows when asked to
+        headers.insert("Prefer", HeaderValue::from_static("return=representation"));
+        
+        let response = self.client.post(&url)
+            .headers(headers)
+            .json(data)
+            .send()
+            .await
+            .map_err(|e| AppError::Internal { message: format!("POST request failed: {}", e) })?;
+        
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(AppError::Internal { message: format!("Supabase API error: {}", error_text) });
+        }
+        
+        let json_response: Value = response.json().await
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse JSON response: {}", e) })?;
+        
+        Ok(json_response)
+    }
+    
+    /// Make an authenticated POST request with a custom `Prefer` header
+    /// Used for PostgREST upserts, which need `resolution=...` alongside `return=...`
+    async fn post_with_prefer(&self, endpoint: &str, data: &Value, access_token: &str, prefer: &'static str) -> AppResult<Value> {
+        let url = format!("{}{}", self.base_url, endpoint);
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", access_token))
+            .map_err(|e| AppError::Internal { message: format!("Invalid authorization header: {}", e) })?);
+        headers.insert("apikey", HeaderValue::from_str(&self.anon_key)
+            .map_err(|e| AppError::Internal { message: format!("Invalid API key: {}", e) })?);
+        headers.insert("Prefer", HeaderValue::from_static(prefer));
         
         let response = self.client.post(&url)
             .headers(headers)
@@ -529,6 +1270,7 @@ impl SupabaseClient {
             .map_err(|e| AppError::Internal { message: format!("Invalid authorization header: {}", e) })?);
         headers.insert("apikey", HeaderValue::from_str(&self.anon_key)
             .map_err(|e| AppError::Internal { message: format!("Invalid API key: {}", e) })?);
+        headers.insert("Prefer", HeaderValue::from_static("return=representation"));
         
         let response = self.client.patch(&url)
             .headers(headers)
@@ -548,6 +1290,33 @@ impl SupabaseClient {
         Ok(json_response)
     }
     
+    /// Make a DELETE request to Supabase, returning the deleted rows
+    async fn delete(&self, endpoint: &str, access_token: &str) -> AppResult<Value> {
+        let url = format!("{}{}", self.base_url, endpoint);
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", access_token))
+            .map_err(|e| AppError::Internal { message: format!("Invalid authorization header: {}", e) })?);
+        headers.insert("apikey", HeaderValue::from_str(&self.anon_key)
+            .map_err(|e| AppError::Internal { message: format!("Invalid API key: {}", e) })?);
+        headers.insert("Prefer", HeaderValue::from_static("return=representation"));
+        
+        let response = self.client.delete(&url)
+            .headers(headers)
+            .send()
+            .await
+            .map_err(|e| AppError::Internal { message: format!("DELETE request failed: {}", e) })?;
+        
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(AppError::Internal { message: format!("Supabase API error: {}", error_text) });
+        }
+        
+        let json_response: Value = response.json().await
+            .map_err(|e| AppError::Internal { message: format!("Failed to parse JSON response: {}", e) })?;
+        
+        Ok(json_response)
+    }
+    
     /// Log audit entry for Zero Trust compliance
     fn log_audit(&self, operation: &str, user_id: Option<Uuid>, resource: &str, success: bool, request_data: Option<Value>, response_data: Option<Value>) {
         let entry = AuditEntry {
@@ -671,4 +1440,135 @@ impl SupabaseClient {
         log::info!("✅ Found {} users matching '{}'", users.len(), email_query);
         Ok(users)
     }
-} 
\ No newline at end of file
+}
+
+// 📨 Row to insert for a new message
+// Encrypted messages carry their session key and nonce. Anything else
+// is stored as sent, under `UNENCRYPTED_VERSION` with no key or nonce, so a
+// reader never mistakes it for session-key ciphertext.
+fn new_message_row(message: &NewMessage, sender_id: Uuid) -> Value {
+    let now = Utc::now();
+    let mut message_data = json!({
+        "conversation_id": message.conversation_id,
+        "sender_id": sender_id,
+        "receiver_id": message.receiver_id,
+        "client_msg_id": message.client_msg_id,
+        "reply_to_id": message.reply_to_id,
+        "encrypted_content": message.content,
+        "content_hash": hex::encode(Sha256::digest(message.content.as_bytes())),
+        "encryption_version": UNENCRYPTED_VERSION,
+        "nonce": null,
+        "session_key_id": null,
+        "message_type": message.message_type.as_ref().map(|mt| format!("{:?}", mt).to_lowercase()).unwrap_or_else(|| "text".to_string()),
+        "is_read": false,
+        "status": MessageStatus::Sent,
+        "created_at": now,
+        "updated_at": now
+    });
+    if let Some(id) = message.id {
+        message_data["id"] = json!(id);
+    }
+    if let Some(encryption) = &message.encryption {
+        message_data["content_hash"] = json!(encryption.content_hash);
+        message_data["encryption_version"] = json!(encryption.encryption_version);
+        message_data["nonce"] = json!(encryption.nonce);
+        message_data["session_key_id"] = json!(encryption.session_key_id);
+        message_data["session_key_generation"] = json!(encryption.session_key_generation);
+        if let Some(signature) = &encryption.signature {
+            message_data["signature"] = json!(signature.signature);
+            message_data["signature_key_version"] = json!(signature.key_version);
+        }
+    }
+    message_data
+}
+
+// 📑 KEYSET PAGINATION
+// PostgREST filter for rows strictly after `(after, after_id)` in `(column, id)` order.
+// Without an id, every row at exactly `after` is considered already seen.
+fn keyset_filter(column: &str, after: DateTime<Utc>, after_id: Option<Uuid>) -> String {
+    keyset_filter_on(column, "id", after, after_id)
+}
+
+// 📑 Same as `keyset_filter`, for tables whose tie-breaker isn't `id`
+fn keyset_filter_on(column: &str, id_column: &str, after: DateTime<Utc>, after_id: Option<Uuid>) -> String {
+    let at = after.to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+    match after_id {
+        Some(id) => format!(
+            "or={}",
+            urlencoding::encode(&format!("({col}.gt.{at},and({col}.eq.{at},{id_col}.gt.{id}))", col = column, at = at, id_col = id_column, id = id))
+        ),
+        None => format!("{}=gt.{}", column, urlencoding::encode(&at)),
+    }
+}
+
+// 📑 Same as `keyset_filter`, for tables whose rows are keyed on two ids
+fn keyset_filter_on_pair(column: &str, id_columns: (&str, &str), after: DateTime<Utc>, after_ids: Option<(Uuid, Uuid)>) -> String {
+    let at = after.to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+    match after_ids {
+        Some((id, sub_id)) => format!(
+            "or={}",
+            urlencoding::encode(&format!(
+                "({col}.gt.{at},and({col}.eq.{at},{id_col}.gt.{id}),and({col}.eq.{at},{id_col}.eq.{id},{sub_col}.gt.{sub_id}))",
+                col = column, at = at, id_col = id_columns.0, id = id, sub_col = id_columns.1, sub_id = sub_id
+            ))
+        ),
+        None => format!("{}=gt.{}", column, urlencoding::encode(&at)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::MessageEncryption;
+
+    fn new_message(content: &str, encryption: Option<MessageEncryption>) -> NewMessage {
+        NewMessage {
+            id: None,
+            conversation_id: Uuid::new_v4(),
+            receiver_id: Some(Uuid::new_v4()),
+            client_msg_id: None,
+            reply_to_id: None,
+            content: content.to_string(),
+            message_type: None,
+            encryption,
+        }
+    }
+
+    #[test]
+    fn test_unencrypted_message_row_has_no_placeholders() {
+        let row = new_message_row(&new_message("hello", None), Uuid::new_v4());
+
+        assert_eq!(row["content_hash"], "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
+        assert_eq!(row["encryption_version"], UNENCRYPTED_VERSION);
+        assert!(row["nonce"].is_null());
+        assert!(row["session_key_id"].is_null());
+        for (field, value) in row.as_object().unwrap() {
+            let text = value.as_str().unwrap_or_default();
+            assert!(!text.contains("TODO"), "{} holds a placeholder: {}", field, text);
+        }
+        // Every string that should be a UUID parses as one
+        for field in ["conversation_id", "sender_id", "receiver_id"] {
+            assert!(Uuid::parse_str(row[field].as_str().unwrap()).is_ok(), "{} is not a UUID", field);
+        }
+    }
+
+    #[test]
+    fn test_encrypted_message_row_keeps_its_encryption() {
+        let session_key_id = Uuid::new_v4();
+        let encryption = MessageEncryption {
+            content_hash: "ab".repeat(32),
+            nonce: "AAAAAAAAAAAAAAAA".to_string(),
+            encryption_version: 3,
+            session_key_id,
+            session_key_generation: 2,
+            signature: None,
+        };
+        let row = new_message_row(&new_message("ciphertext", Some(encryption)), Uuid::new_v4());
+
+        assert_eq!(row["content_hash"], "ab".repeat(32));
+        assert_eq!(row["encryption_version"], 3);
+        assert_eq!(row["nonce"], "AAAAAAAAAAAAAAAA");
+        assert_eq!(row["session_key_id"], json!(session_key_id));
+        assert_eq!(row["session_key_generation"], 2);
+    }
+}
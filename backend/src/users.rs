@@ -18,31 +18,26 @@ AUTHENTICATION FLOW:
 4. Handler verifies user permissions and processes request
 */
 
-use actix_web::{web, HttpRequest, HttpResponse, HttpMessage};
+use actix_web::{web, HttpResponse};
 use serde_json::json;
 use uuid::Uuid;
-use crate::auth::auth::{Claims, jwt_middleware};
+use crate::auth::auth::AccessToken;
 use crate::supabase_api::SupabaseClient;
 use crate::errors::AppResult;
 
-/// 👥 GET ALL USERS ENDPOINT (NO AUTHENTICATION)
+/// 👥 GET ALL USERS ENDPOINT
 /// 
-/// 🚨 TEMPORARY: No authentication required - for testing only!
-/// =============================================================
-/// 
-/// 🔰 BEGINNER EXPLANATION: What this does now
-/// ===========================================
-/// 1. ✅ No JWT token required
+/// 🔰 BEGINNER EXPLANATION: What this does
+/// =======================================
+/// 1. ✅ JWT token verified by the route auth layer before we get here
 /// 2. ✅ Fetches all users from Supabase database  
 /// 3. ✅ Returns user list directly
-/// 4. ✅ No security checks (TEMPORARY!)
 pub async fn get_all_users(
-    req: HttpRequest,
     supabase_client: web::Data<SupabaseClient>,
 ) -> AppResult<HttpResponse> {
-    log::info!("🔍 Fetching all users (NO AUTH MODE)");
+    log::info!("🔍 Fetching all users");
 
-    // 🗄️ QUERY SUPABASE FOR USERS - NO AUTH REQUIRED
+    // 🗄️ QUERY SUPABASE FOR USERS
     match supabase_client.get_all_users().await {
         Ok(users) => {
             log::info!("✅ Successfully retrieved {} users", users.len());
@@ -66,23 +61,23 @@ pub async fn get_all_users(
     }
 }
 
-/// 👤 GET USER BY ID ENDPOINT (NO AUTHENTICATION)
+/// 👤 GET USER BY ID ENDPOINT
 /// 
 /// This endpoint retrieves a specific user by their ID.
-/// No authentication required for testing.
+/// The lookup runs with the caller's token, so RLS decides what they may see.
 pub async fn get_user_by_id(
     path: web::Path<String>,
-    req: HttpRequest,
+    token: web::ReqData<AccessToken>,
     supabase_client: web::Data<SupabaseClient>,
 ) -> AppResult<HttpResponse> {
     let target_user_id = path.into_inner();
     let user_uuid = Uuid::parse_str(&target_user_id)
         .map_err(|_| crate::errors::AppError::bad_request("Invalid user ID format"))?;
 
-    log::info!("🔍 Fetching user: {} (NO AUTH MODE)", user_uuid);
+    log::info!("🔍 Fetching user: {}", user_uuid);
 
-    // 🗄️ QUERY SUPABASE FOR SPECIFIC USER - NO AUTH REQUIRED
-    match supabase_client.get_user(user_uuid, "dummy_token").await {
+    // 🗄️ QUERY SUPABASE FOR SPECIFIC USER
+    match supabase_client.get_user(user_uuid, token.as_str()).await {
         Ok(Some(user)) => {
             log::info!("✅ Successfully retrieved user: {}", user.id);
             
@@ -121,5 +116,6 @@ pub fn configure_routes(cfg: &mut web::ServiceConfig) {
         web::scope("/users")
             .route("", web::get().to(get_all_users))          // GET /users - Get all users
             .route("/{user_id}", web::get().to(get_user_by_id)) // GET /users/{id} - Get specific user
+            .route("/{user_id}/safety-number", web::get().to(crate::key_verification::get_safety_number)) // GET /users/{id}/safety-number - Verify their key
     );
 }
\ No newline at end of file
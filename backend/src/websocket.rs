@@ -14,22 +14,28 @@ RUST CONCEPTS EXPLAINED:
 WEBSOCKET ARCHITECTURE:
 1. Client connects with JWT token
 2. We verify the token and create a WebSocket actor
-3. Actor is stored in SessionManager with user_id as key
+3. Actor is stored in SessionManager under its user_id and a per-connection id
+   (one user can be connected from several devices at once)
 4. When messages arrive, we route them to the correct recipient
 5. When connection closes, we clean up the actor
 */
 
-use actix::{Actor, StreamHandler, Handler, Message as ActixMessage, Addr, AsyncContext, ActorContext};
+use actix::{Actor, StreamHandler, Handler, Message as ActixMessage, Addr, Recipient, AsyncContext, ActorContext, ActorFutureExt, SpawnHandle, WrapFuture};
 use actix_web::{web, HttpRequest, HttpResponse, Error};
 use actix_web_actors::ws;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use crate::auth::auth::{JwtValidator, extract_token_from_ws_request};
-use crate::database::{NewMessage, User};
-use crate::supabase_api::SupabaseClient;
+use crate::blob_store::BlobStore;
+use crate::config::Config;
+use crate::database::{MediaStatus, Message, MessageContentUpdate, MessageEncryption, NewMessage, Thumbnail, User};
+use crate::encryption::{validate_message_encryption, EncryptionService, MessageContext, UNENCRYPTED_VERSION};
+use crate::errors::{AppError, AppResult};
+use crate::supabase_api::{MessageInsert, SupabaseClient};
 // use sqlx::PgPool; // COMMENTED OUT - Using Supabase API instead
 
 // 📨 WEBSOCKET MESSAGE TYPES
@@ -40,10 +46,53 @@ use crate::supabase_api::SupabaseClient;
 #[serde(tag = "type")]
 pub enum IncomingMessage {
     // 💬 Send a chat message
+    // Exactly one of `to` (direct message) or `conversation_id` must be set
     #[serde(rename = "message")]
     SendMessage {
-        to: Uuid,           // Recipient user ID
-        content: String,    // Message content
+        #[serde(default)]
+        client_msg_id: Option<Uuid>,    // Sender-generated ID, echoed back in the `ack`
+        #[serde(default)]
+        to: Option<Uuid>,               // Recipient user ID (1:1 chat)
+        #[serde(default)]
+        conversation_id: Option<Uuid>,  // Target conversation (1:1 or group)
+        #[serde(default)]
+        reply_to: Option<Uuid>,         // Message being replied to (same conversation)
+        #[serde(default)]
+        id: Option<Uuid>,               // Message ID the content was encrypted for
+        content: String,                // Message content (ciphertext when `encryption` is set)
+        #[serde(default)]
+        encryption: Option<MessageEncryption>, // How `content` was encrypted; None sends it as is
+    },
+    
+    // ✏️ Replace the content of a message we sent
+    #[serde(rename = "edit")]
+    Edit {
+        message_id: Uuid,
+        content: String,
+        #[serde(default)]
+        encryption: Option<MessageEncryption>,
+    },
+    
+    // 🗑️ Delete a message for ourselves, or (sender only) for everyone
+    #[serde(rename = "delete")]
+    Delete {
+        message_id: Uuid,
+        #[serde(default)]
+        scope: DeleteScope,
+    },
+    
+    // 😀 Add an emoji reaction to a message
+    #[serde(rename = "react")]
+    React {
+        message_id: Uuid,
+        emoji: String,
+    },
+    
+    // 😶 Take back one of our reactions
+    #[serde(rename = "unreact")]
+    Unreact {
+        message_id: Uuid,
+        emoji: String,
     },
     
     // 💓 Heartbeat to keep connection alive
@@ -62,6 +111,38 @@ pub enum IncomingMessage {
         to: Uuid,
         is_typing: bool,
     },
+    
+    // 🔄 Replace the session's access token before it expires
+    #[serde(rename = "reauth")]
+    Reauth {
+        token: String,
+    },
+    
+    // 📥 Replay everything missed while offline
+    // Without `since` the full history is replayed
+    #[serde(rename = "sync")]
+    Sync {
+        #[serde(default)]
+        since: Option<SyncSince>,
+    },
+}
+
+// 🗑️ Who a deletion applies to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeleteScope {
+    #[default]
+    Me,         // Hide it from the requester's devices only
+    Everyone,   // Sender only: wipe it for every participant
+}
+
+// 📍 Where a `sync` resumes from: the last message the client has,
+// or the `cursor` of a previous `sync_complete`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum SyncSince {
+    MessageId(Uuid),
+    Timestamp(DateTime<Utc>),
 }
 
 // 📤 OUTGOING MESSAGE (from server to client)
@@ -72,11 +153,107 @@ pub enum OutgoingMessage {
     #[serde(rename = "message")]
     NewMessage {
         id: Uuid,
+        conversation_id: Uuid,
+        reply_to_id: Option<Uuid>,
         from: Uuid,
         content: String,
+        encryption: Option<MessageEncryption>, // None when `content` was sent unencrypted
+        timestamp: DateTime<Utc>,
+    },
+    
+    // ✏️ A message's content was edited
+    #[serde(rename = "message_edited")]
+    MessageEdited {
+        message_id: Uuid,
+        conversation_id: Uuid,
+        content: String,
+        encryption: Option<MessageEncryption>,
+        edited_at: DateTime<Utc>,
+    },
+    
+    // 🗑️ A message was deleted (for everyone, or for this user only)
+    #[serde(rename = "message_deleted")]
+    MessageDeleted {
+        message_id: Uuid,
+        conversation_id: Uuid,
+        scope: DeleteScope,
+        deleted_at: DateTime<Utc>,
+    },
+    
+    // 😀 Someone reacted to a message
+    #[serde(rename = "reaction_added")]
+    ReactionAdded {
+        message_id: Uuid,
+        conversation_id: Uuid,
+        user_id: Uuid,
+        emoji: String,
+    },
+    
+    // 😶 Someone took back a reaction
+    #[serde(rename = "reaction_removed")]
+    ReactionRemoved {
+        message_id: Uuid,
+        conversation_id: Uuid,
+        user_id: Uuid,
+        emoji: String,
+    },
+    
+    // 📎 A file was attached to a message
+    #[serde(rename = "attachment_added")]
+    AttachmentAdded {
+        message_id: Uuid,
+        conversation_id: Uuid,
+        attachment_id: Uuid,
+        file_name: String,
+        file_size: i64,
+        mime_type: String,
+    },
+    
+    // 🖼️ An image attachment finished processing; previews are now available
+    // Thumbnails are served from /api/v1/attachments/{attachment_id}/thumbnails/{name}
+    #[serde(rename = "attachment_processed")]
+    AttachmentProcessed {
+        message_id: Uuid,
+        conversation_id: Uuid,
+        attachment_id: Uuid,
+        media_status: MediaStatus,
+        file_size: i64,
+        width: Option<i32>,
+        height: Option<i32>,
+        blurhash: Option<String>,
+        thumbnails: Vec<Thumbnail>,
+    },
+    
+    // 📬 Message persisted; carries the server-assigned ID
+    #[serde(rename = "ack")]
+    Ack {
+        client_msg_id: Option<Uuid>,
+        id: Uuid,
+        conversation_id: Uuid,
         timestamp: DateTime<Utc>,
     },
     
+    // 📬 A recipient device received one of our messages
+    #[serde(rename = "delivered")]
+    Delivered {
+        message_id: Uuid,
+        conversation_id: Uuid,
+        delivered_at: DateTime<Utc>,
+    },
+    
+    // 🔑 A contact rotated their key pair; safety numbers with them have changed
+    #[serde(rename = "identity_key_changed")]
+    IdentityKeyChanged {
+        user_id: Uuid,
+        key_version: i32,
+    },
+    
+    // 🎟️ Someone claimed one of our one-time prekeys and few are left; upload more
+    #[serde(rename = "prekeys_low")]
+    PrekeysLow {
+        remaining: usize,
+    },
+    
     // 💓 Pong response to ping
     #[serde(rename = "pong")]
     Pong,
@@ -112,30 +289,161 @@ pub enum OutgoingMessage {
         message_id: Uuid,
         read_by: Uuid,
     },
+    
+    // 🔄 Access token expires soon (or already has); client must send `reauth`
+    #[serde(rename = "reauth_required")]
+    ReauthRequired {
+        expires_at: DateTime<Utc>,
+    },
+    
+    // ✅ New access token accepted
+    #[serde(rename = "reauthenticated")]
+    Reauthenticated {
+        expires_at: DateTime<Utc>,
+    },
+    
+    // 📥 Replay finished; `cursor` is the `since` for the next sync
+    #[serde(rename = "sync_complete")]
+    SyncComplete {
+        cursor: DateTime<Utc>,
+        events: usize,
+    },
 }
 
+// ⏱️ How long before token expiry the client is asked to re-authenticate
+const REAUTH_WARNING_SECONDS: i64 = 60;
+
 // 🎭 WEBSOCKET ACTOR
 // Each WebSocket connection is represented by this actor
 // RUST PATTERN: Actors are isolated, message-passing entities
 pub struct WebSocketActor {
     user_id: Uuid,                              // The authenticated user
+    connection_id: Uuid,                        // This device's connection (a user may have several)
     session_manager: Arc<Mutex<SessionManager>>, // Shared session manager
     supabase_client: SupabaseClient,            // Supabase API client (ZERO TRUST)
+    blob_store: Arc<dyn BlobStore>,             // Attachment bytes, removed with deleted messages
+    jwt_validator: JwtValidator,                // Verifies tokens sent with `reauth`
+    access_token: String,                       // Caller's token, forwarded to Supabase for RLS
+    edit_window_seconds: Option<u64>,           // How long after sending messages stay editable
+    token_expires_at: DateTime<Utc>,            // `exp` claim of `access_token`
+    expiry_notice: Option<SpawnHandle>,         // Timer that sends `reauth_required`
 }
 
 impl WebSocketActor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         user_id: Uuid, 
         session_manager: Arc<Mutex<SessionManager>>, 
-        supabase_client: SupabaseClient
+        supabase_client: SupabaseClient,
+        blob_store: Arc<dyn BlobStore>,
+        jwt_validator: JwtValidator,
+        access_token: String,
+        token_expires_at: DateTime<Utc>,
+        edit_window_seconds: Option<u64>,
     ) -> Self {
         Self {
             user_id,
+            connection_id: Uuid::new_v4(),
             session_manager,
             supabase_client,
+            blob_store,
+            jwt_validator,
+            access_token,
+            edit_window_seconds,
+            token_expires_at,
+            expiry_notice: None,
         }
     }
     
+    // 🎟️ Token to forward to Supabase
+    // Returns None (after telling the client) once the token has expired,
+    // so requests fail loudly instead of being rejected by RLS in silence.
+    fn current_token(&self, ctx: &mut ws::WebsocketContext<Self>) -> Option<String> {
+        if Utc::now() >= self.token_expires_at {
+            self.send_message(ctx, OutgoingMessage::ReauthRequired {
+                expires_at: self.token_expires_at,
+            });
+            return None;
+        }
+        Some(self.access_token.clone())
+    }
+    
+    // ⏰ Ask the client for a fresh token shortly before the current one expires
+    fn schedule_expiry_notice(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        if let Some(handle) = self.expiry_notice.take() {
+            ctx.cancel_future(handle);
+        }
+        
+        let notify_at = self.token_expires_at - chrono::Duration::seconds(REAUTH_WARNING_SECONDS);
+        let delay = (notify_at - Utc::now()).to_std().unwrap_or(Duration::ZERO);
+        
+        self.expiry_notice = Some(ctx.run_later(delay, |act, ctx| {
+            act.expiry_notice = None;
+            act.send_message(ctx, OutgoingMessage::ReauthRequired {
+                expires_at: act.token_expires_at,
+            });
+        }));
+    }
+    
+    // 📥 Handle sync: stream missed events to this device in order
+    // Pages through each event source and sends one frame per event, then
+    // `sync_complete`. Live events may interleave, so clients dedupe by id.
+    // Replayed new messages are marked delivered once per page, not per frame.
+    fn handle_sync(&self, since: Option<SyncSince>, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(access_token) = self.current_token(ctx) else { return };
+        let supabase_client = self.supabase_client.clone();
+        let user_id = self.user_id;
+        let addr = ctx.address();
+        
+        actix::spawn(async move {
+            if let Err(e) = replay_since(&supabase_client, user_id, since, &access_token, &addr).await {
+                log::error!("Sync failed for user {}: {}", user_id, e);
+                addr.do_send(SendToClient {
+                    message: OutgoingMessage::Error {
+                        message: match e {
+                            AppError::NotFound { .. } => e.to_string(),
+                            _ => "Failed to sync messages".to_string(),
+                        },
+                    },
+                });
+            }
+        });
+    }
+    
+    // 🔄 Handle reauth: verify the new token and swap it in
+    fn handle_reauth(&mut self, token: String, ctx: &mut ws::WebsocketContext<Self>) {
+        let jwt_validator = self.jwt_validator.clone();
+        let new_token = token.clone();
+        
+        let fut = async move { jwt_validator.verify_token(&new_token, None, None).await }
+            .into_actor(self)
+            .map(move |result, act, ctx| match result {
+                Ok(claims) if Uuid::parse_str(&claims.sub).ok() == Some(act.user_id) => {
+                    act.access_token = token;
+                    act.token_expires_at = DateTime::from_timestamp(claims.exp, 0)
+                        .unwrap_or_else(Utc::now);
+                    act.schedule_expiry_notice(ctx);
+                    act.send_message(ctx, OutgoingMessage::Reauthenticated {
+                        expires_at: act.token_expires_at,
+                    });
+                    log::debug!("🔄 Access token refreshed for user {}", act.user_id);
+                }
+                Ok(_) => {
+                    log::warn!("Reauth token for a different user on session {}", act.user_id);
+                    act.send_message(ctx, OutgoingMessage::Error {
+                        message: "Token belongs to a different user".to_string(),
+                    });
+                }
+                Err(e) => {
+                    log::warn!("Reauth failed for user {}: {}", act.user_id, e);
+                    act.send_message(ctx, OutgoingMessage::Error {
+                        message: "Invalid token".to_string(),
+                    });
+                }
+            });
+        ctx.spawn(fut);
+    }
+    
     // 📤 Send message to client
     fn send_message(&self, ctx: &mut ws::WebsocketContext<Self>, msg: OutgoingMessage) {
         match serde_json::to_string(&msg) {
@@ -148,91 +456,238 @@ impl WebSocketActor {
     }
     
     // 💬 Handle incoming chat message
-    async fn handle_send_message(&self, to: Uuid, content: String, ctx: &mut ws::WebsocketContext<Self>) {
-        // Validate message content
-        if content.trim().is_empty() {
-            self.send_message(ctx, OutgoingMessage::Error {
-                message: "Message content cannot be empty".to_string(),
-            });
-            return;
-        }
+    // Persists the message, acks it to the sender, then pushes it to every connected participant
+    fn handle_send_message(&self, send: ChatSend, ctx: &mut ws::WebsocketContext<Self>) {
+        // 🔐 ZERO TRUST: Create message via Supabase API with the caller's token
+        let Some(access_token) = self.current_token(ctx) else { return };
+        let supabase_client = self.supabase_client.clone();
+        let session_manager = match self.session_manager.lock() {
+            Ok(session_manager) => session_manager.clone(),
+            Err(_) => return,
+        };
+        let connection_id = self.connection_id;
         
-        if content.len() > 4000 { // Reasonable message length limit
-            self.send_message(ctx, OutgoingMessage::Error {
-                message: "Message too long (max 4000 characters)".to_string(),
-            });
-            return;
-        }
+        let fut = async move {
+            send_chat_message(&supabase_client, &session_manager, send, Some(connection_id), &access_token).await
+        };
         
-        // Create new message in database
-        let new_message = NewMessage {
-            receiver_id: to,
-            content: content.clone(),
-            message_type: None, // Default to text
-            file_url: None,
-            file_size: None,
-            mime_type: None,
+        ctx.spawn(fut.into_actor(self).map(|result, act, ctx| match result {
+            Ok(inserted) => {
+                let message = inserted.into_message();
+                act.send_message(ctx, OutgoingMessage::Ack {
+                    client_msg_id: message.client_msg_id,
+                    id: message.id,
+                    conversation_id: message.conversation_id,
+                    timestamp: message.created_at,
+                });
+            }
+            Err(e) => {
+                log::error!("Failed to send message: {}", e);
+                act.send_message(ctx, OutgoingMessage::Error {
+                    message: match e {
+                        AppError::BadRequest { .. } | AppError::NotFound { .. } | AppError::Authentication { .. } | AppError::Forbidden { .. } => e.to_string(),
+                        _ => "Failed to send message".to_string(),
+                    },
+                });
+            }
+        }));
+    }
+    
+    // ✏️ Handle edit of one of our messages
+    fn handle_edit(&self, message_id: Uuid, content: String, encryption: Option<MessageEncryption>, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(access_token) = self.current_token(ctx) else { return };
+        let supabase_client = self.supabase_client.clone();
+        let session_manager = match self.session_manager.lock() {
+            Ok(session_manager) => session_manager.clone(),
+            Err(_) => return,
+        };
+        let edit = ChatEdit {
+            editor_id: self.user_id,
+            message_id,
+            content,
+            encryption,
         };
+        let edit_window = self.edit_window_seconds;
+        let connection_id = self.connection_id;
         
-        // 💾 Save message to database via Supabase API (ZERO TRUST)
-        // 🚫 DIRECT DATABASE OPERATION (COMMENTED OUT)
-        // match DbMessage::create(&self.db_pool, self.user_id, new_message).await {
+        let fut = async move {
+            edit_chat_message(&supabase_client, &session_manager, edit, edit_window, Some(connection_id), &access_token).await
+        };
         
-        // 🔐 ZERO TRUST: Create message via Supabase API
-        let access_token = "TODO: Extract from WebSocket context"; // TODO: Implement token extraction
-        match self.supabase_client.create_message(&new_message, self.user_id, access_token).await {
+        ctx.spawn(fut.into_actor(self).map(move |result, act, ctx| match result {
+            // The editing device gets the same event as everyone else, as its confirmation
             Ok(message) => {
-                // Send to recipient if they're online
-                if let Ok(session_manager) = self.session_manager.lock() {
-                    if let Some(recipient_addr) = session_manager.get_user_session(&to) {
-                        recipient_addr.do_send(SendToClient {
-                            message: OutgoingMessage::NewMessage {
-                                id: message.id,
-                                from: self.user_id,
-                                content: message.encrypted_content.clone(),
-                                timestamp: message.created_at,
-                            },
-                        });
-                    }
-                }
-                
-                log::debug!("Message sent from {} to {}", self.user_id, to);
+                act.send_message(ctx, OutgoingMessage::MessageEdited {
+                    message_id: message.id,
+                    conversation_id: message.conversation_id,
+                    encryption: message.encryption(),
+                    content: message.encrypted_content,
+                    edited_at: message.edited_at.unwrap_or(message.updated_at),
+                });
             }
             Err(e) => {
-                log::error!("Failed to save message to database: {}", e);
-                self.send_message(ctx, OutgoingMessage::Error {
-                    message: "Failed to send message".to_string(),
+                log::error!("Failed to edit message {}: {}", message_id, e);
+                act.send_message(ctx, OutgoingMessage::Error {
+                    message: match e {
+                        AppError::BadRequest { .. } | AppError::NotFound { .. } | AppError::Forbidden { .. } => e.to_string(),
+                        _ => "Failed to edit message".to_string(),
+                    },
+                });
+            }
+        }));
+    }
+    
+    // 🗑️ Handle message deletion
+    fn handle_delete(&self, message_id: Uuid, scope: DeleteScope, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(access_token) = self.current_token(ctx) else { return };
+        let supabase_client = self.supabase_client.clone();
+        let blob_store = self.blob_store.clone();
+        let session_manager = match self.session_manager.lock() {
+            Ok(session_manager) => session_manager.clone(),
+            Err(_) => return,
+        };
+        let delete = ChatDelete {
+            user_id: self.user_id,
+            message_id,
+            scope,
+        };
+        let connection_id = self.connection_id;
+        
+        let fut = async move {
+            delete_chat_message(&supabase_client, &session_manager, blob_store.as_ref(), delete, Some(connection_id), &access_token).await
+        };
+        
+        ctx.spawn(fut.into_actor(self).map(move |result, act, ctx| match result {
+            // The deleting device gets the same event as the others, as its confirmation
+            Ok(event) => act.send_message(ctx, event),
+            Err(e) => {
+                log::error!("Failed to delete message {}: {}", message_id, e);
+                act.send_message(ctx, OutgoingMessage::Error {
+                    message: match e {
+                        AppError::NotFound { .. } | AppError::Forbidden { .. } => e.to_string(),
+                        _ => "Failed to delete message".to_string(),
+                    },
+                });
+            }
+        }));
+    }
+    
+    // 😀 Handle adding or removing a reaction
+    fn handle_reaction(&self, message_id: Uuid, emoji: String, added: bool, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(access_token) = self.current_token(ctx) else { return };
+        let supabase_client = self.supabase_client.clone();
+        let session_manager = match self.session_manager.lock() {
+            Ok(session_manager) => session_manager.clone(),
+            Err(_) => return,
+        };
+        let reaction = ChatReaction {
+            user_id: self.user_id,
+            message_id,
+            emoji,
+            added,
+        };
+        let connection_id = self.connection_id;
+        
+        let fut = async move {
+            update_chat_reaction(&supabase_client, &session_manager, reaction, Some(connection_id), &access_token).await
+        };
+        
+        ctx.spawn(fut.into_actor(self).map(move |result, act, ctx| match result {
+            Ok(event) => act.send_message(ctx, event),
+            Err(e) => {
+                log::error!("Failed to update reaction on message {}: {}", message_id, e);
+                act.send_message(ctx, OutgoingMessage::Error {
+                    message: match e {
+                        AppError::BadRequest { .. } | AppError::NotFound { .. } => e.to_string(),
+                        _ => "Failed to update reaction".to_string(),
+                    },
                 });
             }
+        }));
+    }
+    
+    // 📬 Record that this device received someone else's messages and tell the senders
+    // Only the first delivery moves the status, so each sender hears about it once
+    fn acknowledge_delivery(&self, message_ids: Vec<Uuid>) {
+        // An expired token is handled by the next client request; don't spam reauth here
+        if self.token_expires_at <= Utc::now() {
+            return;
+        }
+        
+        let access_token = self.access_token.clone();
+        let supabase_client = self.supabase_client.clone();
+        let session_manager = self.session_manager.clone();
+        let user_id = self.user_id;
+        
+        actix::spawn(async move {
+            match supabase_client.mark_messages_delivered(&message_ids, user_id, &access_token).await {
+                Ok(messages) => {
+                    if let Ok(sm) = session_manager.lock() {
+                        for message in messages {
+                            sm.send_to_users(&[message.sender_id], None, OutgoingMessage::Delivered {
+                                message_id: message.id,
+                                conversation_id: message.conversation_id,
+                                delivered_at: message.delivered_at.unwrap_or_else(Utc::now),
+                            });
+                        }
+                    }
+                }
+                Err(e) => log::warn!("Failed to mark {} message(s) as delivered: {}", message_ids.len(), e),
+            }
+        });
+    }
+    
+    // Someone else's message reaching this device counts as delivery
+    fn delivered_message_id(&self, message: &OutgoingMessage) -> Option<Uuid> {
+        match message {
+            OutgoingMessage::NewMessage { id, from, .. } if *from != self.user_id => Some(*id),
+            _ => None,
         }
     }
     
     // ✅ Handle mark message as read (ZERO TRUST)
-    async fn handle_mark_read(&self, message_id: Uuid, ctx: &mut ws::WebsocketContext<Self>) {
+    // Sends a read receipt to every connected device of the original sender
+    fn handle_mark_read(&self, message_id: Uuid, ctx: &mut ws::WebsocketContext<Self>) {
         // 🚫 DIRECT DATABASE OPERATION (COMMENTED OUT)
         // match DbMessage::mark_as_read(&self.db_pool, message_id, self.user_id).await {
         
         // 🔐 ZERO TRUST: Mark message as read via Supabase API
-        let access_token = "TODO: Extract from WebSocket context"; // TODO: Implement token extraction
-        match self.supabase_client.mark_message_read(message_id, self.user_id, access_token).await {
-            Ok(_) => {
-                // Find the sender and notify them
-                // For simplicity in Week 1, we'll skip this notification
-                log::debug!("Message {} marked as read by {}", message_id, self.user_id);
+        let Some(access_token) = self.current_token(ctx) else { return };
+        let supabase_client = self.supabase_client.clone();
+        let session_manager = self.session_manager.clone();
+        let user_id = self.user_id;
+        
+        let fut = async move {
+            let message = supabase_client.mark_message_read(message_id, user_id, &access_token).await?;
+            
+            // Already read (or not ours to read): nothing new to tell the sender
+            if let Some(message) = message {
+                if let Ok(sm) = session_manager.lock() {
+                    sm.send_to_users(&[message.sender_id], None, OutgoingMessage::ReadReceipt {
+                        message_id,
+                        read_by: user_id,
+                    });
+                }
             }
-            Err(e) => {
+            
+            log::debug!("Message {} marked as read by {}", message_id, user_id);
+            Ok::<_, AppError>(())
+        };
+        
+        ctx.spawn(fut.into_actor(self).map(|result, act, ctx| {
+            if let Err(e) = result {
                 log::error!("Failed to mark message as read: {}", e);
-                self.send_message(ctx, OutgoingMessage::Error {
+                act.send_message(ctx, OutgoingMessage::Error {
                     message: "Failed to mark message as read".to_string(),
                 });
             }
-        }
+        }));
     }
     
     // 👀 Handle typing indicator
     fn handle_typing(&self, to: Uuid, is_typing: bool) {
         if let Ok(session_manager) = self.session_manager.lock() {
-            if let Some(recipient_addr) = session_manager.get_user_session(&to) {
+            for recipient_addr in session_manager.get_user_sessions(&to) {
                 recipient_addr.do_send(SendToClient {
                     message: OutgoingMessage::TypingIndicator {
                         from: self.user_id,
@@ -254,8 +709,16 @@ impl Actor for WebSocketActor {
         log::info!("📡 WebSocket connection started for user {}", self.user_id);
         
         // Register this actor in the session manager
-        if let Ok(mut session_manager) = self.session_manager.lock() {
-            session_manager.add_session(self.user_id, ctx.address());
+        let first_device = match self.session_manager.lock() {
+            Ok(mut session_manager) => session_manager.add_session(self.user_id, self.connection_id, ctx.address().recipient()),
+            Err(_) => false,
+        };
+        
+        self.schedule_expiry_notice(ctx);
+        
+        // Presence only changes when the user's first device connects
+        if !first_device {
+            return;
         }
         
         // 🔐 ZERO TRUST: Update user online status via Supabase API
@@ -271,10 +734,10 @@ impl Actor for WebSocketActor {
         // 🔐 ZERO TRUST: Set user online status via Supabase API
         let supabase_client = self.supabase_client.clone();
         let user_id = self.user_id;
+        let access_token = self.access_token.clone();
         
         actix::spawn(async move {
-            let access_token = "TODO: Extract from WebSocket context"; // TODO: Implement token extraction
-            if let Err(e) = supabase_client.update_user_status(user_id, true, access_token).await {
+            if let Err(e) = supabase_client.update_user_status(user_id, true, &access_token).await {
                 log::error!("Failed to set user online status: {}", e);
             }
         });
@@ -285,8 +748,14 @@ impl Actor for WebSocketActor {
         log::info!("📡 WebSocket connection stopped for user {}", self.user_id);
         
         // Remove this actor from the session manager
-        if let Ok(mut session_manager) = self.session_manager.lock() {
-            session_manager.remove_session(&self.user_id);
+        let last_device = match self.session_manager.lock() {
+            Ok(mut session_manager) => session_manager.remove_session(&self.user_id, &self.connection_id),
+            Err(_) => false,
+        };
+        
+        // Another device is still connected, so the user stays online
+        if !last_device {
+            return;
         }
         
         // 🔐 ZERO TRUST: Update user offline status via Supabase API
@@ -302,10 +771,10 @@ impl Actor for WebSocketActor {
         // 🔐 ZERO TRUST: Set user offline status via Supabase API
         let supabase_client = self.supabase_client.clone();
         let user_id = self.user_id;
+        let access_token = self.access_token.clone();
         
         actix::spawn(async move {
-            let access_token = "TODO: Extract from WebSocket context"; // TODO: Implement token extraction
-            if let Err(e) = supabase_client.update_user_status(user_id, false, access_token).await {
+            if let Err(e) = supabase_client.update_user_status(user_id, false, &access_token).await {
                 log::error!("Failed to set user offline status: {}", e);
             }
         });
@@ -322,56 +791,34 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketActor {
                 match serde_json::from_str::<IncomingMessage>(&text) {
                     Ok(incoming_msg) => {
                         match incoming_msg {
-                            IncomingMessage::SendMessage { to, content } => {
-                                // 🔐 ZERO TRUST: Handle async operation via Supabase API
-                                // 🚫 DIRECT DATABASE OPERATION (COMMENTED OUT)
-                                // let db_pool = self.db_pool.clone();
-                                // let user_id = self.user_id;
-                                // let fut = async move {
-                                //     let new_message = NewMessage {
-                                //         receiver_id: to,
-                                //         content: content.clone(),
-                                //         message_type: None,
-                                //     };
-                                //     DbMessage::create(&db_pool, user_id, new_message).await
-                                // };
-                                
-                                let supabase_client = self.supabase_client.clone();
-                                let user_id = self.user_id;
-                                
-                                let fut = async move {
-                                    let new_message = NewMessage {
-                                        receiver_id: to,
-                                        content: content.clone(),
-                                        message_type: None,
-                                        file_url: None,
-                                        file_size: None,
-                                        mime_type: None,
-                                    };
-                                    let access_token = "TODO: Extract from WebSocket context"; // TODO: Implement token extraction
-                                    supabase_client.create_message(&new_message, user_id, access_token).await
+                            IncomingMessage::SendMessage { client_msg_id, to, conversation_id, reply_to, id, content, encryption } => {
+                                let send = ChatSend {
+                                    sender_id: self.user_id,
+                                    to,
+                                    conversation_id,
+                                    client_msg_id,
+                                    reply_to_id: reply_to,
+                                    id,
+                                    content,
+                                    encryption,
                                 };
-                                
-                                let session_manager = self.session_manager.clone();
-                                actix::spawn(async move {
-                                    match fut.await {
-                                        Ok(message) => {
-                                            if let Ok(sm) = session_manager.lock() {
-                                                if let Some(addr) = sm.get_user_session(&to) {
-                                                    addr.do_send(SendToClient {
-                                                        message: OutgoingMessage::NewMessage {
-                                                            id: message.id,
-                                                            from: user_id,
-                                                            content: message.encrypted_content.clone(),
-                                                            timestamp: message.created_at,
-                                                        },
-                                                    });
-                                                }
-                                            }
-                                        }
-                                        Err(e) => log::error!("Failed to send message: {}", e),
-                                    }
-                                });
+                                self.handle_send_message(send, ctx);
+                            }
+                            
+                            IncomingMessage::Edit { message_id, content, encryption } => {
+                                self.handle_edit(message_id, content, encryption, ctx);
+                            }
+                            
+                            IncomingMessage::Delete { message_id, scope } => {
+                                self.handle_delete(message_id, scope, ctx);
+                            }
+                            
+                            IncomingMessage::React { message_id, emoji } => {
+                                self.handle_reaction(message_id, emoji, true, ctx);
+                            }
+                            
+                            IncomingMessage::Unreact { message_id, emoji } => {
+                                self.handle_reaction(message_id, emoji, false, ctx);
                             }
                             
                             IncomingMessage::Ping => {
@@ -379,30 +826,20 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketActor {
                             }
                             
                             IncomingMessage::MarkRead { message_id } => {
-                                // 🔐 ZERO TRUST: Mark message as read via Supabase API
-                                // 🚫 DIRECT DATABASE OPERATION (COMMENTED OUT)
-                                // let db_pool = self.db_pool.clone();
-                                // let user_id = self.user_id;
-                                // actix::spawn(async move {
-                                //     if let Err(e) = DbMessage::mark_as_read(&db_pool, message_id, user_id).await {
-                                //         log::error!("Failed to mark message as read: {}", e);
-                                //     }
-                                // });
-                                
-                                let supabase_client = self.supabase_client.clone();
-                                let user_id = self.user_id;
-                                
-                                actix::spawn(async move {
-                                    let access_token = "TODO: Extract from WebSocket context"; // TODO: Implement token extraction
-                                    if let Err(e) = supabase_client.mark_message_read(message_id, user_id, access_token).await {
-                                        log::error!("Failed to mark message as read: {}", e);
-                                    }
-                                });
+                                self.handle_mark_read(message_id, ctx);
                             }
                             
                             IncomingMessage::Typing { to, is_typing } => {
                                 self.handle_typing(to, is_typing);
                             }
+                            
+                            IncomingMessage::Reauth { token } => {
+                                self.handle_reauth(token, ctx);
+                            }
+                            
+                            IncomingMessage::Sync { since } => {
+                                self.handle_sync(since, ctx);
+                            }
                         }
                     }
                     Err(e) => {
@@ -463,16 +900,53 @@ impl Handler<SendToClient> for WebSocketActor {
     type Result = ();
     
     fn handle(&mut self, msg: SendToClient, ctx: &mut Self::Context) {
+        let delivered = self.delivered_message_id(&msg.message);
+        
         self.send_message(ctx, msg.message);
+        
+        if let Some(message_id) = delivered {
+            self.acknowledge_delivery(vec![message_id]);
+        }
     }
 }
 
+// 📦 ONE PAGE OF REPLAYED EVENTS
+// Sent as one frame per event; new messages in it are acknowledged together
+#[derive(ActixMessage)]
+#[rtype(result = "()")]
+struct SendSyncPage {
+    messages: Vec<OutgoingMessage>,
+}
+
+impl Handler<SendSyncPage> for WebSocketActor {
+    type Result = ();
+    
+    fn handle(&mut self, msg: SendSyncPage, ctx: &mut Self::Context) {
+        let delivered: Vec<Uuid> = msg.messages.iter()
+            .filter_map(|message| self.delivered_message_id(message))
+            .collect();
+        
+        for message in msg.messages {
+            self.send_message(ctx, message);
+        }
+        
+        if !delivered.is_empty() {
+            self.acknowledge_delivery(delivered);
+        }
+    }
+}
+
+// 📱 One user's connected devices, keyed by connection id
+// Held as recipients of `SendToClient`, all the manager ever sends them
+type DeviceSessions = HashMap<Uuid, Recipient<SendToClient>>;
+
 // 🗂️ SESSION MANAGER
 // Keeps track of all connected WebSocket sessions
+// Each user maps to their devices, keyed by connection id
 // RUST PATTERN: Arc<Mutex<T>> for thread-safe shared state
 #[derive(Debug, Clone)]
 pub struct SessionManager {
-    sessions: Arc<Mutex<HashMap<Uuid, Addr<WebSocketActor>>>>,
+    sessions: Arc<Mutex<HashMap<Uuid, DeviceSessions>>>,
 }
 
 impl SessionManager {
@@ -483,28 +957,44 @@ impl SessionManager {
     }
     
     // ➕ Add a new session
-    pub fn add_session(&mut self, user_id: Uuid, addr: Addr<WebSocketActor>) {
+    // Returns true if this is the user's first connected device
+    pub fn add_session(&mut self, user_id: Uuid, connection_id: Uuid, addr: Recipient<SendToClient>) -> bool {
         if let Ok(mut sessions) = self.sessions.lock() {
-            sessions.insert(user_id, addr);
-            log::debug!("Added session for user {}", user_id);
+            let devices = sessions.entry(user_id).or_default();
+            devices.insert(connection_id, addr);
+            log::debug!("Added session {} for user {} ({} device(s))", connection_id, user_id, devices.len());
+            devices.len() == 1
+        } else {
+            false
         }
     }
     
     // ➖ Remove a session
-    pub fn remove_session(&mut self, user_id: &Uuid) {
+    // Returns true if the user has no connected devices left
+    pub fn remove_session(&mut self, user_id: &Uuid, connection_id: &Uuid) -> bool {
         if let Ok(mut sessions) = self.sessions.lock() {
-            if sessions.remove(user_id).is_some() {
-                log::debug!("Removed session for user {}", user_id);
+            let Some(devices) = sessions.get_mut(user_id) else { return false };
+            
+            if devices.remove(connection_id).is_some() {
+                log::debug!("Removed session {} for user {}", connection_id, user_id);
+            }
+            
+            if devices.is_empty() {
+                sessions.remove(user_id);
+                return true;
             }
         }
+        false
     }
     
-    // 🔍 Get a session by user ID
-    pub fn get_user_session(&self, user_id: &Uuid) -> Option<Addr<WebSocketActor>> {
+    // 🔍 Get every connected device of a user
+    pub fn get_user_sessions(&self, user_id: &Uuid) -> Vec<Recipient<SendToClient>> {
         if let Ok(sessions) = self.sessions.lock() {
-            sessions.get(user_id).cloned()
+            sessions.get(user_id)
+                .map(|devices| devices.values().cloned().collect())
+                .unwrap_or_default()
         } else {
-            None
+            Vec::new()
         }
     }
     
@@ -517,19 +1007,650 @@ impl SessionManager {
         }
     }
     
+    // 📨 Send a message to every device of several users (offline users are skipped)
+    // `except` skips one connection, typically the device that caused the event
+    pub fn send_to_users<'a>(&self, user_ids: impl IntoIterator<Item = &'a Uuid>, except: Option<Uuid>, message: OutgoingMessage) {
+        if let Ok(sessions) = self.sessions.lock() {
+            for user_id in user_ids {
+                let Some(devices) = sessions.get(user_id) else { continue };
+                for (connection_id, addr) in devices {
+                    if Some(*connection_id) == except {
+                        continue;
+                    }
+                    addr.do_send(SendToClient {
+                        message: message.clone(),
+                    });
+                }
+            }
+        }
+    }
+    
     // 📢 Broadcast message to all connected users
     pub fn broadcast(&self, message: OutgoingMessage) {
         if let Ok(sessions) = self.sessions.lock() {
-            for (user_id, addr) in sessions.iter() {
-                addr.do_send(SendToClient {
-                    message: message.clone(),
-                });
+            for (user_id, devices) in sessions.iter() {
+                for addr in devices.values() {
+                    addr.do_send(SendToClient {
+                        message: message.clone(),
+                    });
+                }
                 log::debug!("Broadcasted message to user {}", user_id);
             }
         }
     }
 }
 
+// 🎯 RESOLVED MESSAGE TARGET
+struct ConversationTarget {
+    conversation_id: Uuid,
+    receiver_id: Option<Uuid>,   // Set for 1:1 conversations only
+    participants: Vec<Uuid>,     // Everyone in the conversation, sender included
+}
+
+// 🎯 Work out which conversation a message goes to and who is in it.
+// A bare `to` resolves to (and lazily creates) the direct conversation.
+async fn resolve_conversation(
+    supabase_client: &SupabaseClient,
+    sender_id: Uuid,
+    to: Option<Uuid>,
+    conversation_id: Option<Uuid>,
+    access_token: &str,
+) -> AppResult<ConversationTarget> {
+    if let Some(to) = to {
+        let conversation = supabase_client.get_or_create_direct_conversation(sender_id, to, access_token).await?;
+        return Ok(ConversationTarget {
+            conversation_id: conversation.id,
+            receiver_id: Some(to),
+            participants: vec![sender_id, to],
+        });
+    }
+    
+    let conversation_id = conversation_id.ok_or_else(|| AppError::bad_request("No message target"))?;
+    let conversation = supabase_client.get_conversation_by_id(conversation_id, access_token).await?
+        .ok_or_else(|| AppError::NotFound { resource: format!("conversation {}", conversation_id) })?;
+    let participants = supabase_client.get_conversation_participants(conversation_id, access_token).await?;
+    
+    if !participants.contains(&sender_id) {
+        return Err(AppError::forbidden("Not a participant of this conversation"));
+    }
+    
+    let receiver_id = if conversation.is_group {
+        None
+    } else {
+        participants.iter().copied().find(|id| *id != sender_id)
+    };
+    
+    Ok(ConversationTarget { conversation_id, receiver_id, participants })
+}
+
+// 📨 SENDING A CHAT MESSAGE
+// Shared by the WebSocket `message` frame and REST `POST /messages/send`,
+// so both kinds of sender get the same validation, storage and fan-out.
+
+const MAX_MESSAGE_LENGTH: usize = 4000;
+
+// 📏 Content rules shared by sends and edits
+fn validate_content(content: &str) -> AppResult<()> {
+    if content.trim().is_empty() {
+        return Err(AppError::bad_request("Message content cannot be empty"));
+    }
+    if content.len() > MAX_MESSAGE_LENGTH {
+        return Err(AppError::bad_request(format!("Message too long (max {} characters)", MAX_MESSAGE_LENGTH)));
+    }
+    Ok(())
+}
+
+const MAX_EMOJI_LENGTH: usize = 16;
+
+// 😀 A reaction is a single short token such as "👍" or "❤️"; the emoji set
+// itself is up to clients
+fn validate_emoji(emoji: &str) -> AppResult<()> {
+    if emoji.is_empty() || emoji.chars().count() > MAX_EMOJI_LENGTH {
+        return Err(AppError::bad_request(format!("Reaction must be 1 to {} characters", MAX_EMOJI_LENGTH)));
+    }
+    if emoji.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(AppError::bad_request("Reaction cannot contain whitespace"));
+    }
+    Ok(())
+}
+
+// 📨 A chat message on its way in
+#[derive(Debug)]
+pub struct ChatSend {
+    pub sender_id: Uuid,
+    pub to: Option<Uuid>,               // Direct message recipient...
+    pub conversation_id: Option<Uuid>,  // ...or target conversation (exactly one is set)
+    pub client_msg_id: Option<Uuid>,    // Sender-generated ID for acks and retries
+    pub reply_to_id: Option<Uuid>,      // Message being replied to (same conversation)
+    pub id: Option<Uuid>,               // Sender-chosen message ID, required with `encryption`
+    pub content: String,
+    pub encryption: Option<MessageEncryption>, // None stores `content` as sent
+}
+
+// 🔐 Where a message's ciphertext belongs, as its sender encrypted and signed it
+fn message_context(conversation_id: Uuid, sender_id: Uuid, receiver_id: Option<Uuid>, message_id: Uuid) -> MessageContext {
+    match receiver_id {
+        Some(receiver_id) => MessageContext::direct(sender_id, receiver_id, message_id),
+        None => MessageContext::group(conversation_id, sender_id, message_id),
+    }
+}
+
+// 🔐 Check the encryption metadata sent with some content before storing it.
+// The server can't decrypt, so only the shape is checked, plus the signature (if
+// any): it covers the plaintext through `content_hash` and must come from the
+// sender's current key pair.
+async fn validate_encryption(
+    supabase_client: &SupabaseClient,
+    context: &MessageContext,
+    content: &str,
+    encryption: &MessageEncryption,
+    access_token: &str,
+) -> AppResult<()> {
+    validate_message_encryption(content, encryption)
+        .map_err(|e| AppError::bad_request(format!("Invalid encryption metadata: {}", e)))?;
+    let Some(signature) = &encryption.signature else { return Ok(()) };
+    
+    // 🔐 RLS only shows the caller their own key pairs, and the caller is the sender
+    let key = supabase_client.get_encryption_keys(context.sender_id, access_token).await?
+        .into_iter()
+        .find(|key| key.key_version == signature.key_version && key.expires_at.is_none())
+        .ok_or_else(|| AppError::bad_request("Messages must be signed with the current key pair"))?;
+    let service = EncryptionService::new();
+    let public_key = service.import_public_key_pem(&key.public_key)
+        .map_err(|e| AppError::Internal { message: format!("Stored public key is invalid: {}", e) })?;
+    service.verify_content_signature(&public_key, context, &encryption.content_hash, &signature.signature)
+        .map_err(|e| AppError::bad_request(format!("Invalid message signature: {}", e)))
+}
+
+// 📨 Validate, persist and fan out a chat message.
+// Every connected device of every participant gets `NewMessage`, except
+// `skip_connection` (the sending device, which gets an `ack` instead).
+// Retried sends return the original message and are not fanned out again.
+pub async fn send_chat_message(
+    supabase_client: &SupabaseClient,
+    session_manager: &SessionManager,
+    send: ChatSend,
+    skip_connection: Option<Uuid>,
+    access_token: &str,
+) -> AppResult<MessageInsert> {
+    validate_content(&send.content)?;
+    if send.to.is_some() == send.conversation_id.is_some() {
+        return Err(AppError::bad_request("Specify exactly one of `to` or `conversation_id`"));
+    }
+    
+    let target = resolve_conversation(supabase_client, send.sender_id, send.to, send.conversation_id, access_token).await?;
+    
+    // ↩️ Only messages from the same conversation can be replied to
+    if let Some(reply_to_id) = send.reply_to_id {
+        let original = supabase_client.get_message(reply_to_id, access_token).await?;
+        if original.is_none_or(|original| original.conversation_id != target.conversation_id) {
+            return Err(AppError::bad_request("Reply target is not in this conversation"));
+        }
+    }
+    
+    // 🔐 Ciphertext is bound to the message ID it was encrypted for, so that ID is kept
+    if let Some(encryption) = &send.encryption {
+        let message_id = send.id
+            .ok_or_else(|| AppError::bad_request("Encrypted messages need the `id` they were encrypted for"))?;
+        let context = message_context(target.conversation_id, send.sender_id, target.receiver_id, message_id);
+        validate_encryption(supabase_client, &context, &send.content, encryption, access_token).await?;
+    }
+    
+    let new_message = NewMessage {
+        id: send.id,
+        conversation_id: target.conversation_id,
+        receiver_id: target.receiver_id,
+        client_msg_id: send.client_msg_id,
+        reply_to_id: send.reply_to_id,
+        content: send.content,
+        message_type: None, // Default to text
+        encryption: send.encryption,
+    };
+    let inserted = supabase_client.create_message(&new_message, send.sender_id, access_token).await?;
+    
+    // 🔁 A retried send was already delivered the first time
+    let MessageInsert::Created(message) = &inserted else {
+        log::debug!("Duplicate send {:?} from {} resolved to original message", send.client_msg_id, send.sender_id);
+        return Ok(inserted);
+    };
+    
+    // 📢 Fan out to every connected device of every participant,
+    // including the sender's other devices
+    session_manager.send_to_users(
+        &target.participants,
+        skip_connection,
+        OutgoingMessage::NewMessage {
+            id: message.id,
+            conversation_id: message.conversation_id,
+            reply_to_id: message.reply_to_id,
+            from: send.sender_id,
+            content: message.encrypted_content.clone(),
+            encryption: message.encryption(),
+            timestamp: message.created_at,
+        },
+    );
+    
+    log::debug!("Message sent from {} to conversation {}", send.sender_id, message.conversation_id);
+    Ok(inserted)
+}
+
+// ✏️ An edit to an existing message
+#[derive(Debug)]
+pub struct ChatEdit {
+    pub editor_id: Uuid,
+    pub message_id: Uuid,
+    pub content: String,
+    pub encryption: Option<MessageEncryption>, // None stores `content` as sent
+}
+
+// ✏️ Replace a message's content and tell every connected participant.
+// Shared by the WebSocket `edit` frame and REST `PATCH /messages/{id}`.
+// The previous revision is kept in `message_edits` by the database.
+pub async fn edit_chat_message(
+    supabase_client: &SupabaseClient,
+    session_manager: &SessionManager,
+    edit: ChatEdit,
+    edit_window_seconds: Option<u64>,
+    skip_connection: Option<Uuid>,
+    access_token: &str,
+) -> AppResult<Message> {
+    validate_content(&edit.content)?;
+    
+    let not_found = || AppError::NotFound { resource: format!("message {}", edit.message_id) };
+    let message = supabase_client.get_message(edit.message_id, access_token).await?
+        .ok_or_else(not_found)?;
+    message.ensure_editable_by(edit.editor_id, edit_window_seconds, Utc::now())?;
+    
+    let update = match edit.encryption {
+        Some(encryption) => {
+            let context = message_context(message.conversation_id, message.sender_id, message.receiver_id, message.id);
+            validate_encryption(supabase_client, &context, &edit.content, &encryption, access_token).await?;
+            MessageContentUpdate {
+                encrypted_content: edit.content,
+                content_hash: encryption.content_hash,
+                nonce: Some(encryption.nonce),
+                session_key_id: Some(encryption.session_key_id),
+                session_key_generation: Some(encryption.session_key_generation),
+                encryption_version: Some(encryption.encryption_version),
+                signature: encryption.signature,
+            }
+        }
+        // Stored as sent, like an unencrypted new message
+        None => MessageContentUpdate {
+            content_hash: EncryptionService::new().generate_content_hash(&edit.content),
+            encrypted_content: edit.content,
+            nonce: None,
+            session_key_id: None,
+            session_key_generation: None,
+            encryption_version: Some(UNENCRYPTED_VERSION as i32),
+            signature: None,
+        },
+    };
+    let message = supabase_client
+        .update_message_content(edit.message_id, edit.editor_id, &update, access_token).await?
+        .ok_or_else(not_found)?;
+    
+    let participants = supabase_client.get_conversation_participants(message.conversation_id, access_token).await?;
+    session_manager.send_to_users(
+        &participants,
+        skip_connection,
+        OutgoingMessage::MessageEdited {
+            message_id: message.id,
+            conversation_id: message.conversation_id,
+            content: message.encrypted_content.clone(),
+            encryption: message.encryption(),
+            edited_at: message.edited_at.unwrap_or(message.updated_at),
+        },
+    );
+    
+    log::debug!("Message {} edited by {}", message.id, edit.editor_id);
+    Ok(message)
+}
+
+// 🗑️ A deletion request
+#[derive(Debug)]
+pub struct ChatDelete {
+    pub user_id: Uuid,
+    pub message_id: Uuid,
+    pub scope: DeleteScope,
+}
+
+// 🗑️ Delete a message and tell the devices that should stop showing it.
+// `Me` hides it for the requester; `Everyone` (sender only) leaves a tombstone
+// for all participants. Returns the `message_deleted` event that was sent.
+pub async fn delete_chat_message(
+    supabase_client: &SupabaseClient,
+    session_manager: &SessionManager,
+    blob_store: &dyn BlobStore,
+    delete: ChatDelete,
+    skip_connection: Option<Uuid>,
+    access_token: &str,
+) -> AppResult<OutgoingMessage> {
+    let not_found = || AppError::NotFound { resource: format!("message {}", delete.message_id) };
+    let message = supabase_client.get_message(delete.message_id, access_token).await?
+        .ok_or_else(not_found)?;
+    
+    let (recipients, deleted_at) = match delete.scope {
+        DeleteScope::Me => {
+            supabase_client.hide_message(&message, delete.user_id, access_token).await?;
+            (vec![delete.user_id], Utc::now())
+        }
+        DeleteScope::Everyone => {
+            if message.sender_id != delete.user_id {
+                return Err(AppError::forbidden("Only the sender can delete a message for everyone"));
+            }
+            // The database drops the attachment rows; their bytes are ours to remove
+            let attachments = supabase_client.get_message_attachments(message.id, access_token).await?;
+            let deleted = supabase_client
+                .delete_message_for_everyone(delete.message_id, delete.user_id, access_token).await?
+                .ok_or_else(not_found)?;
+            for attachment in attachments {
+                for key in crate::attachments::blob_keys(&attachment) {
+                    if let Err(e) = blob_store.delete(&key).await {
+                        log::warn!("Failed to remove blob {} of attachment {}: {}", key, attachment.id, e);
+                    }
+                }
+            }
+            let participants = supabase_client.get_conversation_participants(deleted.conversation_id, access_token).await?;
+            (participants, deleted.deleted_at.unwrap_or_else(Utc::now))
+        }
+    };
+    
+    let event = OutgoingMessage::MessageDeleted {
+        message_id: message.id,
+        conversation_id: message.conversation_id,
+        scope: delete.scope,
+        deleted_at,
+    };
+    session_manager.send_to_users(&recipients, skip_connection, event.clone());
+    
+    log::debug!("Message {} deleted for {:?} by {}", message.id, delete.scope, delete.user_id);
+    Ok(event)
+}
+
+// 😀 A reaction being added or removed
+#[derive(Debug)]
+pub struct ChatReaction {
+    pub user_id: Uuid,
+    pub message_id: Uuid,
+    pub emoji: String,
+    pub added: bool,            // false: take the reaction back
+}
+
+// 😀 Add or remove a reaction and tell the conversation's connected participants.
+// Repeating a change is a no-op: the event is returned but not fanned out again.
+pub async fn update_chat_reaction(
+    supabase_client: &SupabaseClient,
+    session_manager: &SessionManager,
+    reaction: ChatReaction,
+    skip_connection: Option<Uuid>,
+    access_token: &str,
+) -> AppResult<OutgoingMessage> {
+    validate_emoji(&reaction.emoji)?;
+    
+    // 🔐 ZERO TRUST: RLS hides messages outside the user's conversations
+    let message = supabase_client.get_message(reaction.message_id, access_token).await?
+        .ok_or_else(|| AppError::NotFound { resource: format!("message {}", reaction.message_id) })?;
+    
+    let changed = if reaction.added {
+        if message.deleted_at.is_some() {
+            return Err(AppError::bad_request("Cannot react to a deleted message"));
+        }
+        supabase_client.add_reaction(&message, reaction.user_id, &reaction.emoji, access_token).await?
+    } else {
+        supabase_client.remove_reaction(message.id, reaction.user_id, &reaction.emoji, access_token).await?
+    };
+    
+    let event = if reaction.added {
+        OutgoingMessage::ReactionAdded {
+            message_id: message.id,
+            conversation_id: message.conversation_id,
+            user_id: reaction.user_id,
+            emoji: reaction.emoji,
+        }
+    } else {
+        OutgoingMessage::ReactionRemoved {
+            message_id: message.id,
+            conversation_id: message.conversation_id,
+            user_id: reaction.user_id,
+            emoji: reaction.emoji,
+        }
+    };
+    
+    if changed {
+        let participants = supabase_client.get_conversation_participants(message.conversation_id, access_token).await?;
+        session_manager.send_to_users(&participants, skip_connection, event.clone());
+    }
+    
+    Ok(event)
+}
+
+// 📥 OFFLINE SYNC
+// Missed events come from several sources (new messages, edits, deletions,
+// delivery and read receipts), each paged by keyset on (event time, id) and
+// merged into one ordered stream.
+
+const SYNC_PAGE_SIZE: i64 = 100;
+
+// 📌 Position within one event source
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SyncPosition {
+    at: DateTime<Utc>,
+    id: Option<Uuid>,   // Last row seen at `at`; None treats every row at `at` as seen
+    sub_id: Option<Uuid>, // Second key of that row, for sources keyed on two ids
+}
+
+// 📨 One replayable event
+#[derive(Debug)]
+struct SyncEvent {
+    at: DateTime<Utc>,
+    id: Uuid,
+    sub_id: Option<Uuid>,
+    message: OutgoingMessage,
+}
+
+// 📄 One page fetched from an event source
+#[derive(Debug)]
+struct SyncPage {
+    events: Vec<SyncEvent>,   // Sorted by (at, id, sub_id)
+    full: bool,               // More may follow in this source
+}
+
+// 🧮 Result of merging one round of pages
+#[derive(Debug)]
+struct SyncBatch {
+    events: Vec<SyncEvent>,             // Safe to emit now, in order
+    advanced: Vec<Option<SyncPosition>>, // New position per source (None: unchanged)
+    exhausted: bool,                    // Every source has been drained
+}
+
+// 🧮 Merge one page per source into an ordered batch.
+// A full page may have more events just past its end, so only events up to the
+// earliest end of any full page are emitted; the rest are fetched again next round.
+fn merge_sync_pages(pages: Vec<SyncPage>) -> SyncBatch {
+    let watermark = pages.iter()
+        .filter(|page| page.full)
+        .filter_map(|page| page.events.last().map(|event| event.at))
+        .min();
+    let exhausted = pages.iter().all(|page| !page.full);
+    
+    let mut advanced = Vec::with_capacity(pages.len());
+    let mut events = Vec::new();
+    for page in pages {
+        let taken: Vec<SyncEvent> = page.events.into_iter()
+            .take_while(|event| watermark.is_none_or(|w| event.at <= w))
+            .collect();
+        advanced.push(taken.last().map(|event| SyncPosition { at: event.at, id: Some(event.id), sub_id: event.sub_id }));
+        events.extend(taken);
+    }
+    events.sort_by_key(|event| (event.at, event.id, event.sub_id));
+    
+    SyncBatch { events, advanced, exhausted }
+}
+
+// 📥 Stream every event after `since` to `addr`, then `sync_complete`
+async fn replay_since(
+    supabase_client: &SupabaseClient,
+    user_id: Uuid,
+    since: Option<SyncSince>,
+    access_token: &str,
+    addr: &Addr<WebSocketActor>,
+) -> AppResult<()> {
+    let start = match since {
+        None => SyncPosition { at: DateTime::<Utc>::UNIX_EPOCH, id: None, sub_id: None },
+        Some(SyncSince::Timestamp(at)) => SyncPosition { at, id: None, sub_id: None },
+        Some(SyncSince::MessageId(message_id)) => {
+            let message = supabase_client.get_message(message_id, access_token).await?
+                .ok_or_else(|| AppError::NotFound { resource: format!("message {}", message_id) })?;
+            SyncPosition { at: message.created_at, id: Some(message.id), sub_id: None }
+        }
+    };
+    
+    // Source 0: new messages. Source 1: edits. Sources 2 and 3: deletions for
+    // everyone and for this user. Sources 4 and 5: delivery and read receipts
+    // for messages the user sent. Only new messages can resume from a message
+    // id; the others start at the time only.
+    let time_start = SyncPosition { at: start.at, id: None, sub_id: None };
+    let mut positions = [start, time_start, time_start, time_start, time_start, time_start];
+    let mut cursor = start.at;
+    let mut sent = 0;
+    
+    loop {
+        let messages = supabase_client
+            .get_messages_since(positions[0].at, positions[0].id, SYNC_PAGE_SIZE, access_token)
+            .await?;
+        let edits = supabase_client
+            .get_edits_since(positions[1].at, positions[1].id, SYNC_PAGE_SIZE, access_token)
+            .await?;
+        let deletions = supabase_client
+            .get_deletions_since(positions[2].at, positions[2].id, SYNC_PAGE_SIZE, access_token)
+            .await?;
+        let hidden = supabase_client
+            .get_hidden_since(user_id, positions[3].at, positions[3].id, SYNC_PAGE_SIZE, access_token)
+            .await?;
+        let deliveries = supabase_client
+            .get_delivery_receipts_since(user_id, positions[4].at, positions[4].id, SYNC_PAGE_SIZE, access_token)
+            .await?;
+        let receipts = supabase_client
+            .get_read_receipts_since(user_id, positions[5].at, positions[5].id.zip(positions[5].sub_id), SYNC_PAGE_SIZE, access_token)
+            .await?;
+        
+        let pages = vec![
+            SyncPage {
+                full: messages.len() as i64 == SYNC_PAGE_SIZE,
+                events: messages.into_iter().map(|message| SyncEvent {
+                    at: message.created_at,
+                    id: message.id,
+                    sub_id: None,
+                    message: OutgoingMessage::NewMessage {
+                        id: message.id,
+                        conversation_id: message.conversation_id,
+                        reply_to_id: message.reply_to_id,
+                        from: message.sender_id,
+                        encryption: message.encryption(),
+                        content: message.encrypted_content,
+                        timestamp: message.created_at,
+                    },
+                }).collect(),
+            },
+            SyncPage {
+                full: edits.len() as i64 == SYNC_PAGE_SIZE,
+                events: edits.into_iter().filter_map(|message| Some(SyncEvent {
+                    at: message.edited_at?,
+                    id: message.id,
+                    sub_id: None,
+                    message: OutgoingMessage::MessageEdited {
+                        message_id: message.id,
+                        conversation_id: message.conversation_id,
+                        encryption: message.encryption(),
+                        content: message.encrypted_content,
+                        edited_at: message.edited_at?,
+                    },
+                })).collect(),
+            },
+            SyncPage {
+                full: deletions.len() as i64 == SYNC_PAGE_SIZE,
+                events: deletions.into_iter().filter_map(|message| Some(SyncEvent {
+                    at: message.deleted_at?,
+                    id: message.id,
+                    sub_id: None,
+                    message: OutgoingMessage::MessageDeleted {
+                        message_id: message.id,
+                        conversation_id: message.conversation_id,
+                        scope: DeleteScope::Everyone,
+                        deleted_at: message.deleted_at?,
+                    },
+                })).collect(),
+            },
+            SyncPage {
+                full: hidden.len() as i64 == SYNC_PAGE_SIZE,
+                events: hidden.into_iter().map(|hidden| SyncEvent {
+                    at: hidden.hidden_at,
+                    id: hidden.message_id,
+                    sub_id: None,
+                    message: OutgoingMessage::MessageDeleted {
+                        message_id: hidden.message_id,
+                        conversation_id: hidden.conversation_id,
+                        scope: DeleteScope::Me,
+                        deleted_at: hidden.hidden_at,
+                    },
+                }).collect(),
+            },
+            SyncPage {
+                full: deliveries.len() as i64 == SYNC_PAGE_SIZE,
+                events: deliveries.into_iter().filter_map(|message| Some(SyncEvent {
+                    at: message.delivered_at?,
+                    id: message.id,
+                    sub_id: None,
+                    message: OutgoingMessage::Delivered {
+                        message_id: message.id,
+                        conversation_id: message.conversation_id,
+                        delivered_at: message.delivered_at?,
+                    },
+                })).collect(),
+            },
+            SyncPage {
+                full: receipts.len() as i64 == SYNC_PAGE_SIZE,
+                events: receipts.into_iter().map(|read| SyncEvent {
+                    at: read.read_at,
+                    id: read.message_id,
+                    sub_id: Some(read.user_id),
+                    message: OutgoingMessage::ReadReceipt {
+                        message_id: read.message_id,
+                        read_by: read.user_id,
+                    },
+                }).collect(),
+            },
+        ];
+        
+        let batch = merge_sync_pages(pages);
+        for (position, advanced) in positions.iter_mut().zip(batch.advanced) {
+            if let Some(advanced) = advanced {
+                *position = advanced;
+            }
+        }
+        let mut page = Vec::with_capacity(batch.events.len());
+        for event in batch.events {
+            cursor = cursor.max(event.at);
+            page.push(event.message);
+        }
+        sent += page.len();
+        if !page.is_empty() {
+            addr.do_send(SendSyncPage { messages: page });
+        }
+        
+        if batch.exhausted {
+            break;
+        }
+    }
+    
+    log::debug!("Replayed {} events to user {}", sent, user_id);
+    addr.do_send(SendToClient {
+        message: OutgoingMessage::SyncComplete { cursor, events: sent },
+    });
+    Ok(())
+}
+
 // 🔌 WEBSOCKET HANDLER ENDPOINT
 // This is the HTTP endpoint that upgrades connections to WebSocket
 pub async fn websocket_handler(
@@ -538,6 +1659,8 @@ pub async fn websocket_handler(
     jwt_validator: web::Data<JwtValidator>,
     session_manager: web::Data<SessionManager>,
     supabase_client: web::Data<SupabaseClient>,
+    blob_store: web::Data<dyn BlobStore>,
+    config: web::Data<Config>,
 ) -> Result<HttpResponse, Error> {
     log::info!("📡 New WebSocket connection attempt");
     
@@ -578,7 +1701,6 @@ pub async fn websocket_handler(
     //     })?;
     
     // 🔐 ZERO TRUST: Upsert user via Supabase API
-    let access_token = "TODO: Extract from request"; // TODO: Implement token extraction
     let user = User {
         id: user_id,
         email: claims.email.clone(),
@@ -590,7 +1712,7 @@ pub async fn websocket_handler(
         updated_at: Utc::now(),
     };
     
-    if let Err(e) = supabase_client.get_ref().upsert_user(&user, access_token).await {
+    if let Err(e) = supabase_client.get_ref().upsert_user(&user, &token).await {
         log::error!("Failed to upsert user: {}", e);
         return Err(actix_web::error::ErrorInternalServerError("Database error"));
     }
@@ -598,11 +1720,85 @@ pub async fn websocket_handler(
     log::info!("✅ WebSocket authentication successful for user {}", user_id);
     
     // Create WebSocket actor and start connection
+    let token_expires_at = DateTime::from_timestamp(claims.exp, 0)
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid token expiry"))?;
+    
     let actor = WebSocketActor::new(
         user_id,
         Arc::new(Mutex::new(session_manager.get_ref().clone())),
         supabase_client.get_ref().clone(),
+        blob_store.into_inner(),
+        jwt_validator.get_ref().clone(),
+        token,
+        token_expires_at,
+        config.message_edit_window_seconds,
     );
     
     ws::start(actor, &req, stream)
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn event(second: u32, id: u128) -> SyncEvent {
+        SyncEvent {
+            at: Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, second).unwrap(),
+            id: Uuid::from_u128(id),
+            sub_id: None,
+            message: OutgoingMessage::Pong,
+        }
+    }
+
+    #[test]
+    fn reactions_must_be_a_single_short_token() {
+        assert!(validate_emoji("👍").is_ok());
+        assert!(validate_emoji("❤️").is_ok());
+        assert!(validate_emoji("").is_err());
+        assert!(validate_emoji("👍 👎").is_err());
+        assert!(validate_emoji(&"👍".repeat(MAX_EMOJI_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn merge_interleaves_partial_pages_and_finishes() {
+        let batch = merge_sync_pages(vec![
+            SyncPage { events: vec![event(1, 1), event(4, 2)], full: false },
+            SyncPage { events: vec![event(2, 3)], full: false },
+        ]);
+
+        let order: Vec<u128> = batch.events.iter().map(|e| e.id.as_u128()).collect();
+        assert_eq!(order, vec![1, 3, 2]);
+        assert!(batch.exhausted);
+    }
+
+    #[test]
+    fn merge_stops_at_the_end_of_the_earliest_full_page() {
+        let batch = merge_sync_pages(vec![
+            SyncPage { events: vec![event(1, 1), event(3, 2)], full: true },
+            SyncPage { events: vec![event(2, 3), event(5, 4)], full: false },
+        ]);
+
+        // Source 0 may have more events between 3s and 5s, so 5s must wait
+        let order: Vec<u128> = batch.events.iter().map(|e| e.id.as_u128()).collect();
+        assert_eq!(order, vec![1, 3, 2]);
+        assert!(!batch.exhausted);
+        assert_eq!(batch.advanced[0].unwrap().id, Some(Uuid::from_u128(2)));
+        assert_eq!(batch.advanced[1].unwrap().id, Some(Uuid::from_u128(3)));
+    }
+
+    #[test]
+    fn merge_tracks_the_reader_of_read_receipts() {
+        let read_by = |reader: u128| SyncEvent { sub_id: Some(Uuid::from_u128(reader)), ..event(1, 1) };
+        let batch = merge_sync_pages(vec![
+            SyncPage { events: vec![read_by(7), read_by(8)], full: true },
+            SyncPage { events: vec![], full: false },
+        ]);
+
+        // Two group members read the same message at the same instant, so the
+        // next page has to resume after the second reader, not the message
+        assert_eq!(batch.events.len(), 2);
+        assert_eq!(batch.advanced[0].unwrap().id, Some(Uuid::from_u128(1)));
+        assert_eq!(batch.advanced[0].unwrap().sub_id, Some(Uuid::from_u128(8)));
+    }
+}